@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS `caja_transiciones`;
+
+UPDATE `cajas` SET `estado` = 'cerrado' WHERE `estado` NOT IN ('abierto', 'cerrado');
+
+ALTER TABLE `cajas`
+	MODIFY `estado` ENUM('cerrado', 'abierto') NOT NULL DEFAULT 'cerrado';
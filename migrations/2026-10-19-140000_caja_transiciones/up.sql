@@ -0,0 +1,14 @@
+-- Your SQL goes here
+ALTER TABLE `cajas`
+	MODIFY `estado` ENUM('abriendo', 'abierto', 'cerrando', 'cerrado', 'error_cierre', 'deshabilitado') NOT NULL DEFAULT 'cerrado';
+
+CREATE TABLE `caja_transiciones`(
+	`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+	`id_caja` INT NOT NULL,
+	`estado_anterior` ENUM('abriendo', 'abierto', 'cerrando', 'cerrado', 'error_cierre', 'deshabilitado') NOT NULL,
+	`estado_nuevo` ENUM('abriendo', 'abierto', 'cerrando', 'cerrado', 'error_cierre', 'deshabilitado') NOT NULL,
+	`actor` ENUM('kiosko', 'scheduler', 'admin') NOT NULL,
+	`motivo` VARCHAR(255) NOT NULL,
+	`fecha` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (`id_caja`) REFERENCES `cajas`(`id`)
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER IF EXISTS `auditoria_sin_delete`;
+DROP TRIGGER IF EXISTS `auditoria_sin_update`;
+DROP TABLE IF EXISTS `auditoria`;
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE `auditoria`(
+	`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+	`actor` ENUM('kiosko', 'scheduler', 'admin') NOT NULL,
+	`id_kiosko` INT NULL,
+	`id_caja` INT NULL,
+	`accion` VARCHAR(50) NOT NULL,
+	`resumen` JSON NOT NULL,
+	`codigo_http` INT NULL,
+	`codigo_yappy` VARCHAR(20) NULL,
+	`resultado` VARCHAR(20) NOT NULL,
+	`fecha` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	INDEX `idx_auditoria_caja_fecha` (`id_caja`, `fecha`)
+);
+
+-- la auditoria es solo de insercion
+CREATE TRIGGER `auditoria_sin_update` BEFORE UPDATE ON `auditoria`
+	FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'auditoria es solo de insercion';
+
+CREATE TRIGGER `auditoria_sin_delete` BEFORE DELETE ON `auditoria`
+	FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'auditoria es solo de insercion';
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `kioskos`
+	DROP COLUMN `en_linea`,
+	DROP COLUMN `ip`,
+	DROP COLUMN `version_app`,
+	DROP COLUMN `ultima_conexion`;
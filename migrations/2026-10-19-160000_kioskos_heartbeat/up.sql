@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE `kioskos`
+	ADD COLUMN `ultima_conexion` TIMESTAMP NULL,
+	ADD COLUMN `version_app` VARCHAR(50) NULL,
+	ADD COLUMN `ip` VARCHAR(45) NULL,
+	ADD COLUMN `en_linea` BOOLEAN NOT NULL DEFAULT FALSE;
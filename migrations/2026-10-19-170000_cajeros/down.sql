@@ -0,0 +1,18 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `caja_transiciones`
+	MODIFY `actor` ENUM('kiosko', 'scheduler', 'admin') NOT NULL;
+
+ALTER TABLE `auditoria`
+	DROP COLUMN `id_cajero`,
+	MODIFY `actor` ENUM('kiosko', 'scheduler', 'admin') NOT NULL;
+ALTER TABLE `caja_cierre_errores` DROP COLUMN `id_cajero`;
+ALTER TABLE `caja_cierre_resumen` DROP COLUMN `id_cajero`;
+
+DROP TABLE IF EXISTS `sesiones_cajero`;
+
+ALTER TABLE `cajas` DROP FOREIGN KEY `fk_cajas_cajero`;
+ALTER TABLE `cajas`
+	DROP COLUMN `id_cajero_actual`,
+	MODIFY `tipo` VARCHAR(50) NOT NULL;
+
+DROP TABLE IF EXISTS `cajeros`;
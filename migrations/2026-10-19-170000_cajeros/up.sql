@@ -0,0 +1,33 @@
+-- Your SQL goes here
+CREATE TABLE `cajeros`(
+	`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+	`id_grupo` INT NOT NULL,
+	`nombre` VARCHAR(100) NOT NULL,
+	`pin_hash` VARCHAR(255) NOT NULL,
+	`activo` BOOLEAN NOT NULL DEFAULT TRUE,
+	FOREIGN KEY (`id_grupo`) REFERENCES `grupos`(`id`)
+);
+
+ALTER TABLE `cajas`
+	MODIFY `tipo` ENUM('kiosko', 'cajero') NOT NULL DEFAULT 'kiosko',
+	ADD COLUMN `id_cajero_actual` INT NULL,
+	ADD CONSTRAINT `fk_cajas_cajero` FOREIGN KEY (`id_cajero_actual`) REFERENCES `cajeros`(`id`);
+
+CREATE TABLE `sesiones_cajero`(
+	`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+	`id_caja` INT NOT NULL,
+	`id_cajero` INT NOT NULL,
+	`inicio` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`fin` TIMESTAMP NULL,
+	FOREIGN KEY (`id_caja`) REFERENCES `cajas`(`id`),
+	FOREIGN KEY (`id_cajero`) REFERENCES `cajeros`(`id`)
+);
+
+ALTER TABLE `caja_cierre_resumen` ADD COLUMN `id_cajero` INT NULL;
+ALTER TABLE `caja_cierre_errores` ADD COLUMN `id_cajero` INT NULL;
+ALTER TABLE `auditoria`
+	ADD COLUMN `id_cajero` INT NULL,
+	MODIFY `actor` ENUM('kiosko', 'cajero', 'scheduler', 'admin') NOT NULL;
+
+ALTER TABLE `caja_transiciones`
+	MODIFY `actor` ENUM('kiosko', 'cajero', 'scheduler', 'admin') NOT NULL;
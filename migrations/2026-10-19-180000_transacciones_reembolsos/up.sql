@@ -0,0 +1,33 @@
+-- Your SQL goes here
+CREATE TABLE `transacciones`(
+	`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+	`id_caja` INT NOT NULL,
+	`id_cajero` INT NULL,
+	`id_transaccion_yappy` VARCHAR(100) NOT NULL UNIQUE,
+	`id_orden` VARCHAR(100) NULL,
+	`tipo_qr` VARCHAR(10) NOT NULL,
+	`subtotal` DECIMAL(10, 2) NOT NULL,
+	`impuesto` DECIMAL(10, 2) NOT NULL,
+	`propina` DECIMAL(10, 2) NOT NULL,
+	`descuento` DECIMAL(10, 2) NOT NULL,
+	`total` DECIMAL(10, 2) NOT NULL,
+	`estado` VARCHAR(20) NOT NULL DEFAULT 'PENDING',
+	`fecha` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`fecha_actualizacion` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+	INDEX `idx_transacciones_orden` (`id_orden`),
+	FOREIGN KEY (`id_caja`) REFERENCES `cajas`(`id`)
+);
+
+CREATE TABLE `reembolsos`(
+	`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+	`id_transaccion` INT NOT NULL,
+	`monto` DECIMAL(10, 2) NOT NULL,
+	`motivo` VARCHAR(255) NOT NULL,
+	`actor` ENUM('kiosko', 'cajero', 'scheduler', 'admin') NOT NULL,
+	`id_cajero` INT NULL,
+	`exitoso` BOOLEAN NOT NULL,
+	`codigo_yappy` VARCHAR(20) NULL,
+	`respuesta_json` JSON NOT NULL,
+	`fecha` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (`id_transaccion`) REFERENCES `transacciones`(`id`)
+);
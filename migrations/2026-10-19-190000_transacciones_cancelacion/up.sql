@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `transacciones`
+	ADD COLUMN `motivo_cancelacion` VARCHAR(255) NULL;
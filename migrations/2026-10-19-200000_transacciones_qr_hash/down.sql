@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `transacciones`
+	DROP COLUMN `qr_hash`;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `transacciones`
+	ADD COLUMN `qr_hash` TEXT NULL;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `grupos`
+	ADD COLUMN `plantilla_recibo` TEXT NULL;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE `transaccion_items`(
+	`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+	`id_transaccion` INT NOT NULL,
+	`sku` VARCHAR(100) NOT NULL,
+	`descripcion` VARCHAR(255) NOT NULL,
+	`cantidad` INT NOT NULL,
+	`precio_unitario` DECIMAL(10, 2) NOT NULL,
+	`descuento` DECIMAL(10, 2) NOT NULL,
+	`exento_itbms` BOOLEAN NOT NULL,
+	`impuesto` DECIMAL(10, 2) NOT NULL,
+	`total` DECIMAL(10, 2) NOT NULL,
+	FOREIGN KEY (`id_transaccion`) REFERENCES `transacciones`(`id`)
+);
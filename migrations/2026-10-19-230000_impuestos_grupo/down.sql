@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `transaccion_items`
+	DROP COLUMN `tasa_impuesto`,
+	DROP COLUMN `categoria`;
+
+ALTER TABLE `transacciones`
+	DROP COLUMN `discrepancia_impuesto`,
+	DROP COLUMN `tasa_impuesto`;
+
+DROP TABLE IF EXISTS `impuestos_grupo`;
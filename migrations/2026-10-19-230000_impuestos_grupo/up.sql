@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE `impuestos_grupo`(
+	`id_grupo` INT NOT NULL PRIMARY KEY,
+	`tasa_general` DECIMAL(5, 4) NOT NULL DEFAULT 0.0700,
+	`tasas_categoria` JSON NOT NULL,
+	`categorias_exentas` JSON NOT NULL,
+	`modo_redondeo` ENUM('half_up', 'half_even', 'up', 'down') NOT NULL DEFAULT 'half_up',
+	FOREIGN KEY (`id_grupo`) REFERENCES `grupos`(`id`)
+);
+
+ALTER TABLE `transacciones`
+	ADD COLUMN `tasa_impuesto` DECIMAL(5, 4) NULL,
+	ADD COLUMN `discrepancia_impuesto` VARCHAR(255) NULL;
+
+ALTER TABLE `transaccion_items`
+	ADD COLUMN `categoria` VARCHAR(50) NULL,
+	ADD COLUMN `tasa_impuesto` DECIMAL(5, 4) NOT NULL DEFAULT 0.0000;
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE `usuarios`(
+	`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+	`nombre` VARCHAR(100) NOT NULL,
+	`email` VARCHAR(255) NOT NULL UNIQUE,
+	`password_hash` VARCHAR(255) NOT NULL,
+	`rol` ENUM('superadmin', 'grupo_admin', 'finanzas', 'soporte') NOT NULL,
+	`activo` BOOLEAN NOT NULL DEFAULT TRUE,
+	`creado` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE `usuarios_grupos`(
+	`id_usuario` INT NOT NULL,
+	`id_grupo` INT NOT NULL,
+	PRIMARY KEY (`id_usuario`, `id_grupo`),
+	FOREIGN KEY (`id_usuario`) REFERENCES `usuarios`(`id`) ON DELETE CASCADE,
+	FOREIGN KEY (`id_grupo`) REFERENCES `grupos`(`id`)
+);
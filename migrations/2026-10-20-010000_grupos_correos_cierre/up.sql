@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `grupos`
+	ADD COLUMN `correos_cierre` JSON NULL;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS `webhooks_outbox`;
+DROP TABLE IF EXISTS `webhooks`;
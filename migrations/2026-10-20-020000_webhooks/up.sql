@@ -0,0 +1,26 @@
+-- Your SQL goes here
+CREATE TABLE `webhooks`(
+	`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+	`id_grupo` INT NOT NULL,
+	`url` VARCHAR(500) NOT NULL,
+	`secreto` VARCHAR(255) NOT NULL,
+	`eventos` JSON NOT NULL,
+	`activo` BOOLEAN NOT NULL DEFAULT TRUE,
+	`creado` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (`id_grupo`) REFERENCES `grupos`(`id`)
+);
+
+CREATE TABLE `webhooks_outbox`(
+	`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+	`id_webhook` INT NOT NULL,
+	`evento` VARCHAR(50) NOT NULL,
+	`payload` JSON NOT NULL,
+	`estado` VARCHAR(20) NOT NULL DEFAULT 'pendiente',
+	`intentos` INT NOT NULL DEFAULT 0,
+	`proximo_intento` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`ultimo_error` VARCHAR(500) NULL,
+	`creado` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`entregado` TIMESTAMP NULL,
+	FOREIGN KEY (`id_webhook`) REFERENCES `webhooks`(`id`),
+	INDEX `idx_webhooks_outbox_pendientes` (`estado`, `proximo_intento`)
+);
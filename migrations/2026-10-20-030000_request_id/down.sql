@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `reembolsos`
+	DROP COLUMN `request_id`;
+
+ALTER TABLE `transacciones`
+	DROP COLUMN `request_id`;
+
+ALTER TABLE `auditoria`
+	DROP INDEX `idx_auditoria_request_id`,
+	DROP COLUMN `request_id`;
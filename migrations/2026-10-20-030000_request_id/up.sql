@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE `auditoria`
+	ADD COLUMN `request_id` VARCHAR(64) NULL,
+	ADD INDEX `idx_auditoria_request_id` (`request_id`);
+
+ALTER TABLE `transacciones`
+	ADD COLUMN `request_id` VARCHAR(64) NULL;
+
+ALTER TABLE `reembolsos`
+	ADD COLUMN `request_id` VARCHAR(64) NULL;
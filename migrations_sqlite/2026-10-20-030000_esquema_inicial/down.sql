@@ -0,0 +1,18 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE `webhooks_outbox`;
+DROP TABLE `webhooks`;
+DROP TABLE `usuarios_grupos`;
+DROP TABLE `usuarios`;
+DROP TABLE `sesiones_cajero`;
+DROP TABLE `impuestos_grupo`;
+DROP TABLE `transaccion_items`;
+DROP TABLE `reembolsos`;
+DROP TABLE `transacciones`;
+DROP TABLE `auditoria`;
+DROP TABLE `caja_cierre_errores`;
+DROP TABLE `caja_cierre_resumen`;
+DROP TABLE `caja_transiciones`;
+DROP TABLE `kioskos`;
+DROP TABLE `cajas`;
+DROP TABLE `cajeros`;
+DROP TABLE `grupos`;
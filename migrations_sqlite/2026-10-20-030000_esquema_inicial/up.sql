@@ -0,0 +1,232 @@
+-- Esquema completo equivalente a migrations/ hasta 2026-10-20-030000_request_id, para SQLite.
+-- Las tablas base (grupos, cajas, kioskos, cierres) no tienen migración en MySQL.
+CREATE TABLE `grupos`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_yappy` VARCHAR(100) NOT NULL,
+	`nombre` VARCHAR(100) NOT NULL,
+	`api_key` VARCHAR(255) NOT NULL,
+	`secret_key` VARCHAR(255) NOT NULL,
+	`plantilla_recibo` TEXT NULL,
+	`correos_cierre` JSON NULL
+);
+
+CREATE TABLE `cajeros`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_grupo` INTEGER NOT NULL,
+	`nombre` VARCHAR(100) NOT NULL,
+	`pin_hash` VARCHAR(255) NOT NULL,
+	`activo` BOOLEAN NOT NULL DEFAULT TRUE,
+	FOREIGN KEY (`id_grupo`) REFERENCES `grupos`(`id`)
+);
+
+CREATE TABLE `cajas`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_grupo` INTEGER NOT NULL,
+	`nombre_caja` VARCHAR(100) NOT NULL,
+	`tipo` TEXT NOT NULL DEFAULT 'kiosko' CHECK (`tipo` IN ('kiosko', 'cajero')),
+	`token_autorizacion` TEXT NULL,
+	`transaccion_actual` VARCHAR(100) NULL,
+	`estado` TEXT NOT NULL DEFAULT 'cerrado' CHECK (`estado` IN ('abriendo', 'abierto', 'cerrando', 'cerrado', 'error_cierre', 'deshabilitado')),
+	`id_cajero_actual` INTEGER NULL,
+	FOREIGN KEY (`id_grupo`) REFERENCES `grupos`(`id`),
+	FOREIGN KEY (`id_cajero_actual`) REFERENCES `cajeros`(`id`)
+);
+
+CREATE TABLE `kioskos`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_caja` INTEGER NOT NULL,
+	`nombre` VARCHAR(100) NOT NULL,
+	`mac_address` VARCHAR(50) NOT NULL,
+	`ultima_conexion` TIMESTAMP NULL,
+	`version_app` VARCHAR(50) NULL,
+	`ip` VARCHAR(45) NULL,
+	`en_linea` BOOLEAN NOT NULL DEFAULT FALSE,
+	FOREIGN KEY (`id_caja`) REFERENCES `cajas`(`id`)
+);
+
+CREATE TABLE `caja_transiciones`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_caja` INTEGER NOT NULL,
+	`estado_anterior` TEXT NOT NULL CHECK (`estado_anterior` IN ('abriendo', 'abierto', 'cerrando', 'cerrado', 'error_cierre', 'deshabilitado')),
+	`estado_nuevo` TEXT NOT NULL CHECK (`estado_nuevo` IN ('abriendo', 'abierto', 'cerrando', 'cerrado', 'error_cierre', 'deshabilitado')),
+	`actor` TEXT NOT NULL CHECK (`actor` IN ('kiosko', 'cajero', 'scheduler', 'admin')),
+	`motivo` VARCHAR(255) NOT NULL,
+	`fecha` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (`id_caja`) REFERENCES `cajas`(`id`)
+);
+
+CREATE TABLE `caja_cierre_resumen`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_caja` INTEGER NOT NULL,
+	`tipo` VARCHAR(50) NOT NULL,
+	`monto` DECIMAL(10, 2) NOT NULL,
+	`transacciones` INTEGER NOT NULL,
+	`fecha` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`id_cajero` INTEGER NULL,
+	FOREIGN KEY (`id_caja`) REFERENCES `cajas`(`id`)
+);
+
+CREATE TABLE `caja_cierre_errores`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_caja` INTEGER NOT NULL,
+	`respuesta_json` JSON NOT NULL,
+	`fecha` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`id_cajero` INTEGER NULL,
+	FOREIGN KEY (`id_caja`) REFERENCES `cajas`(`id`)
+);
+
+CREATE TABLE `auditoria`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`actor` TEXT NOT NULL CHECK (`actor` IN ('kiosko', 'cajero', 'scheduler', 'admin')),
+	`id_kiosko` INTEGER NULL,
+	`id_caja` INTEGER NULL,
+	`accion` VARCHAR(50) NOT NULL,
+	`resumen` JSON NOT NULL,
+	`codigo_http` INTEGER NULL,
+	`codigo_yappy` VARCHAR(20) NULL,
+	`resultado` VARCHAR(20) NOT NULL,
+	`fecha` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`id_cajero` INTEGER NULL,
+	`request_id` VARCHAR(64) NULL
+);
+
+CREATE INDEX `idx_auditoria_caja_fecha` ON `auditoria` (`id_caja`, `fecha`);
+CREATE INDEX `idx_auditoria_request_id` ON `auditoria` (`request_id`);
+
+-- la auditoria es solo de insercion
+CREATE TRIGGER `auditoria_sin_update` BEFORE UPDATE ON `auditoria`
+BEGIN
+	SELECT RAISE(ABORT, 'auditoria es solo de insercion');
+END;
+
+CREATE TRIGGER `auditoria_sin_delete` BEFORE DELETE ON `auditoria`
+BEGIN
+	SELECT RAISE(ABORT, 'auditoria es solo de insercion');
+END;
+
+CREATE TABLE `transacciones`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_caja` INTEGER NOT NULL,
+	`id_cajero` INTEGER NULL,
+	`id_transaccion_yappy` VARCHAR(100) NOT NULL UNIQUE,
+	`id_orden` VARCHAR(100) NULL,
+	`tipo_qr` VARCHAR(10) NOT NULL,
+	`subtotal` DECIMAL(10, 2) NOT NULL,
+	`impuesto` DECIMAL(10, 2) NOT NULL,
+	`propina` DECIMAL(10, 2) NOT NULL,
+	`descuento` DECIMAL(10, 2) NOT NULL,
+	`total` DECIMAL(10, 2) NOT NULL,
+	`estado` VARCHAR(20) NOT NULL DEFAULT 'PENDING',
+	`fecha` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`fecha_actualizacion` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`motivo_cancelacion` VARCHAR(255) NULL,
+	`qr_hash` TEXT NULL,
+	`tasa_impuesto` DECIMAL(5, 4) NULL,
+	`discrepancia_impuesto` VARCHAR(255) NULL,
+	`request_id` VARCHAR(64) NULL,
+	FOREIGN KEY (`id_caja`) REFERENCES `cajas`(`id`)
+);
+
+CREATE INDEX `idx_transacciones_orden` ON `transacciones` (`id_orden`);
+
+-- equivalente a ON UPDATE CURRENT_TIMESTAMP
+CREATE TRIGGER `transacciones_fecha_actualizacion` AFTER UPDATE ON `transacciones`
+	WHEN NEW.`fecha_actualizacion` IS OLD.`fecha_actualizacion`
+BEGIN
+	UPDATE `transacciones` SET `fecha_actualizacion` = CURRENT_TIMESTAMP WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `reembolsos`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_transaccion` INTEGER NOT NULL,
+	`monto` DECIMAL(10, 2) NOT NULL,
+	`motivo` VARCHAR(255) NOT NULL,
+	`actor` TEXT NOT NULL CHECK (`actor` IN ('kiosko', 'cajero', 'scheduler', 'admin')),
+	`id_cajero` INTEGER NULL,
+	`exitoso` BOOLEAN NOT NULL,
+	`codigo_yappy` VARCHAR(20) NULL,
+	`respuesta_json` JSON NOT NULL,
+	`fecha` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`request_id` VARCHAR(64) NULL,
+	FOREIGN KEY (`id_transaccion`) REFERENCES `transacciones`(`id`)
+);
+
+CREATE TABLE `transaccion_items`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_transaccion` INTEGER NOT NULL,
+	`sku` VARCHAR(100) NOT NULL,
+	`descripcion` VARCHAR(255) NOT NULL,
+	`cantidad` INTEGER NOT NULL,
+	`precio_unitario` DECIMAL(10, 2) NOT NULL,
+	`descuento` DECIMAL(10, 2) NOT NULL,
+	`exento_itbms` BOOLEAN NOT NULL,
+	`impuesto` DECIMAL(10, 2) NOT NULL,
+	`total` DECIMAL(10, 2) NOT NULL,
+	`categoria` VARCHAR(50) NULL,
+	`tasa_impuesto` DECIMAL(5, 4) NOT NULL DEFAULT 0.0000,
+	FOREIGN KEY (`id_transaccion`) REFERENCES `transacciones`(`id`)
+);
+
+CREATE TABLE `impuestos_grupo`(
+	`id_grupo` INTEGER NOT NULL PRIMARY KEY,
+	`tasa_general` DECIMAL(5, 4) NOT NULL DEFAULT 0.0700,
+	`tasas_categoria` JSON NOT NULL,
+	`categorias_exentas` JSON NOT NULL,
+	`modo_redondeo` TEXT NOT NULL DEFAULT 'half_up' CHECK (`modo_redondeo` IN ('half_up', 'half_even', 'up', 'down')),
+	FOREIGN KEY (`id_grupo`) REFERENCES `grupos`(`id`)
+);
+
+CREATE TABLE `sesiones_cajero`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_caja` INTEGER NOT NULL,
+	`id_cajero` INTEGER NOT NULL,
+	`inicio` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`fin` TIMESTAMP NULL,
+	FOREIGN KEY (`id_caja`) REFERENCES `cajas`(`id`),
+	FOREIGN KEY (`id_cajero`) REFERENCES `cajeros`(`id`)
+);
+
+CREATE TABLE `usuarios`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`nombre` VARCHAR(100) NOT NULL,
+	`email` VARCHAR(255) NOT NULL UNIQUE,
+	`password_hash` VARCHAR(255) NOT NULL,
+	`rol` TEXT NOT NULL CHECK (`rol` IN ('superadmin', 'grupo_admin', 'finanzas', 'soporte')),
+	`activo` BOOLEAN NOT NULL DEFAULT TRUE,
+	`creado` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE `usuarios_grupos`(
+	`id_usuario` INTEGER NOT NULL,
+	`id_grupo` INTEGER NOT NULL,
+	PRIMARY KEY (`id_usuario`, `id_grupo`),
+	FOREIGN KEY (`id_usuario`) REFERENCES `usuarios`(`id`) ON DELETE CASCADE,
+	FOREIGN KEY (`id_grupo`) REFERENCES `grupos`(`id`)
+);
+
+CREATE TABLE `webhooks`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_grupo` INTEGER NOT NULL,
+	`url` VARCHAR(500) NOT NULL,
+	`secreto` VARCHAR(255) NOT NULL,
+	`eventos` JSON NOT NULL,
+	`activo` BOOLEAN NOT NULL DEFAULT TRUE,
+	`creado` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (`id_grupo`) REFERENCES `grupos`(`id`)
+);
+
+CREATE TABLE `webhooks_outbox`(
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`id_webhook` INTEGER NOT NULL,
+	`evento` VARCHAR(50) NOT NULL,
+	`payload` JSON NOT NULL,
+	`estado` VARCHAR(20) NOT NULL DEFAULT 'pendiente',
+	`intentos` INTEGER NOT NULL DEFAULT 0,
+	`proximo_intento` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	`ultimo_error` VARCHAR(500) NULL,
+	`creado` TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+	`entregado` TIMESTAMP NULL,
+	FOREIGN KEY (`id_webhook`) REFERENCES `webhooks`(`id`)
+);
+
+CREATE INDEX `idx_webhooks_outbox_pendientes` ON `webhooks_outbox` (`estado`, `proximo_intento`);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `grupos`
+	ADD COLUMN `version_credenciales` INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `reembolsos`
+	ADD COLUMN `pendiente` BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,624 @@
+use clap::{Parser, Subcommand};
+use diesel::prelude::*;
+use dotenvy::dotenv;
+use serde_json::{Value, json};
+
+use macy_utp::AppState;
+use macy_utp::db::conection::create_pool;
+use macy_utp::db::models::{AlcanceCierre, EventoWebhook, NewAuditoria, NewKiosko, NewWebhook, Webhook};
+use macy_utp::db::repos::Repos;
+use macy_utp::db::types::enums::{ActorEnum, CajasEstadoEnum, CajasTipoEnum};
+use macy_utp::schema::{caja_cierre_errores, cajas, grupos, kioskos, webhooks, webhooks_outbox};
+use macy_utp::utils::auditoria::{registrar_auditoria, resultado_de};
+use macy_utp::utils::cajas_utils::{ResultadoCierre, abrir_sesion_en_yappy, cerrar_cajas, forzar_cierre};
+use macy_utp::utils::monitor_transacciones::MonitorTransacciones;
+use macy_utp::utils::tiempo;
+use macy_utp::utils::utils::get_info_by_mac_address;
+use macy_utp::utils::webhooks::generar_secreto;
+
+/// Herramienta de operación de MACY: kioskos, cajas, credenciales y cierres.
+#[derive(Parser)]
+#[command(name = "macy-admin", version)]
+struct Cli {
+    /// Imprime JSON en lugar de una tabla
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    comando: Comando,
+}
+
+#[derive(Subcommand)]
+enum Comando {
+    /// Kioskos registrados por mac-address
+    #[command(subcommand)]
+    Kiosk(ComandoKiosko),
+    /// Estado y sesión de las cajas en Yappy
+    #[command(subcommand)]
+    Caja(ComandoCaja),
+    /// Configuración de los grupos
+    #[command(subcommand)]
+    Grupo(ComandoGrupo),
+    /// Cierres de caja
+    #[command(subcommand)]
+    Cierre(ComandoCierre),
+    /// Webhooks salientes por grupo
+    #[command(subcommand)]
+    Webhook(ComandoWebhook),
+}
+
+#[derive(Subcommand)]
+enum ComandoKiosko {
+    /// Registra un kiosko nuevo en una caja
+    Add {
+        #[arg(long)]
+        caja: i32,
+        #[arg(long)]
+        nombre: String,
+        #[arg(long)]
+        mac: String,
+    },
+    /// Lista los kioskos, opcionalmente de una sola caja
+    List {
+        #[arg(long)]
+        caja: Option<i32>,
+    },
+    /// Elimina el kiosko con esa mac-address
+    Remove { mac: String },
+}
+
+#[derive(Subcommand)]
+enum ComandoCaja {
+    /// Lista las cajas, opcionalmente de un solo grupo
+    List {
+        #[arg(long)]
+        grupo: Option<i32>,
+    },
+    /// Abre la sesión de la caja en Yappy
+    Open { id: i32 },
+    /// Cierra la sesión de la caja en Yappy
+    Close { id: i32 },
+}
+
+#[derive(Subcommand)]
+enum ComandoGrupo {
+    /// Reemplaza las credenciales de Yappy del grupo
+    SetKeys {
+        id: i32,
+        #[arg(long)]
+        api_key: String,
+        #[arg(long)]
+        secret_key: String,
+    },
+    /// Destinatarios del correo de cierre; sin correos se dejan de enviar
+    SetCorreos { id: i32, correos: Vec<String> },
+}
+
+#[derive(Subcommand)]
+enum ComandoCierre {
+    /// Corre el cierre de las cajas abiertas o con error, igual que el cierre programado
+    Run {
+        #[arg(long)]
+        caja: Option<i32>,
+    },
+    /// Últimos errores de cierre guardados
+    Errors {
+        #[arg(long)]
+        caja: Option<i32>,
+        /// Día de Panamá (AAAA-MM-DD)
+        #[arg(long)]
+        dia: Option<chrono::NaiveDate>,
+        #[arg(long, default_value_t = 20)]
+        limite: i64,
+    },
+}
+
+#[derive(Subcommand)]
+enum ComandoWebhook {
+    /// Registra un webhook; sin --eventos recibe todos. Imprime el secreto de la firma
+    Add {
+        #[arg(long)]
+        grupo: i32,
+        #[arg(long)]
+        url: String,
+        /// pago.completado, pago.reembolsado, caja.abierta, cierre.finalizado
+        #[arg(long, value_delimiter = ',')]
+        eventos: Vec<String>,
+    },
+    /// Lista los webhooks con sus entregas pendientes
+    List {
+        #[arg(long)]
+        grupo: Option<i32>,
+    },
+    /// Desactiva el webhook; sus entregas pendientes dejan de enviarse
+    Remove { id: i32 },
+}
+
+// id, nombre, mac_address, id_caja, nombre_caja, en_linea, ultima_conexion
+type FilaKiosko = (i32, String, String, i32, String, bool, Option<chrono::NaiveDateTime>);
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    let cli = Cli::parse();
+
+    let db_pool = create_pool();
+    let state = AppState {
+        repos: Repos::diesel(db_pool.clone()),
+        db_pool,
+        monitor: MonitorTransacciones::default(),
+    };
+
+    let resultado = match cli.comando {
+        Comando::Kiosk(comando) => kiosko(&state, comando, cli.json),
+        Comando::Caja(comando) => caja(&state, comando, cli.json).await,
+        Comando::Grupo(comando) => grupo(&state, comando, cli.json),
+        Comando::Cierre(comando) => cierre(&state, comando, cli.json).await,
+        Comando::Webhook(comando) => webhook(&state, comando, cli.json),
+    };
+
+    if let Err(err) = resultado {
+        println!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn kiosko(state: &AppState, comando: ComandoKiosko, json: bool) -> Result<(), String> {
+    let mut conn = state.db_pool.get().unwrap();
+
+    match comando {
+        ComandoKiosko::Add { caja, nombre, mac } => {
+            diesel::insert_into(kioskos::table)
+                .values(&NewKiosko {
+                    id_caja: caja,
+                    nombre,
+                    mac_address: mac.clone(),
+                })
+                .execute(&mut conn)
+                .map_err(|err| err.to_string())?;
+
+            mostrar_mensaje(json, json!({ "mac_address": mac, "id_caja": caja }), "Kiosko registrado");
+        }
+        ComandoKiosko::List { caja } => {
+            let mut query = kioskos::table
+                .inner_join(cajas::table)
+                .select((
+                    kioskos::id,
+                    kioskos::nombre,
+                    kioskos::mac_address,
+                    kioskos::id_caja,
+                    cajas::nombre_caja,
+                    kioskos::en_linea,
+                    kioskos::ultima_conexion,
+                ))
+                .order(kioskos::id)
+                .into_boxed();
+
+            if let Some(id_caja) = caja {
+                query = query.filter(kioskos::id_caja.eq(id_caja));
+            }
+
+            let filas: Vec<FilaKiosko> = query.load(&mut conn).map_err(|err| err.to_string())?;
+
+            let filas = filas
+                .into_iter()
+                .map(|(id, nombre, mac, id_caja, caja, en_linea, ultima_conexion)| {
+                    json!({
+                        "id": id,
+                        "nombre": nombre,
+                        "mac_address": mac,
+                        "id_caja": id_caja,
+                        "caja": caja,
+                        "en_linea": en_linea,
+                        "ultima_conexion": ultima_conexion.as_ref().map(tiempo::rfc3339),
+                    })
+                })
+                .collect();
+
+            mostrar(
+                json,
+                &["id", "nombre", "mac_address", "id_caja", "caja", "en_linea", "ultima_conexion"],
+                filas,
+            );
+        }
+        ComandoKiosko::Remove { mac } => {
+            let borrados = diesel::delete(kioskos::table.filter(kioskos::mac_address.eq(&mac)))
+                .execute(&mut conn)
+                .map_err(|err| err.to_string())?;
+
+            if borrados == 0 {
+                return Err(format!("No existe un kiosko con mac-address {}", mac));
+            }
+
+            mostrar_mensaje(json, json!({ "mac_address": mac }), "Kiosko eliminado");
+        }
+    }
+
+    Ok(())
+}
+
+async fn caja(state: &AppState, comando: ComandoCaja, json: bool) -> Result<(), String> {
+    match comando {
+        ComandoCaja::List { grupo } => {
+            let mut conn = state.db_pool.get().unwrap();
+
+            let mut query = cajas::table
+                .inner_join(grupos::table)
+                .select((
+                    cajas::id,
+                    cajas::nombre_caja,
+                    grupos::nombre,
+                    cajas::tipo,
+                    cajas::estado,
+                    cajas::id_cajero_actual,
+                ))
+                .order(cajas::id)
+                .into_boxed();
+
+            if let Some(id_grupo) = grupo {
+                query = query.filter(cajas::id_grupo.eq(id_grupo));
+            }
+
+            let filas: Vec<(i32, String, String, CajasTipoEnum, CajasEstadoEnum, Option<i32>)> =
+                query.load(&mut conn).map_err(|err| err.to_string())?;
+
+            let filas = filas
+                .into_iter()
+                .map(|(id, nombre_caja, grupo, tipo, estado, id_cajero)| {
+                    json!({
+                        "id": id,
+                        "nombre_caja": nombre_caja,
+                        "grupo": grupo,
+                        "tipo": tipo,
+                        "estado": estado,
+                        "id_cajero_actual": id_cajero,
+                    })
+                })
+                .collect();
+
+            mostrar(
+                json,
+                &["id", "nombre_caja", "grupo", "tipo", "estado", "id_cajero_actual"],
+                filas,
+            );
+        }
+        ComandoCaja::Open { id } => {
+            // la apertura usa los datos de la caja tal como los vería su kiosko
+            let mac_address: String = {
+                let mut conn = state.db_pool.get().unwrap();
+                kioskos::table
+                    .filter(kioskos::id_caja.eq(id))
+                    .select(kioskos::mac_address)
+                    .first(&mut conn)
+                    .map_err(|_| format!("La caja {} no tiene kioskos registrados", id))?
+            };
+
+            let info = get_info_by_mac_address(state, &mac_address).map_err(|err| err.to_string())?;
+            let id_kiosko = info.id_kiosko;
+
+            let resultado = abrir_sesion_en_yappy(state, info, ActorEnum::Admin).await;
+
+            let (respuesta, exito) = match resultado {
+                Ok(json) => (json, true),
+                Err((_status, err_json)) => (err_json.0, false),
+            };
+            let codigo_yappy = respuesta
+                .pointer("/status/code")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+
+            registrar_auditoria(
+                state,
+                NewAuditoria {
+                    actor: ActorEnum::Admin,
+                    id_kiosko: Some(id_kiosko),
+                    id_caja: Some(id),
+                    id_cajero: None,
+                    request_id: None,
+                    accion: "apertura-manual".to_string(),
+                    resumen: json!({ "error": respuesta.get("error") }),
+                    codigo_http: None,
+                    resultado: resultado_de(exito, codigo_yappy.as_deref()),
+                    codigo_yappy: codigo_yappy.clone(),
+                },
+            );
+
+            if !exito || respuesta.pointer("/body/token").is_none() {
+                return Err(format!(
+                    "Yappy no abrió la caja {} ({}): {}",
+                    id,
+                    codigo_yappy.as_deref().unwrap_or("sin código"),
+                    respuesta
+                ));
+            }
+
+            mostrar_mensaje(json, json!({ "id_caja": id, "codigo_yappy": codigo_yappy }), "Caja abierta");
+        }
+        ComandoCaja::Close { id } => {
+            let resultado = forzar_cierre(
+                state,
+                id,
+                ActorEnum::Admin,
+                "cierre-manual",
+                "Cierre forzado desde macy-admin".to_string(),
+            )
+            .await
+            .map_err(|err| err.to_string())?
+            .ok_or_else(|| format!("La caja {} está cerrada o deshabilitada", id))?;
+
+            mostrar_cierres(json, &[resultado])?;
+        }
+    }
+
+    Ok(())
+}
+
+fn grupo(state: &AppState, comando: ComandoGrupo, json: bool) -> Result<(), String> {
+    let mut conn = state.db_pool.get().unwrap();
+
+    match comando {
+        ComandoGrupo::SetKeys {
+            id,
+            api_key,
+            secret_key,
+        } => {
+            let actualizados = diesel::update(grupos::table.find(id))
+                .set((
+                    grupos::api_key.eq(api_key),
+                    grupos::secret_key.eq(secret_key),
+                    grupos::version_credenciales.eq(grupos::version_credenciales + 1),
+                ))
+                .execute(&mut conn)
+                .map_err(|err| err.to_string())?;
+
+            if actualizados == 0 {
+                return Err(format!("No existe el grupo {}", id));
+            }
+
+            mostrar_mensaje(json, json!({ "id_grupo": id }), "Credenciales actualizadas");
+        }
+        ComandoGrupo::SetCorreos { id, correos } => {
+            if let Some(invalido) = correos.iter().find(|c| c.parse::<lettre::Address>().is_err()) {
+                return Err(format!("Correo inválido: {}", invalido));
+            }
+
+            let valor = (!correos.is_empty()).then(|| json!(correos));
+
+            let actualizados = diesel::update(grupos::table.find(id))
+                .set(grupos::correos_cierre.eq(valor))
+                .execute(&mut conn)
+                .map_err(|err| err.to_string())?;
+
+            if actualizados == 0 {
+                return Err(format!("No existe el grupo {}", id));
+            }
+
+            mostrar_mensaje(json, json!({ "id_grupo": id, "correos": correos }), "Correos de cierre actualizados");
+        }
+    }
+
+    Ok(())
+}
+
+async fn cierre(state: &AppState, comando: ComandoCierre, json: bool) -> Result<(), String> {
+    match comando {
+        ComandoCierre::Run { caja } => {
+            let alcance = caja.map_or(AlcanceCierre::Todas, AlcanceCierre::Caja);
+            let resultados = cerrar_cajas(state, alcance, ActorEnum::Admin, "cierre-manual")
+                .await
+                .map_err(|err| err.to_string())?;
+
+            mostrar_cierres(json, &resultados)?;
+        }
+        ComandoCierre::Errors { caja, dia, limite } => {
+            let mut conn = state.db_pool.get().unwrap();
+
+            let mut query = caja_cierre_errores::table
+                .inner_join(cajas::table)
+                .select((
+                    caja_cierre_errores::id,
+                    caja_cierre_errores::id_caja,
+                    cajas::nombre_caja,
+                    caja_cierre_errores::fecha,
+                    caja_cierre_errores::respuesta_json,
+                ))
+                .order(caja_cierre_errores::id.desc())
+                .limit(limite)
+                .into_boxed();
+
+            if let Some(id_caja) = caja {
+                query = query.filter(caja_cierre_errores::id_caja.eq(id_caja));
+            }
+            if let Some(dia) = dia {
+                let (inicio, fin) = tiempo::limites_dia(dia);
+                query = query.filter(caja_cierre_errores::fecha.ge(inicio).and(caja_cierre_errores::fecha.lt(fin)));
+            }
+
+            let filas: Vec<(i32, i32, String, Option<chrono::NaiveDateTime>, Value)> =
+                query.load(&mut conn).map_err(|err| err.to_string())?;
+
+            let filas = filas
+                .into_iter()
+                .map(|(id, id_caja, caja, fecha, respuesta)| {
+                    json!({
+                        "id": id,
+                        "id_caja": id_caja,
+                        "caja": caja,
+                        "fecha": fecha.as_ref().map(tiempo::rfc3339),
+                        "codigo_yappy": respuesta.pointer("/status/code"),
+                        "error": respuesta
+                            .pointer("/status/description")
+                            .or_else(|| respuesta.get("error")),
+                        "respuesta": respuesta,
+                    })
+                })
+                .collect();
+
+            mostrar(
+                json,
+                &["id", "id_caja", "caja", "fecha", "codigo_yappy", "error"],
+                filas,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn webhook(state: &AppState, comando: ComandoWebhook, json: bool) -> Result<(), String> {
+    let mut conn = state.db_pool.get().unwrap();
+
+    match comando {
+        ComandoWebhook::Add { grupo, url, eventos } => {
+            if let Some(invalido) = eventos.iter().find(|e| EventoWebhook::desde_nombre(e).is_none()) {
+                return Err(format!("Evento desconocido: {}", invalido));
+            }
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err(format!("URL inválida: {}", url));
+            }
+
+            let secreto = generar_secreto();
+
+            diesel::insert_into(webhooks::table)
+                .values(&NewWebhook {
+                    id_grupo: grupo,
+                    url: url.clone(),
+                    secreto: secreto.clone(),
+                    eventos: json!(eventos),
+                })
+                .execute(&mut conn)
+                .map_err(|err| err.to_string())?;
+
+            // el secreto solo se muestra al crearlo
+            mostrar_mensaje(
+                json,
+                json!({ "id_grupo": grupo, "url": url, "eventos": eventos, "secreto": secreto }),
+                &format!("Webhook registrado. Secreto para verificar la firma: {}", secreto),
+            );
+        }
+        ComandoWebhook::List { grupo } => {
+            let mut query = webhooks::table
+                .select(Webhook::as_select())
+                .order(webhooks::id)
+                .into_boxed();
+
+            if let Some(id_grupo) = grupo {
+                query = query.filter(webhooks::id_grupo.eq(id_grupo));
+            }
+
+            let lista: Vec<Webhook> = query.load(&mut conn).map_err(|err| err.to_string())?;
+
+            let filas = lista
+                .into_iter()
+                .map(|webhook| {
+                    let pendientes: i64 = webhooks_outbox::table
+                        .filter(webhooks_outbox::id_webhook.eq(webhook.id))
+                        .filter(webhooks_outbox::estado.eq("pendiente"))
+                        .count()
+                        .get_result(&mut conn)?;
+
+                    let mut fila = serde_json::to_value(&webhook).unwrap();
+                    fila["pendientes"] = json!(pendientes);
+                    Ok(fila)
+                })
+                .collect::<QueryResult<Vec<_>>>()
+                .map_err(|err| err.to_string())?;
+
+            mostrar(
+                json,
+                &["id", "id_grupo", "url", "eventos", "activo", "pendientes"],
+                filas,
+            );
+        }
+        ComandoWebhook::Remove { id } => {
+            let actualizados = diesel::update(webhooks::table.find(id))
+                .set(webhooks::activo.eq(false))
+                .execute(&mut conn)
+                .map_err(|err| err.to_string())?;
+
+            if actualizados == 0 {
+                return Err(format!("No existe el webhook {}", id));
+            }
+
+            mostrar_mensaje(json, json!({ "id": id }), "Webhook desactivado");
+        }
+    }
+
+    Ok(())
+}
+
+/// Los cierres fallidos hacen que el comando termine con error, para poder usarlo en scripts.
+fn mostrar_cierres(json: bool, resultados: &[ResultadoCierre]) -> Result<(), String> {
+    let filas = resultados
+        .iter()
+        .map(|r| serde_json::to_value(r).unwrap())
+        .collect();
+
+    mostrar(json, &["id_caja", "nombre_caja", "exito", "codigo_yappy"], filas);
+
+    let fallidos = resultados.iter().filter(|r| !r.exito).count();
+    if fallidos > 0 {
+        return Err(format!("{} de {} cierres fallaron", fallidos, resultados.len()));
+    }
+
+    Ok(())
+}
+
+fn mostrar_mensaje(json: bool, datos: Value, mensaje: &str) {
+    if json {
+        println!("{}", serde_json::to_string_pretty(&datos).unwrap());
+    } else {
+        println!("{}", mensaje);
+    }
+}
+
+/// Imprime las filas como arreglo JSON o como tabla con las columnas indicadas.
+fn mostrar(json: bool, columnas: &[&str], filas: Vec<Value>) {
+    if json {
+        println!("{}", serde_json::to_string_pretty(&Value::Array(filas)).unwrap());
+        return;
+    }
+
+    let celdas: Vec<Vec<String>> = filas
+        .iter()
+        .map(|fila| {
+            columnas
+                .iter()
+                .map(|columna| match fila.get(*columna) {
+                    None | Some(Value::Null) => "-".to_string(),
+                    Some(Value::String(s)) => s.clone(),
+                    Some(otro) => otro.to_string(),
+                })
+                .collect()
+        })
+        .collect();
+
+    let anchos: Vec<usize> = columnas
+        .iter()
+        .enumerate()
+        .map(|(i, columna)| {
+            celdas
+                .iter()
+                .map(|fila| fila[i].chars().count())
+                .chain([columna.len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let linea = |valores: Vec<&str>| {
+        valores
+            .iter()
+            .zip(&anchos)
+            .map(|(valor, ancho)| format!("{:<ancho$}", valor, ancho = ancho))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", linea(columnas.to_vec()));
+    for fila in &celdas {
+        println!("{}", linea(fila.iter().map(String::as_str).collect()));
+    }
+}
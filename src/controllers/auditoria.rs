@@ -0,0 +1,98 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use serde_json::Value;
+
+use crate::AppState;
+use crate::db::models::{Auditoria, ErrorResponse, FiltroAuditoria};
+use crate::db::types::enums::RolUsuarioEnum;
+use crate::middlewares::usuarios::UsuarioSesion;
+use crate::utils::utils::json_error;
+
+#[utoipa::path(get, path = "/auditoria", tag = "admin",
+    security(("jwt" = [])),
+    params(FiltroAuditoria),
+    responses(
+        (status = 200, description = "Registros de auditoría, más recientes primero", body = Vec<Auditoria>),
+        (status = 401, description = "Sesión inválida", body = ErrorResponse),
+        (status = 403, description = "Rol sin permiso", body = ErrorResponse),
+    ))]
+pub async fn get_auditoria(
+    sesion: UsuarioSesion,
+    State(state): State<AppState>,
+    Query(filtro): Query<FiltroAuditoria>,
+) -> Result<Json<Vec<Auditoria>>, (StatusCode, Json<Value>)> {
+    sesion.exigir_rol(&[RolUsuarioEnum::GrupoAdmin, RolUsuarioEnum::Soporte])?;
+    // fuera del superadmin, solo los registros de cajas de sus grupos
+    let registros = state
+        .repos
+        .auditoria
+        .listar(&filtro, sesion.grupos_visibles())
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    Ok(Json(registros))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::NewAuditoria;
+    use crate::db::repos::memoria::prueba;
+    use crate::db::types::enums::{ActorEnum, CajasEstadoEnum, CajasTipoEnum};
+    use crate::utils::auditoria::registrar_auditoria;
+    use serde_json::json;
+
+    fn registro(id_caja: i32, accion: &str) -> NewAuditoria {
+        NewAuditoria {
+            actor: ActorEnum::Kiosko,
+            id_kiosko: None,
+            id_caja: Some(id_caja),
+            accion: accion.to_string(),
+            resumen: json!({}),
+            codigo_http: Some(200),
+            codigo_yappy: None,
+            resultado: "exito".to_string(),
+            id_cajero: None,
+            request_id: None,
+        }
+    }
+
+    fn filtro(valor: Value) -> Query<FiltroAuditoria> {
+        Query(serde_json::from_value(valor).unwrap())
+    }
+
+    #[tokio::test]
+    async fn un_admin_de_grupo_solo_ve_sus_cajas() {
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        let mut otra_caja = memoria.cajas[0].clone();
+        otra_caja.id = 2;
+        otra_caja.id_grupo = 2;
+        memoria.cajas.push(otra_caja);
+        let (state, _) = prueba::estado(memoria);
+
+        registrar_auditoria(&state, registro(1, "abrir-caja"));
+        registrar_auditoria(&state, registro(2, "abrir-caja"));
+        registrar_auditoria(&state, registro(1, "generar-qr"));
+
+        let sesion = UsuarioSesion {
+            id: 1,
+            nombre: "Admin".to_string(),
+            rol: RolUsuarioEnum::GrupoAdmin,
+            grupos: vec![1],
+        };
+
+        let Json(registros) = get_auditoria(sesion.clone(), State(state.clone()), filtro(json!({})))
+            .await
+            .unwrap();
+        let ids: Vec<i32> = registros.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![3, 1]);
+
+        let Json(registros) = get_auditoria(sesion, State(state), filtro(json!({ "accion": "abrir-caja" })))
+            .await
+            .unwrap();
+        assert_eq!(registros.len(), 1);
+        assert_eq!(registros[0].id_caja, Some(1));
+    }
+}
@@ -0,0 +1,270 @@
+use crate::AppState;
+use crate::controllers::structs::yappy::{CuerpoSesionYappy, RespuestaYappy};
+use crate::db::models::ErrorResponse;
+use crate::db::types::enums::CajasTipoEnum;
+use crate::utils::cajas_utils::{abrir_caja_and_return_value, guardar_datos_caja};
+use crate::utils::credenciales::verificar_secreto;
+use crate::utils::utils::{get_info_by_mac_address, json_error};
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+#[derive(Deserialize, ToSchema)]
+pub struct LoginCajero {
+    pub id_cajero: i32,
+    pub pin: String,
+}
+
+/// Turno recién abierto; `yappy` es la respuesta de la apertura.
+pub struct TurnoAbierto {
+    pub cajero: String,
+    pub caja: String,
+    pub yappy: Value,
+}
+
+#[utoipa::path(post, path = "/cajero/login", tag = "cajeros",
+    security(("mac_address" = [])),
+    request_body = LoginCajero,
+    responses(
+        (status = 200, description = "Turno abierto y sesión de Yappy a nombre del cajero", body = Value),
+        (status = 401, description = "Cajero o PIN inválido", body = ErrorResponse),
+        (status = 409, description = "Ya hay un cajero en turno", body = ErrorResponse),
+        (status = 502, description = "Yappy no abrió la sesión; el turno no queda abierto", body = ErrorResponse),
+    ))]
+pub async fn login_cajero(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(payload): Json<LoginCajero>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let turno = abrir_turno(headers, &state, payload).await?;
+
+    Ok(Json(json!({
+        "success": true,
+        "data": {
+            "cajero": turno.cajero,
+            "caja": turno.caja,
+            "yappy": turno.yappy,
+        }
+    })))
+}
+
+/// Valida el PIN, deja al cajero en turno y abre la sesión de Yappy a su nombre.
+pub async fn abrir_turno(
+    headers: HeaderMap,
+    state: &AppState,
+    payload: LoginCajero,
+) -> Result<TurnoAbierto, (StatusCode, Json<Value>)> {
+
+    let mac_address = headers
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok())
+        .ok_or_else(|| json_error(StatusCode::FORBIDDEN, "Prohibido"))?;
+
+    let info = get_info_by_mac_address(state, mac_address)
+        .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?;
+
+    if info.tipo != CajasTipoEnum::Cajero {
+        return Err(json_error(
+            StatusCode::BAD_REQUEST,
+            "Esta caja no es atendida por cajero",
+        ));
+    }
+
+    if info.id_cajero.is_some_and(|id| id != payload.id_cajero) {
+        return Err(json_error(
+            StatusCode::CONFLICT,
+            "Ya hay un cajero en turno en esta caja",
+        ));
+    }
+
+    let cajas = &state.repos.cajas;
+
+    let cajero = cajas
+        .buscar_cajero(payload.id_cajero)
+        .ok()
+        .filter(|c| c.id_grupo == info.id_grupo && c.activo)
+        .filter(|c| verificar_secreto(&payload.pin, &c.pin_hash))
+        .ok_or_else(|| json_error(StatusCode::UNAUTHORIZED, "Cajero o PIN inválido"))?;
+
+    let en_otra_caja = cajas
+        .turno_en_otra_caja(cajero.id, info.id_caja)
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    if en_otra_caja {
+        return Err(json_error(
+            StatusCode::CONFLICT,
+            "El cajero ya tiene un turno abierto en otra caja",
+        ));
+    }
+
+    if info.id_cajero.is_none() {
+        cajas
+            .iniciar_turno(info.id_caja, cajero.id)
+            .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+        // la apertura vuelve a leer el kiosko y necesita ver al cajero
+        state.repos.kioskos.invalidar_caja(info.id_caja);
+    }
+
+    // la sesion en Yappy se abre a nombre del cajero (device.user)
+    let response_json = abrir_caja_and_return_value(headers, state.clone()).await;
+
+    let error = match &response_json {
+        Ok(json) => {
+            let yappy = RespuestaYappy::<CuerpoSesionYappy>::de(json);
+            let abierta = yappy.body.is_some_and(|cuerpo| cuerpo.token.is_some());
+            (!abierta).then(|| {
+                let detalle = yappy
+                    .status
+                    .and_then(|status| status.description)
+                    .unwrap_or_else(|| "sin token".to_string());
+                json_error(
+                    StatusCode::BAD_GATEWAY,
+                    format!("No se pudo abrir la sesión de Yappy: {}", detalle),
+                )
+            })
+        }
+        Err(err) => Some(err.clone()),
+    };
+
+    if let Some(error) = error {
+        // solo se deshace el turno si lo abrio este login; un cajero que ya estaba
+        // en turno lo conserva aunque la reapertura falle
+        if info.id_cajero.is_none() {
+            revertir_turno(state, info.id_caja);
+        }
+        return Err(error);
+    }
+
+    Ok(TurnoAbierto {
+        cajero: cajero.nombre,
+        caja: info.nombre_caja,
+        yappy: response_json.unwrap_or_default(),
+    })
+}
+
+fn revertir_turno(state: &AppState, id_caja: i32) {
+    if let Err(err) = state.repos.cajas.revertir_turno(id_caja) {
+        tracing::error!("no se pudo deshacer el turno de la caja {}: {}", id_caja, err);
+    }
+
+    state.repos.kioskos.invalidar_caja(id_caja);
+}
+
+#[utoipa::path(post, path = "/cajero/logout", tag = "cajeros",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Turno cerrado con su resumen", body = Value),
+        (status = 400, description = "No hay cajero en turno", body = ErrorResponse),
+    ))]
+pub async fn logout_cajero(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let response_json = terminar_turno(&headers, state).await?;
+
+    Ok(Json(json!({
+        "success": true,
+        "data": response_json
+    })))
+}
+
+/// Cierra la sesión de Yappy del cajero en turno; devuelve la respuesta de Yappy con el resumen.
+pub async fn terminar_turno(
+    headers: &HeaderMap,
+    state: AppState,
+) -> Result<Value, (StatusCode, Json<Value>)> {
+
+    let mac_address = headers
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok())
+        .ok_or_else(|| json_error(StatusCode::FORBIDDEN, "Prohibido"))?;
+
+    let info = get_info_by_mac_address(&state, mac_address)
+        .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?;
+
+    if info.id_cajero.is_none() {
+        return Err(json_error(StatusCode::BAD_REQUEST, "No hay cajero en turno"));
+    }
+
+    let actor = info.actor();
+
+    // el cierre en Yappy deja el resumen del turno a nombre del cajero y termina su sesion
+    guardar_datos_caja(
+        state,
+        info.api_key,
+        info.secret_key,
+        info.token_autorizacion,
+        info.id_caja,
+        info.nombre_caja,
+        actor,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::Cajero;
+    use crate::db::repos::memoria::{Memoria, prueba};
+    use crate::db::types::enums::CajasEstadoEnum;
+    use crate::utils::credenciales::hashear_secreto;
+    use axum::http::HeaderValue;
+    use std::sync::LazyLock;
+
+    // argon2 es lento sin optimizar; el hash del PIN se calcula una vez
+    static PIN_HASH: LazyLock<String> = LazyLock::new(|| hashear_secreto("1234").unwrap());
+
+    fn memoria() -> Memoria {
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Cerrado, CajasTipoEnum::Cajero);
+        memoria.cajeros.push(Cajero {
+            id: 7,
+            id_grupo: 1,
+            nombre: "Ana".to_string(),
+            pin_hash: PIN_HASH.clone(),
+            activo: true,
+        });
+        memoria
+    }
+
+    async fn login(memoria: Memoria, pin: &str) -> StatusCode {
+        let (state, _) = prueba::estado(memoria);
+        let mut headers = HeaderMap::new();
+        headers.insert("mac-address", HeaderValue::from_static(prueba::MAC));
+        let payload = LoginCajero { id_cajero: 7, pin: pin.to_string() };
+
+        match abrir_turno(headers, &state, payload).await {
+            Ok(_) => StatusCode::OK,
+            Err((status, _)) => status,
+        }
+    }
+
+    #[tokio::test]
+    async fn el_cajero_debe_ser_del_grupo_y_tener_el_pin() {
+        assert_eq!(login(memoria(), "0000").await, StatusCode::UNAUTHORIZED);
+
+        let mut de_otro_grupo = memoria();
+        de_otro_grupo.cajeros[0].id_grupo = 2;
+        assert_eq!(login(de_otro_grupo, "1234").await, StatusCode::UNAUTHORIZED);
+
+        let mut inactivo = memoria();
+        inactivo.cajeros[0].activo = false;
+        assert_eq!(login(inactivo, "1234").await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn un_cajero_no_abre_turno_en_dos_cajas() {
+        let mut memoria = memoria();
+        let mut otra_caja = memoria.cajas[0].clone();
+        otra_caja.id = 2;
+        otra_caja.id_cajero_actual = Some(7);
+        memoria.cajas.push(otra_caja);
+
+        assert_eq!(login(memoria, "1234").await, StatusCode::CONFLICT);
+    }
+}
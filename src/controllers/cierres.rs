@@ -0,0 +1,182 @@
+use crate::AppState;
+use crate::controllers::structs::v1::{ApiError, ApiResult, ErrorApi, Respuesta};
+use crate::db::models::AlcanceCierre;
+use crate::db::types::enums::{ActorEnum, RolUsuarioEnum};
+use crate::middlewares::usuarios::UsuarioSesion;
+use crate::utils::auditoria::auditar_usuario;
+use crate::utils::cajas_utils::{ResultadoCierre, cerrar_cajas, forzar_cierre};
+use crate::utils::utils::json_error;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+// Cierres bajo demanda; solo existen en /v1, no tienen ruta obsoleta.
+
+/// Sin `id_grupo` ni `id_caja` se cierran todas las cajas visibles para el usuario.
+#[derive(Deserialize, Default, ToSchema)]
+pub struct SolicitudCierre {
+    pub id_grupo: Option<i32>,
+    pub id_caja: Option<i32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CorridaCierre {
+    pub exitosos: usize,
+    pub fallidos: usize,
+    pub resultados: Vec<ResultadoCierre>,
+}
+
+fn grupo_de_caja(state: &AppState, id_caja: i32) -> Result<i32, (StatusCode, Json<Value>)> {
+    state
+        .repos
+        .cajas
+        .buscar(id_caja)
+        .map(|caja| caja.id_grupo)
+        .map_err(|_| json_error(StatusCode::NOT_FOUND, "Caja no encontrada"))
+}
+
+async fn correr_cierre(
+    state: &AppState,
+    alcance: AlcanceCierre,
+) -> Result<CorridaCierre, (StatusCode, Json<Value>)> {
+    let resultados = cerrar_cajas(state, alcance, ActorEnum::Admin, "cierre-admin")
+        .await
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+    let exitosos = resultados.iter().filter(|r| r.exito).count();
+
+    Ok(CorridaCierre {
+        exitosos,
+        fallidos: resultados.len() - exitosos,
+        resultados,
+    })
+}
+
+#[utoipa::path(post, path = "/v1/admin/cierres", tag = "v1",
+    security(("jwt" = [])),
+    request_body = SolicitudCierre,
+    responses(
+        (status = 200, description = "Resultado del cierre de cada caja abierta o con error dentro del alcance", body = Respuesta<CorridaCierre>),
+        (status = 403, description = "Rol o grupo fuera de su alcance", body = Respuesta<Value>),
+        (status = 404, description = "Caja no encontrada", body = Respuesta<Value>),
+    ))]
+pub async fn correr_cierres(
+    sesion: UsuarioSesion,
+    State(state): State<AppState>,
+    payload: Option<Json<SolicitudCierre>>,
+) -> ApiResult<CorridaCierre> {
+    sesion.exigir_rol(&[RolUsuarioEnum::GrupoAdmin])?;
+
+    let Json(payload) = payload.unwrap_or_default();
+
+    let alcance = match (payload.id_caja, payload.id_grupo) {
+        (Some(id_caja), _) => {
+            let id_grupo = grupo_de_caja(&state, id_caja)?;
+            sesion.exigir_grupo(id_grupo)?;
+            if payload.id_grupo.is_some_and(|g| g != id_grupo) {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "solicitud_invalida",
+                    "La caja no pertenece al grupo indicado",
+                ));
+            }
+            AlcanceCierre::Caja(id_caja)
+        }
+        (None, Some(id_grupo)) => {
+            sesion.exigir_grupo(id_grupo)?;
+            AlcanceCierre::Grupos(vec![id_grupo])
+        }
+        (None, None) => match sesion.grupos_visibles() {
+            Some(grupos) => AlcanceCierre::Grupos(grupos.to_vec()),
+            None => AlcanceCierre::Todas,
+        },
+    };
+
+    let corrida = correr_cierre(&state, alcance.clone()).await?;
+
+    auditar_usuario(
+        &state,
+        Some((sesion.id, sesion.rol)),
+        "cierre-solicitado",
+        payload.id_caja,
+        json!({
+            "alcance": alcance,
+            "exitosos": corrida.exitosos,
+            "fallidos": corrida.fallidos,
+        }),
+        StatusCode::OK,
+    );
+
+    Ok(Respuesta::ok(corrida))
+}
+
+#[utoipa::path(post, path = "/v1/admin/cajas/{id}/cierre", tag = "v1",
+    security(("jwt" = [])),
+    params(("id" = i32, Path, description = "Id de la caja")),
+    responses(
+        (status = 200, description = "Caja cerrada en Yappy, o devuelta a cerrada si la apertura quedó a medias", body = Respuesta<ResultadoCierre>),
+        (status = 403, description = "Rol o grupo fuera de su alcance", body = Respuesta<Value>),
+        (status = 404, description = "Caja no encontrada", body = Respuesta<Value>),
+        (status = 409, description = "La caja está cerrada o deshabilitada", body = Respuesta<Value>),
+        (status = 502, description = "Yappy rechazó el cierre", body = Respuesta<Value>),
+    ))]
+pub async fn cerrar_caja_admin(
+    sesion: UsuarioSesion,
+    State(state): State<AppState>,
+    Path(id_caja): Path<i32>,
+) -> ApiResult<ResultadoCierre> {
+    sesion.exigir_rol(&[RolUsuarioEnum::GrupoAdmin, RolUsuarioEnum::Soporte])?;
+    sesion.exigir_grupo(grupo_de_caja(&state, id_caja)?)?;
+
+    let resultado = forzar_cierre(
+        &state,
+        id_caja,
+        ActorEnum::Admin,
+        "cierre-admin",
+        format!("Cierre forzado por el usuario {}", sesion.id),
+    )
+    .await
+    .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))
+    .and_then(|resultado| {
+        resultado
+            .ok_or_else(|| json_error(StatusCode::CONFLICT, "La caja está cerrada o deshabilitada"))
+    });
+
+    let status = match &resultado {
+        Ok(resultado) if resultado.exito => StatusCode::OK,
+        Ok(_) => StatusCode::BAD_GATEWAY,
+        Err((status, _)) => *status,
+    };
+    auditar_usuario(
+        &state,
+        Some((sesion.id, sesion.rol)),
+        "cierre-forzado",
+        Some(id_caja),
+        json!({
+            "codigo_yappy": resultado.as_ref().ok().and_then(|r| r.codigo_yappy.clone()),
+        }),
+        status,
+    );
+
+    let resultado = resultado?;
+
+    if !resultado.exito {
+        return Err(ApiError {
+            status: StatusCode::BAD_GATEWAY,
+            error: ErrorApi {
+                code: "error_yappy".to_string(),
+                message: "Yappy rechazó el cierre".to_string(),
+                detalles: Some(json!({
+                    "codigo_yappy": resultado.codigo_yappy,
+                    "respuesta": resultado.respuesta,
+                })),
+            },
+        });
+    }
+
+    Ok(Respuesta::ok(resultado))
+}
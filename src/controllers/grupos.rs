@@ -1,12 +1,14 @@
-use axum::{Json, http::StatusCode, response::IntoResponse, extract::{State}};
+use axum::{Json, http::StatusCode, extract::{State}};
 
-use crate::db::models::{Caja, Grupo};
-use crate::db::types::enums::CajasEstadoEnum;
+use crate::db::models::{Caja, ErrorResponse, Grupo, Kiosko};
+use crate::db::types::enums::{CajasEstadoEnum, CajasTipoEnum};
 use crate::AppState;
-use diesel::prelude::*;
+use crate::middlewares::usuarios::UsuarioSesion;
 use serde::Serialize;
+use utoipa::ToSchema;
+use chrono::NaiveDateTime;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct GrupoConCajas {
     pub id: i32,
     pub id_yappy: String,
@@ -16,40 +18,56 @@ pub struct GrupoConCajas {
     pub cajas: Vec<CajaConKiosko>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CajaConKiosko {
     pub id: i32,
     pub nombre_caja: String,
-    pub tipo: String,
+    pub tipo: CajasTipoEnum,
     //pub token_autorizacion: Option<String>,
     pub estado: CajasEstadoEnum,
-    //pub kiosko: Option<Kiosko>,
+    pub kiosko: Option<KioskoEstado>,
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct KioskoEstado {
+    pub id: i32,
+    pub nombre: String,
+    pub en_linea: bool,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub ultima_conexion: Option<NaiveDateTime>,
+    pub version_app: Option<String>,
+}
+
+#[utoipa::path(get, path = "/grupos", tag = "admin",
+    security(("jwt" = [])),
+    responses(
+        (status = 200, description = "Grupos visibles para el usuario con sus cajas y kioskos", body = Vec<GrupoConCajas>),
+        (status = 401, description = "Sesión inválida", body = ErrorResponse),
+    ))]
 pub async fn get_grupos(
+    sesion: UsuarioSesion,
     State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    use crate::schema::{cajas::dsl as cajas_dsl, grupos::dsl::*};
-
-    let mut conn = state.db_pool.get().unwrap();
-
-    // 1. Obtener todos los grupos
-    let all_grupos: Vec<Grupo> = grupos
-        .select(Grupo::as_select())
-        .load(&mut conn)
+) -> Result<Json<Vec<GrupoConCajas>>, StatusCode> {
+    // 1. Obtener los grupos que el usuario puede ver
+    let all_grupos: Vec<Grupo> = state
+        .repos
+        .grupos
+        .listar(sesion.grupos_visibles())
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // 2. Obtener todas las cajas
-    let all_cajas: Vec<Caja> = cajas_dsl::cajas
-        .select(Caja::as_select())
-        .load(&mut conn)
+    let all_cajas: Vec<Caja> = state
+        .repos
+        .cajas
+        .listar()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // 3. Obtener todos los kioskos
-    // let all_kioskos: Vec<Kiosko> = kioskos_dsl::kioskos
-    //     .select(Kiosko::as_select())
-    //     .load(&mut conn)
-    //     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let all_kioskos: Vec<Kiosko> = state
+        .repos
+        .kioskos
+        .listar()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // 4. Composición
     let grupos_con_cajas: Vec<GrupoConCajas> = all_grupos
@@ -59,11 +77,16 @@ pub async fn get_grupos(
                 .iter()
                 .filter(|c| c.id_grupo == g.id)
                 .map(|c| {
-                    // let kiosko = if c.tipo == "kiosko" {
-                    //     all_kioskos.iter().find(|k| k.id_caja == c.id).cloned()
-                    // } else {
-                    //     None
-                    // };
+                    let kiosko = all_kioskos
+                        .iter()
+                        .find(|k| k.id_caja == c.id)
+                        .map(|k| KioskoEstado {
+                            id: k.id,
+                            nombre: k.nombre.clone(),
+                            en_linea: k.en_linea,
+                            ultima_conexion: k.ultima_conexion,
+                            version_app: k.version_app.clone(),
+                        });
 
                     CajaConKiosko {
                         id: c.id,
@@ -71,7 +94,7 @@ pub async fn get_grupos(
                         tipo: c.tipo.clone(),
                         //token_autorizacion: c.token_autorizacion.clone(),
                         estado: c.estado.clone(),
-                        //kiosko,
+                        kiosko,
                     }
                 })
                 .collect();
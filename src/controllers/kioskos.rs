@@ -0,0 +1,100 @@
+use crate::AppState;
+use crate::controllers::structs::v1::Latido;
+use crate::db::models::ErrorResponse;
+use crate::middlewares::limites::ip_cliente;
+use crate::utils::tiempo;
+use crate::utils::utils::{get_info_by_mac_address, json_error};
+use axum::{
+    Json,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use utoipa::ToSchema;
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct Heartbeat {
+    pub version_app: Option<String>,
+}
+
+#[utoipa::path(post, path = "/heartbeat", tag = "kiosko",
+    security(("mac_address" = [])),
+    request_body(content = Option<Heartbeat>, description = "Versión de la app del kiosko"),
+    responses(
+        (status = 200, description = "Latido registrado", body = Value),
+        (status = 403, description = "Kiosko sin acceso", body = ErrorResponse),
+    ))]
+pub async fn heartbeat(
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    payload: Option<Json<Heartbeat>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let Json(payload) = payload.unwrap_or_default();
+
+    let latido = registrar_latido(&headers, addr, &state, payload)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "data": latido
+    })))
+}
+
+/// Marca el kiosko en línea con su versión e IP.
+pub fn registrar_latido(
+    headers: &HeaderMap,
+    addr: SocketAddr,
+    state: &AppState,
+    payload: Heartbeat,
+) -> Result<Latido, (StatusCode, Json<Value>)> {
+    let mac_address = headers
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok())
+        .ok_or_else(|| json_error(StatusCode::FORBIDDEN, "Prohibido"))?;
+
+    let info = get_info_by_mac_address(state, mac_address)
+        .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?;
+
+    let ip = ip_cliente(headers, addr).to_string();
+
+    let ahora = tiempo::ahora();
+
+    state
+        .repos
+        .kioskos
+        .registrar_latido(info.id_kiosko, ip, payload.version_app, ahora)
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    Ok(Latido {
+        kiosko: info.nombre,
+        estado_caja: info.estado,
+        ultima_conexion: Some(tiempo::rfc3339(&ahora)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repos::memoria::prueba;
+    use crate::db::types::enums::{CajasEstadoEnum, CajasTipoEnum};
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn un_latido_sin_version_conserva_la_conocida() {
+        let (state, repos) = prueba::estado(prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko));
+        let mut headers = HeaderMap::new();
+        headers.insert("mac-address", HeaderValue::from_static(prueba::MAC));
+        let addr: SocketAddr = "10.0.0.5:4000".parse().unwrap();
+
+        let con_version = Heartbeat { version_app: Some("2.1.0".to_string()) };
+        registrar_latido(&headers, addr, &state, con_version).unwrap();
+        registrar_latido(&headers, addr, &state, Heartbeat::default()).unwrap();
+
+        let kiosko = repos.datos().kioskos[0].clone();
+        assert_eq!(kiosko.version_app.as_deref(), Some("2.1.0"));
+        assert_eq!(kiosko.ip.as_deref(), Some("10.0.0.5"));
+        assert!(kiosko.en_linea && kiosko.ultima_conexion.is_some());
+    }
+}
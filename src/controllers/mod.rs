@@ -1,3 +1,12 @@
 pub mod yappy;
 pub mod grupos;
-pub mod structs;
\ No newline at end of file
+pub mod auditoria;
+pub mod kioskos;
+pub mod cajeros;
+pub mod reembolsos;
+pub mod recibos;
+pub mod usuarios;
+pub mod cierres;
+pub mod openapi;
+pub mod v1;
+pub mod structs;
@@ -0,0 +1,212 @@
+use axum::Json;
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+};
+
+use crate::controllers::{auditoria, cajeros, cierres, grupos, kioskos, recibos, reembolsos, usuarios, v1, yappy};
+use crate::db::models::ErrorResponse;
+
+/// Registra los dos esquemas de autenticación: la MAC del kiosko y el JWT de los usuarios.
+struct Seguridad;
+
+impl Modify for Seguridad {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "mac_address",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "mac-address",
+                "MAC del kiosko registrada en la tabla kioskos",
+            ))),
+        );
+        components.add_security_scheme(
+            "jwt",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "MACY",
+        description = "Manejador Automático de Cajas Yappy. Las rutas sin `/v1` son alias obsoletos.",
+    ),
+    paths(
+        yappy::hello_world,
+        yappy::abrir_caja,
+        yappy::generar_qr,
+        yappy::qr_transaccion,
+        yappy::cerrar_caja,
+        yappy::handle_transaccion,
+        yappy::retornar_transaccion,
+        yappy::stream_transaccion,
+        yappy::cancelar_transaccion,
+        reembolsos::crear_reembolso,
+        recibos::reimprimir_recibo,
+        cajeros::login_cajero,
+        cajeros::logout_cajero,
+        kioskos::heartbeat,
+        grupos::get_grupos,
+        auditoria::get_auditoria,
+        usuarios::login_usuario,
+        usuarios::perfil_usuario,
+        usuarios::listar_usuarios,
+        usuarios::crear_usuario,
+        v1::abrir_sesion,
+        v1::cerrar_sesion,
+        v1::crear_cobro,
+        v1::estado_cobro,
+        v1::stream_cobro,
+        v1::qr_cobro,
+        v1::cancelar_cobro,
+        v1::reembolsar_cobro,
+        v1::reembolsar,
+        v1::recibo,
+        v1::entrar_cajero,
+        v1::salir_cajero,
+        v1::latido,
+        v1::grupos,
+        v1::auditoria,
+        v1::admin_login,
+        v1::admin_perfil,
+        v1::admin_usuarios,
+        v1::admin_crear_usuario,
+        cierres::correr_cierres,
+        cierres::cerrar_caja_admin,
+    ),
+    components(schemas(ErrorResponse)),
+    modifiers(&Seguridad),
+    tags(
+        (name = "v1", description = "API versionada con el sobre `success`, `data`, `error`, `request_id`"),
+        (name = "kiosko", description = "Rutas que llaman los kioskos, autenticadas con `mac-address`"),
+        (name = "cajeros", description = "Turnos de cajeros en cajas atendidas"),
+        (name = "admin", description = "Rutas administrativas, autenticadas con JWT"),
+    )
+)]
+pub struct ApiDoc;
+
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+
+    use super::*;
+
+    const METODOS: [&str; 3] = ["get", "post", "delete"];
+
+    // `.metodo("argumento` -> argumento y el resto del texto despues del parentesis que cierra
+    fn llamada<'a>(texto: &'a str, metodo: &str) -> Option<(&'a str, &'a str, &'a str)> {
+        let inicio = texto.find(&format!(".{}(", metodo))? + metodo.len() + 2;
+        let mut nivel = 1;
+        let fin = texto[inicio..].char_indices().find_map(|(i, c)| {
+            match c {
+                '(' => nivel += 1,
+                ')' => nivel -= 1,
+                _ => {}
+            }
+            (nivel == 0).then_some(inicio + i)
+        })?;
+        Some((&texto[..inicio - metodo.len() - 2], &texto[inicio..fin], &texto[fin + 1..]))
+    }
+
+    /// Rutas (metodo, path) que arma `start_axum`, siguiendo los `nest` y `merge` de cada router.
+    fn rutas_del_router() -> BTreeSet<(String, String)> {
+        let fuente = include_str!("../start_axum.rs");
+        let cuerpo = fuente.split("pub async fn start_axum").nth(1).unwrap();
+        let mut routers: HashMap<String, Vec<(String, String)>> = HashMap::new();
+
+        for sentencia in cuerpo.split(';') {
+            let sentencia = sentencia.trim();
+            let Some((nombre, valor)) = sentencia
+                .split_once("let ")
+                .and_then(|(_, resto)| resto.split_once(" = "))
+            else {
+                continue;
+            };
+
+            let base = valor.split(['.', '\n']).next().unwrap_or_default().trim();
+            let mut rutas = routers.get(base).cloned().unwrap_or_default();
+            let mut resto = valor;
+
+            loop {
+                let siguiente = ["route", "nest", "merge"]
+                    .into_iter()
+                    .filter_map(|metodo| llamada(resto, metodo).map(|l| (l.0.len(), metodo, l)))
+                    .min_by_key(|(posicion, ..)| *posicion);
+                let Some((_, metodo, (_, argumentos, despues))) = siguiente else {
+                    break;
+                };
+
+                match metodo {
+                    "route" => {
+                        let (path, handlers) = argumentos.split_once(',').unwrap();
+                        for verbo in METODOS {
+                            let usa_verbo = handlers.trim_start().starts_with(&format!("{}(", verbo))
+                                || handlers.contains(&format!(".{}(", verbo));
+                            if usa_verbo {
+                                rutas.push((verbo.to_string(), path.trim().trim_matches('"').to_string()));
+                            }
+                        }
+                    }
+                    "nest" => {
+                        let (prefijo, router) = argumentos.split_once(',').unwrap();
+                        let prefijo = prefijo.trim().trim_matches('"');
+                        for (verbo, path) in routers.get(router.trim()).cloned().unwrap_or_default() {
+                            rutas.push((verbo, format!("{}{}", prefijo, path)));
+                        }
+                    }
+                    _ => rutas.extend(routers.get(argumentos.trim()).cloned().unwrap_or_default()),
+                }
+
+                resto = despues;
+            }
+
+            routers.insert(nombre.trim().to_string(), rutas);
+        }
+
+        routers.remove("app").unwrap().into_iter().collect()
+    }
+
+    fn rutas_documentadas() -> BTreeSet<(String, String)> {
+        let mut rutas = BTreeSet::new();
+
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            let operaciones = [("get", &item.get), ("post", &item.post), ("delete", &item.delete)];
+            for (verbo, operacion) in operaciones {
+                if operacion.is_some() {
+                    rutas.insert((verbo.to_string(), path.clone()));
+                }
+            }
+        }
+
+        rutas
+    }
+
+    #[test]
+    fn toda_ruta_del_router_esta_documentada() {
+        let mut router = rutas_del_router();
+        // la especificacion misma no se documenta
+        router.remove(&("get".to_string(), "/openapi.json".to_string()));
+
+        // si el recorrido del router deja de encontrar rutas la prueba no debe pasar en vano
+        assert!(router.len() > 30, "solo se encontraron {} rutas", router.len());
+
+        let documentadas = rutas_documentadas();
+        let faltan: Vec<_> = router.difference(&documentadas).collect();
+        let sobran: Vec<_> = documentadas.difference(&router).collect();
+
+        assert!(faltan.is_empty(), "rutas sin documentar en ApiDoc: {:?}", faltan);
+        assert!(sobran.is_empty(), "rutas documentadas que el router no tiene: {:?}", sobran);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::AppState;
+use crate::db::models::ErrorResponse;
+use crate::utils::recibos::{Recibo, recibo_de_transaccion};
+use crate::utils::utils::{get_info_by_mac_address, json_error};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Deserialize, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatoRecibo {
+    #[default]
+    Json,
+    Texto,
+    Html,
+    Escpos,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OpcionesRecibo {
+    #[serde(default)]
+    pub formato: FormatoRecibo,
+}
+
+#[utoipa::path(get, path = "/recibos/{id}", tag = "kiosko",
+    security(("mac_address" = [])),
+    params(("id" = i32, Path, description = "Id de la transacción"), OpcionesRecibo),
+    responses(
+        (status = 200, description = "Recibo en el formato pedido", content(("application/json"), ("text/plain"), ("text/html"), ("application/octet-stream"))),
+        (status = 404, description = "Transacción no encontrada", body = ErrorResponse),
+    ))]
+pub async fn reimprimir_recibo(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(opciones): Query<OpcionesRecibo>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let recibo = buscar_recibo(&headers, &state, id)?;
+
+    Ok(match opciones.formato {
+        FormatoRecibo::Json => Json(json!({
+            "success": true,
+            "data": recibo.impreso()
+        }))
+        .into_response(),
+        FormatoRecibo::Texto => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            recibo.texto,
+        )
+            .into_response(),
+        FormatoRecibo::Html => (
+            [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+            recibo.html(),
+        )
+            .into_response(),
+        FormatoRecibo::Escpos => (
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            recibo.escpos(),
+        )
+            .into_response(),
+    })
+}
+
+/// Reimpresión de una venta completada o reembolsada del mismo grupo que el kiosko.
+pub fn buscar_recibo(
+    headers: &HeaderMap,
+    state: &AppState,
+    id: i32,
+) -> Result<Recibo, (StatusCode, Json<Value>)> {
+    let mac_address = headers
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok())
+        .ok_or_else(|| json_error(StatusCode::FORBIDDEN, "Prohibido"))?;
+
+    let info = get_info_by_mac_address(state, mac_address)
+        .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?;
+
+    let transaccion = state
+        .repos
+        .transacciones
+        .buscar_por_id(id)
+        .ok()
+        .filter(|t| matches!(t.estado.as_str(), "COMPLETED" | "REFUNDED"))
+        .filter(|t| {
+            state
+                .repos
+                .cajas
+                .buscar(t.id_caja)
+                .is_ok_and(|caja| caja.id_grupo == info.id_grupo)
+        })
+        .ok_or_else(|| json_error(StatusCode::NOT_FOUND, "Transacción no encontrada"))?;
+
+    recibo_de_transaccion(&state.repos, &transaccion, true)
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))
+}
@@ -0,0 +1,234 @@
+use crate::AppState;
+use crate::controllers::structs::yappy::RespuestaYappy;
+use crate::db::models::{ErrorResponse, Reembolso, Transaccion};
+use crate::db::repos::ReferenciaVenta;
+use crate::db::types::enums::CajasEstadoEnum;
+use crate::utils::transacciones_utils::{decimal, reembolsar_en_yappy};
+use crate::utils::utils::{get_info_by_mac_address, json_error};
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use bigdecimal::BigDecimal;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+#[derive(Deserialize, ToSchema)]
+pub struct SolicitudReembolso {
+    pub id: Option<i32>,
+    pub id_transaccion: Option<String>,
+    pub id_orden: Option<String>,
+    pub monto: Option<f64>,
+    pub motivo: String,
+}
+
+/// Reembolso ya enviado a Yappy y registrado, con la venta como quedó después.
+pub struct ReembolsoAplicado {
+    pub transaccion: Transaccion,
+    pub reembolso: Reembolso,
+    pub saldo_reembolsable: BigDecimal,
+    pub yappy: Value,
+}
+
+#[utoipa::path(post, path = "/reembolsos", tag = "kiosko",
+    security(("mac_address" = [])),
+    request_body = SolicitudReembolso,
+    responses(
+        (status = 200, description = "Reembolso aplicado en Yappy", body = Value),
+        (status = 400, description = "Solicitud inválida", body = ErrorResponse),
+        (status = 409, description = "La caja no está abierta", body = ErrorResponse),
+        (status = 422, description = "El monto excede el saldo reembolsable", body = ErrorResponse),
+        (status = 502, description = "Yappy rechazó el reembolso", body = ErrorResponse),
+    ))]
+pub async fn crear_reembolso(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(payload): Json<SolicitudReembolso>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let aplicado = reembolsar_venta(&headers, &state, payload).await?;
+
+    Ok(Json(json!({
+        "success": true,
+        "data": {
+            "reembolso": aplicado.reembolso,
+            "saldo_reembolsable": aplicado.saldo_reembolsable,
+            "yappy": aplicado.yappy,
+        }
+    })))
+}
+
+/// Reembolsa en Yappy una venta del grupo del kiosko, total o parcial.
+pub async fn reembolsar_venta(
+    headers: &HeaderMap,
+    state: &AppState,
+    payload: SolicitudReembolso,
+) -> Result<ReembolsoAplicado, (StatusCode, Json<Value>)> {
+
+    let mac_address = headers
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok())
+        .ok_or_else(|| json_error(StatusCode::FORBIDDEN, "Prohibido"))?;
+
+    let info = get_info_by_mac_address(state, mac_address)
+        .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?;
+
+    if payload.motivo.trim().is_empty() {
+        return Err(json_error(StatusCode::BAD_REQUEST, "El motivo es obligatorio"));
+    }
+
+    if info.estado != CajasEstadoEnum::Abierto {
+        return Err(json_error(
+            StatusCode::CONFLICT,
+            "La caja debe estar abierta para reembolsar",
+        ));
+    }
+
+    let referencia = match (&payload.id, &payload.id_transaccion, &payload.id_orden) {
+        (Some(id), _, _) => ReferenciaVenta::Id(*id),
+        (None, Some(id_transaccion), _) => ReferenciaVenta::Yappy(id_transaccion.clone()),
+        (None, None, Some(id_orden)) => ReferenciaVenta::Orden(id_orden.clone()),
+        (None, None, None) => {
+            return Err(json_error(
+                StatusCode::BAD_REQUEST,
+                "Debe indicar id, id_transaccion o id_orden",
+            ));
+        }
+    };
+
+    let transacciones = &state.repos.transacciones;
+
+    // solo ventas de cajas del mismo grupo
+    let transaccion = transacciones
+        .de_grupo(info.id_grupo, &referencia)
+        .map_err(|_| json_error(StatusCode::NOT_FOUND, "Transacción no encontrada"))?;
+
+    if transaccion.estado != "COMPLETED" {
+        return Err(json_error(
+            StatusCode::CONFLICT,
+            format!("No se puede reembolsar una transacción {}", transaccion.estado),
+        ));
+    }
+
+    // el monto se aparta antes de ir a Yappy: un segundo reembolso simultaneo ya no lo ve
+    let reserva = transacciones.reservar_reembolso(
+        transaccion.id,
+        payload.monto.map(decimal),
+        payload.motivo.clone(),
+        info.actor(),
+        info.id_cajero,
+    )?;
+
+    // por el saldo completo no se envia monto, igual que el reembolso total
+    let parcial = (reserva.monto != transaccion.total).then_some(&reserva.monto);
+
+    let response_json = reembolsar_en_yappy(
+        info.api_key.clone(),
+        info.secret_key.clone(),
+        info.token_autorizacion.clone(),
+        &transaccion.id_transaccion_yappy,
+        parcial,
+        &payload.motivo,
+    )
+    .await;
+
+    // si no hubo respuesta de Yappy el intento queda registrado como fallido y el monto se libera
+    let (response_json, error) = match response_json {
+        Ok(json) => (json, None),
+        Err((status, err_json)) => (err_json.0.clone(), Some((status, err_json))),
+    };
+
+    let reembolso = transacciones
+        .completar_reembolso(&reserva, &response_json)
+        .map_err(|err| {
+            tracing::error!("no se pudo completar el reembolso {}: {}", reserva.id, err);
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, err)
+        })?;
+
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    // un rechazo de Yappy se informa igual que los demas errores de Yappy
+    if !reembolso.exitoso {
+        let yappy = RespuestaYappy::<Value>::de(&response_json);
+        let detalle = yappy
+            .status
+            .as_ref()
+            .and_then(|status| status.description.clone())
+            .unwrap_or_else(|| "sin detalle".to_string());
+
+        let (status, Json(mut cuerpo)) = json_error(
+            StatusCode::BAD_GATEWAY,
+            format!("Yappy rechazó el reembolso: {}", detalle),
+        );
+        cuerpo["codigo_yappy"] = json!(yappy.codigo());
+        return Err((status, Json(cuerpo)));
+    }
+
+    // el reembolso pudo dejar la venta en REFUNDED
+    let transaccion = transacciones
+        .buscar_por_id(transaccion.id)
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    let saldo = transacciones
+        .saldo_reembolsable(&transaccion)
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    Ok(ReembolsoAplicado {
+        transaccion,
+        reembolso,
+        saldo_reembolsable: saldo,
+        yappy: response_json,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repos::memoria::prueba;
+    use crate::db::types::enums::CajasTipoEnum;
+    use crate::utils::tiempo;
+    use axum::http::HeaderValue;
+
+    fn solicitud(valor: Value) -> SolicitudReembolso {
+        serde_json::from_value(valor).unwrap()
+    }
+
+    async fn reembolsar(state: &AppState, valor: Value) -> StatusCode {
+        let mut headers = HeaderMap::new();
+        headers.insert("mac-address", HeaderValue::from_static(prueba::MAC));
+
+        match reembolsar_venta(&headers, state, solicitud(valor)).await {
+            Ok(_) => StatusCode::OK,
+            Err((status, _)) => status,
+        }
+    }
+
+    #[tokio::test]
+    async fn solo_se_reembolsan_ventas_completadas_del_grupo() {
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        prueba::transaccion(&mut memoria, "pendiente", "PENDING", tiempo::ahora());
+        memoria.transacciones[0].id_orden = Some("orden-1".to_string());
+        prueba::transaccion(&mut memoria, "otro-grupo", "COMPLETED", tiempo::ahora());
+        let mut otra_caja = memoria.cajas[0].clone();
+        otra_caja.id = 2;
+        otra_caja.id_grupo = 2;
+        memoria.cajas.push(otra_caja);
+        memoria.transacciones[1].id_caja = 2;
+        let (state, repos) = prueba::estado(memoria);
+
+        let pendiente = json!({ "id_transaccion": "pendiente", "motivo": "cliente" });
+        assert_eq!(reembolsar(&state, pendiente).await, StatusCode::CONFLICT);
+
+        // por orden solo cuentan las ventas completadas
+        let por_orden = json!({ "id_orden": "orden-1", "motivo": "cliente" });
+        assert_eq!(reembolsar(&state, por_orden).await, StatusCode::NOT_FOUND);
+
+        let ajena = json!({ "id": 2, "motivo": "cliente" });
+        assert_eq!(reembolsar(&state, ajena).await, StatusCode::NOT_FOUND);
+
+        assert!(repos.datos().reembolsos.is_empty());
+    }
+}
@@ -1 +1,2 @@
-pub mod yappy;
\ No newline at end of file
+pub mod yappy;
+pub mod v1;
\ No newline at end of file
@@ -0,0 +1,234 @@
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::db::types::enums::CajasEstadoEnum;
+use crate::middlewares::correlacion;
+use crate::utils::qr::FormatoQR;
+use crate::utils::recibos::ReciboImpreso;
+
+/// Sobre común de todas las respuestas de `/v1`.
+#[derive(Serialize, ToSchema)]
+pub struct Respuesta<T> {
+    pub success: bool,
+    pub data: Option<T>,
+    pub error: Option<ErrorApi>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorApi {
+    pub code: String,
+    pub message: String,
+    // datos extra del error, p. ej. las discrepancias de montos
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detalles: Option<Value>,
+}
+
+impl<T: Serialize> Respuesta<T> {
+    pub fn ok(data: T) -> Json<Self> {
+        Json(Respuesta {
+            success: true,
+            data: Some(data),
+            error: None,
+            request_id: correlacion::actual(),
+        })
+    }
+}
+
+/// Error de `/v1`: el status HTTP más el `error` del sobre.
+pub struct ApiError {
+    pub status: StatusCode,
+    pub error: ErrorApi,
+}
+
+pub type ApiResult<T> = Result<Json<Respuesta<T>>, ApiError>;
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            error: ErrorApi {
+                code: code.to_string(),
+                message: message.into(),
+                detalles: None,
+            },
+        }
+    }
+}
+
+fn codigo_de_status(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "solicitud_invalida",
+        StatusCode::UNAUTHORIZED => "no_autenticado",
+        StatusCode::FORBIDDEN => "prohibido",
+        StatusCode::NOT_FOUND => "no_encontrado",
+        StatusCode::CONFLICT => "conflicto",
+        StatusCode::PAYLOAD_TOO_LARGE => "cuerpo_muy_grande",
+        StatusCode::UNPROCESSABLE_ENTITY => "validacion",
+        StatusCode::TOO_MANY_REQUESTS => "demasiadas_solicitudes",
+        StatusCode::BAD_GATEWAY => "error_yappy",
+        _ => "error_interno",
+    }
+}
+
+/// Convierte los errores de `json_error` al sobre de `/v1`; los campos extra
+/// (fuera de `success`, `error` y `request_id`) pasan a `detalles`.
+impl From<(StatusCode, Json<Value>)> for ApiError {
+    fn from((status, Json(cuerpo)): (StatusCode, Json<Value>)) -> Self {
+        let message = cuerpo
+            .get("error")
+            .map(|e| e.as_str().map(str::to_string).unwrap_or_else(|| e.to_string()))
+            .unwrap_or_else(|| status.to_string());
+
+        let detalles = cuerpo
+            .as_object()
+            .map(|obj| {
+                obj.iter()
+                    .filter(|(k, _)| !matches!(k.as_str(), "success" | "error" | "request_id"))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect::<serde_json::Map<_, _>>()
+            })
+            .filter(|extra| !extra.is_empty())
+            .map(Value::Object);
+
+        ApiError {
+            status,
+            error: ErrorApi {
+                code: codigo_de_status(status).to_string(),
+                message,
+                detalles,
+            },
+        }
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        ApiError::new(
+            status,
+            codigo_de_status(status),
+            status.canonical_reason().unwrap_or("Error"),
+        )
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (
+            self.status,
+            Json(Respuesta::<()> {
+                success: false,
+                data: None,
+                error: Some(self.error),
+                request_id: correlacion::actual(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SesionCaja {
+    pub id_caja: i32,
+    pub caja: String,
+    pub estado: CajasEstadoEnum,
+    pub codigo_yappy: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LineaResumenCierre {
+    pub tipo: String,
+    pub monto: f64,
+    pub transacciones: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CierreCaja {
+    pub id_caja: i32,
+    pub caja: String,
+    pub estado: CajasEstadoEnum,
+    pub codigo_yappy: Option<String>,
+    pub resumen: Vec<LineaResumenCierre>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Montos {
+    pub subtotal: f64,
+    pub impuesto: f64,
+    pub propina: f64,
+    pub descuento: f64,
+    pub total: f64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImagenQRCobro {
+    pub formato: FormatoQR,
+    pub content_type: String,
+    pub base64: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Cobro {
+    pub id: i32,
+    pub id_transaccion: String,
+    pub id_orden: Option<String>,
+    pub tipo_qr: String,
+    pub estado: String,
+    pub montos: Montos,
+    pub hash_qr: Option<String>,
+    pub qr: Option<ImagenQRCobro>,
+    pub advertencias: Vec<String>,
+    pub codigo_yappy: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EstadoCobro {
+    pub id_transaccion: String,
+    pub estado: String,
+    pub referencia: Option<String>,
+    /// RFC 3339 con la hora de Panamá.
+    pub fecha: Option<String>,
+    /// `fecha` en el formato que mostraban los kioskos (`MM/DD/AAAA hh:mm:ss AM`).
+    pub fecha_texto: Option<String>,
+    pub recibo: Option<ReciboImpreso>,
+    pub codigo_yappy: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CancelacionCobro {
+    pub id_transaccion: String,
+    pub estado: String,
+    pub motivo: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReembolsoCobro {
+    pub id: i32,
+    pub id_transaccion: String,
+    pub monto: f64,
+    pub motivo: String,
+    pub estado_transaccion: String,
+    pub saldo_reembolsable: f64,
+    pub codigo_yappy: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TurnoCajero {
+    pub cajero: Option<String>,
+    pub caja: String,
+    pub abierta: bool,
+    pub codigo_yappy: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Latido {
+    pub kiosko: String,
+    pub estado_caja: CajasEstadoEnum,
+    pub ultima_conexion: Option<String>,
+}
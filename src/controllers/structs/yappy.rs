@@ -1,5 +1,13 @@
+use chrono::NaiveDateTime;
 use serde::Deserialize;
 use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::db::models::Reembolso;
+use crate::utils::qr::{ImagenQR, OpcionesQR};
+use crate::utils::recibos::ReciboImpreso;
 
 pub fn default_f64() -> f64 {
     0.0
@@ -48,13 +56,14 @@ impl AbrirCaja {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct GenerarQR {
     pub tipo_qr: String,
     pub subtotal: f64,
     pub total: f64,
-    #[serde(default = "default_f64")]
-    pub impuesto: f64,
+    // si no viene, se calcula con la configuracion de impuestos del grupo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impuesto: Option<f64>,
     #[serde(default = "default_f64")]
     pub propina: f64,
     #[serde(default = "default_f64")]
@@ -63,6 +72,26 @@ pub struct GenerarQR {
     pub id_orden: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub descripcion: Option<String>,
+    // si viene, la respuesta incluye el QR ya dibujado en base64; si no se pudo dibujar, va en `advertencias`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imagen_qr: Option<OpcionesQR>,
+    // si vienen, MACY calcula los totales y rechaza los que no cuadren
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items: Option<Vec<ItemPedido>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct ItemPedido {
+    pub sku: String,
+    pub descripcion: String,
+    pub cantidad: i32,
+    pub precio_unitario: f64,
+    #[serde(default = "default_f64")]
+    pub descuento: f64,
+    #[serde(default)]
+    pub exento_itbms: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub categoria: Option<String>,
 }
 
 impl GenerarQR {
@@ -71,7 +100,7 @@ impl GenerarQR {
             body: BodyGenerarQR {
                 charge_amount: ChargeAmount {
                     sub_total: self.subtotal,
-                    tax: self.impuesto,
+                    tax: self.impuesto.unwrap_or_default(),
                     tip: self.propina,
                     discount: self.descuento,
                     total: self.total,
@@ -104,4 +133,113 @@ pub struct ChargeAmount {
     pub tip: f64,
     pub discount: f64,
     pub total: f64,
-}
\ No newline at end of file
+}
+/// Sobre de las respuestas de Yappy: `status` con el código y `body` con los datos de la operación.
+#[derive(Deserialize, Debug)]
+pub struct RespuestaYappy<T> {
+    pub status: Option<EstadoYappy>,
+    pub body: Option<T>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EstadoYappy {
+    pub code: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl<T: DeserializeOwned> RespuestaYappy<T> {
+    /// Si el `body` no tiene la forma esperada (p. ej. en un error) se conserva al menos el `status`.
+    pub fn de(json: &Value) -> Self {
+        serde_json::from_value(json.clone()).unwrap_or_else(|_| RespuestaYappy {
+            status: json
+                .get("status")
+                .and_then(|status| serde_json::from_value(status.clone()).ok()),
+            body: None,
+        })
+    }
+
+    pub fn codigo(&self) -> Option<&str> {
+        self.status.as_ref().map(|status| status.code.as_str())
+    }
+
+    pub fn exitosa(&self) -> bool {
+        self.codigo() == Some("YP-0000")
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CuerpoCobroYappy {
+    pub transaction_id: Option<String>,
+    pub hash: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CuerpoTransaccionYappy {
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CuerpoSesionYappy {
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct CuerpoCierreYappy {
+    #[serde(default)]
+    pub summary: Vec<LineaCierreYappy>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LineaCierreYappy {
+    #[serde(rename = "type", default = "tipo_desconocido")]
+    pub tipo: String,
+    #[serde(default)]
+    pub amount: f64,
+    #[serde(default)]
+    pub transactions: i64,
+}
+
+fn tipo_desconocido() -> String {
+    "UNKNOWN".to_string()
+}
+
+/// Cobro recién creado; `/generar-qr` y `/v1/cobros` arman su respuesta a partir de esto.
+pub struct CobroCreado {
+    /// Respuesta de Yappy tal cual.
+    pub yappy: Value,
+    /// Fila en `transacciones`; `None` si Yappy no creó el cobro.
+    pub id: Option<i32>,
+    pub id_transaccion: Option<String>,
+    pub hash_qr: Option<String>,
+    pub tipo_qr: String,
+    /// Lo que se cobró, con los montos ya calculados por MACY.
+    pub cobro: GenerarQR,
+    pub qr: Option<ImagenQR>,
+    pub advertencias: Vec<String>,
+}
+
+/// Consulta (o devolución) de la transacción en curso de una caja.
+pub struct ConsultaTransaccion {
+    pub yappy: Value,
+    pub id_caja: i32,
+    pub nombre_caja: String,
+    pub id_transaccion: String,
+    /// `body.status` de Yappy, si vino.
+    pub estado: Option<String>,
+    /// La transacción, cuando terminó y la caja quedó libre.
+    pub referencia: Option<String>,
+    /// Momento de la consulta; es la fecha del pago que muestran los kioskos.
+    pub fecha: NaiveDateTime,
+    pub recibo: Option<ReciboImpreso>,
+    /// Reembolso registrado por una devolución.
+    pub reembolso: Option<Reembolso>,
+}
+
+#[derive(Serialize, ToSchema, Clone)]
+pub struct Cancelacion {
+    pub transaccion: String,
+    pub estado: String,
+    pub motivo: String,
+}
@@ -0,0 +1,335 @@
+use crate::AppState;
+use crate::db::models::{ErrorResponse, NewUsuario, Usuario};
+use crate::db::types::enums::RolUsuarioEnum;
+use crate::middlewares::usuarios::UsuarioSesion;
+use crate::utils::auditoria::auditar_usuario;
+use crate::utils::credenciales::{emitir_token, hashear_secreto, verificar_secreto};
+use crate::utils::tiempo;
+use crate::utils::utils::json_error;
+use axum::{Json, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use utoipa::ToSchema;
+
+#[derive(Deserialize, ToSchema)]
+pub struct LoginUsuario {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CrearUsuario {
+    pub nombre: String,
+    pub email: String,
+    pub password: String,
+    pub rol: RolUsuarioEnum,
+    #[serde(default)]
+    pub grupos: Vec<i32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UsuarioConGrupos {
+    #[serde(flatten)]
+    pub usuario: Usuario,
+    pub grupos: Vec<i32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SesionUsuario {
+    pub token: String,
+    /// RFC 3339 con la hora de Panamá.
+    pub expira: String,
+    pub usuario: UsuarioConGrupos,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PerfilUsuario {
+    pub id: i32,
+    pub nombre: String,
+    pub rol: RolUsuarioEnum,
+    pub grupos: Vec<i32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UsuarioCreado {
+    pub id: i32,
+}
+
+#[utoipa::path(post, path = "/admin/login", tag = "admin",
+    request_body = LoginUsuario,
+    responses(
+        (status = 200, description = "JWT y datos del usuario", body = SesionUsuario),
+        (status = 401, description = "Email o contraseña inválidos", body = ErrorResponse),
+    ))]
+pub async fn login_usuario(
+    State(state): State<AppState>,
+    Json(payload): Json<LoginUsuario>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let sesion = iniciar_sesion(&state, payload)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "data": sesion
+    })))
+}
+
+pub fn iniciar_sesion(
+    state: &AppState,
+    payload: LoginUsuario,
+) -> Result<SesionUsuario, (StatusCode, Json<Value>)> {
+    let email = payload.email.trim().to_lowercase();
+
+    let Some(usuario) = state
+        .repos
+        .usuarios
+        .activo_por_email(&email)
+        .ok()
+        .filter(|u| verificar_secreto(&payload.password, &u.password_hash))
+    else {
+        auditar_usuario(state, None, "login", None, json!({ "email": email }), StatusCode::UNAUTHORIZED);
+        return Err(json_error(StatusCode::UNAUTHORIZED, "Email o contraseña inválidos"));
+    };
+
+    let grupos = state
+        .repos
+        .usuarios
+        .grupos(usuario.id)
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    let (token, expira) = emitir_token(usuario.id)
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    auditar_usuario(
+        state,
+        Some((usuario.id, usuario.rol)),
+        "login",
+        None,
+        json!({ "email": email }),
+        StatusCode::OK,
+    );
+
+    Ok(SesionUsuario {
+        token,
+        expira: tiempo::rfc3339(&expira.naive_utc()),
+        usuario: UsuarioConGrupos { usuario, grupos },
+    })
+}
+
+#[utoipa::path(get, path = "/admin/yo", tag = "admin",
+    security(("jwt" = [])),
+    responses(
+        (status = 200, description = "Usuario de la sesión, su rol y sus grupos", body = PerfilUsuario),
+        (status = 401, description = "Sesión inválida", body = ErrorResponse),
+    ))]
+pub async fn perfil_usuario(usuario: UsuarioSesion) -> Json<Value> {
+    Json(json!({
+        "success": true,
+        "data": perfil(usuario)
+    }))
+}
+
+pub fn perfil(usuario: UsuarioSesion) -> PerfilUsuario {
+    PerfilUsuario {
+        id: usuario.id,
+        nombre: usuario.nombre,
+        rol: usuario.rol,
+        grupos: usuario.grupos,
+    }
+}
+
+#[utoipa::path(get, path = "/admin/usuarios", tag = "admin",
+    security(("jwt" = [])),
+    responses(
+        (status = 200, description = "Usuarios visibles con sus grupos", body = Vec<UsuarioConGrupos>),
+        (status = 403, description = "Rol sin permiso", body = ErrorResponse),
+    ))]
+pub async fn listar_usuarios(
+    sesion: UsuarioSesion,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let lista = usuarios_visibles(&sesion, &state)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "data": lista
+    })))
+}
+
+pub fn usuarios_visibles(
+    sesion: &UsuarioSesion,
+    state: &AppState,
+) -> Result<Vec<UsuarioConGrupos>, (StatusCode, Json<Value>)> {
+    sesion.exigir_rol(&[RolUsuarioEnum::GrupoAdmin])?;
+
+    let usuarios = &state.repos.usuarios;
+
+    // un grupo-admin solo ve a los usuarios que comparten alguno de sus grupos
+    let lista = usuarios
+        .listar(sesion.grupos_visibles())
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    lista
+        .into_iter()
+        .map(|usuario| {
+            let grupos = usuarios.grupos(usuario.id)?;
+            Ok(UsuarioConGrupos { usuario, grupos })
+        })
+        .collect::<diesel::QueryResult<Vec<_>>>()
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))
+}
+
+#[utoipa::path(post, path = "/admin/usuarios", tag = "admin",
+    security(("jwt" = [])),
+    request_body = CrearUsuario,
+    responses(
+        (status = 201, description = "Usuario creado", body = UsuarioCreado),
+        (status = 403, description = "Rol o grupo fuera de su alcance", body = ErrorResponse),
+        (status = 409, description = "Email repetido", body = ErrorResponse),
+    ))]
+pub async fn crear_usuario(
+    sesion: UsuarioSesion,
+    State(state): State<AppState>,
+    Json(payload): Json<CrearUsuario>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let creado = registrar_usuario(&sesion, &state, payload)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "success": true,
+            "data": creado
+        })),
+    ))
+}
+
+pub fn registrar_usuario(
+    sesion: &UsuarioSesion,
+    state: &AppState,
+    payload: CrearUsuario,
+) -> Result<UsuarioCreado, (StatusCode, Json<Value>)> {
+    sesion.exigir_rol(&[RolUsuarioEnum::GrupoAdmin])?;
+
+    // un grupo-admin solo crea finanzas o soporte dentro de sus propios grupos
+    if !sesion.es_superadmin() {
+        if matches!(
+            payload.rol,
+            RolUsuarioEnum::Superadmin | RolUsuarioEnum::GrupoAdmin
+        ) {
+            return Err(json_error(StatusCode::FORBIDDEN, "Rol sin permiso para esta operación"));
+        }
+        for id_grupo in &payload.grupos {
+            sesion.exigir_grupo(*id_grupo)?;
+        }
+    }
+
+    if payload.rol != RolUsuarioEnum::Superadmin && payload.grupos.is_empty() {
+        return Err(json_error(
+            StatusCode::BAD_REQUEST,
+            "El usuario debe tener al menos un grupo",
+        ));
+    }
+
+    if payload.password.len() < 8 {
+        return Err(json_error(
+            StatusCode::BAD_REQUEST,
+            "La contraseña debe tener al menos 8 caracteres",
+        ));
+    }
+
+    let password_hash = hashear_secreto(&payload.password)
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    let nuevo = NewUsuario {
+        nombre: payload.nombre.clone(),
+        email: payload.email.trim().to_lowercase(),
+        password_hash,
+        rol: payload.rol,
+    };
+
+    let id_usuario = state
+        .repos
+        .usuarios
+        .crear(nuevo, &payload.grupos)
+        .map_err(|err| match err {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => json_error(StatusCode::CONFLICT, "Ya existe un usuario con ese email"),
+            err => json_error(StatusCode::INTERNAL_SERVER_ERROR, err),
+        });
+
+    auditar_usuario(
+        state,
+        Some((sesion.id, sesion.rol)),
+        "crear-usuario",
+        None,
+        json!({
+            "id_usuario_creado": id_usuario.as_ref().ok(),
+            "email": payload.email.trim().to_lowercase(),
+            "rol_creado": payload.rol,
+            "grupos": payload.grupos,
+        }),
+        id_usuario.as_ref().map_or_else(|(status, _)| *status, |_| StatusCode::CREATED),
+    );
+
+    Ok(UsuarioCreado { id: id_usuario? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repos::memoria::{Memoria, prueba};
+
+    fn sesion(rol: RolUsuarioEnum, grupos: Vec<i32>) -> UsuarioSesion {
+        UsuarioSesion { id: 99, nombre: "Admin".to_string(), rol, grupos }
+    }
+
+    fn nuevo(email: &str, rol: RolUsuarioEnum, grupos: Vec<i32>) -> CrearUsuario {
+        CrearUsuario {
+            nombre: "Usuario".to_string(),
+            email: email.to_string(),
+            password: "contrasena-larga".to_string(),
+            rol,
+            grupos,
+        }
+    }
+
+    #[test]
+    fn un_admin_de_grupo_solo_lista_usuarios_de_sus_grupos() {
+        let (state, repos) = prueba::estado(Memoria::default());
+        let superadmin = sesion(RolUsuarioEnum::Superadmin, vec![]);
+
+        registrar_usuario(&superadmin, &state, nuevo("uno@macy.pa", RolUsuarioEnum::Finanzas, vec![1])).unwrap();
+        registrar_usuario(&superadmin, &state, nuevo("dos@macy.pa", RolUsuarioEnum::Soporte, vec![2])).unwrap();
+
+        // el email se compara normalizado
+        let (status, _) = registrar_usuario(&superadmin, &state, nuevo(" UNO@macy.pa", RolUsuarioEnum::Soporte, vec![1]))
+            .err()
+            .unwrap();
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let visibles = usuarios_visibles(&sesion(RolUsuarioEnum::GrupoAdmin, vec![1]), &state).unwrap();
+        let emails: Vec<_> = visibles.iter().map(|u| u.usuario.email.as_str()).collect();
+        assert_eq!(emails, vec!["uno@macy.pa"]);
+        assert_eq!(visibles[0].grupos, vec![1]);
+
+        let codigos: Vec<_> = repos.datos().auditoria.iter().map(|a| a.codigo_http).collect();
+        assert_eq!(codigos, vec![Some(201), Some(201), Some(409)]);
+    }
+
+    #[test]
+    fn un_login_fallido_queda_auditado() {
+        let (state, repos) = prueba::estado(Memoria::default());
+        let superadmin = sesion(RolUsuarioEnum::Superadmin, vec![]);
+        registrar_usuario(&superadmin, &state, nuevo("uno@macy.pa", RolUsuarioEnum::Finanzas, vec![1])).unwrap();
+
+        let login = LoginUsuario { email: "uno@macy.pa".to_string(), password: "otra-clave".to_string() };
+        let (status, _) = iniciar_sesion(&state, login).err().unwrap();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let datos = repos.datos();
+        let ultimo = datos.auditoria.last().unwrap();
+        assert_eq!(ultimo.accion, "login");
+        assert_eq!(ultimo.codigo_http, Some(401));
+    }
+}
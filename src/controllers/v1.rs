@@ -0,0 +1,540 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bigdecimal::{BigDecimal, ToPrimitive};
+use serde_json::{Value, json};
+
+use crate::AppState;
+use crate::controllers::auditoria::get_auditoria;
+use crate::controllers::cajeros::{LoginCajero, abrir_turno, terminar_turno};
+use crate::controllers::grupos::{GrupoConCajas, get_grupos};
+use crate::controllers::kioskos::{Heartbeat, registrar_latido};
+use crate::controllers::recibos::{FormatoRecibo, OpcionesRecibo, buscar_recibo, reimprimir_recibo};
+use crate::controllers::reembolsos::{SolicitudReembolso, reembolsar_venta};
+use crate::controllers::structs::v1::{
+    ApiError, ApiResult, CancelacionCobro, CierreCaja, Cobro, ErrorApi, EstadoCobro,
+    ImagenQRCobro, Latido, LineaResumenCierre, Montos, ReembolsoCobro, Respuesta, SesionCaja,
+    TurnoCajero,
+};
+use crate::controllers::structs::yappy::{CuerpoCierreYappy, GenerarQR, RespuestaYappy};
+use crate::controllers::usuarios::{
+    CrearUsuario, LoginUsuario, PerfilUsuario, SesionUsuario, UsuarioConGrupos, UsuarioCreado,
+    iniciar_sesion, perfil, registrar_usuario, usuarios_visibles,
+};
+use crate::controllers::yappy::{
+    CancelarTransaccion, anular_transaccion, cerrar_sesion_yappy, consultar_transaccion,
+    devolver_transaccion, generar_cobro, qr_transaccion, stream_transaccion,
+};
+use crate::db::models::{Auditoria, FiltroAuditoria, KioskoInfo, Reembolso, Transaccion};
+use crate::middlewares::usuarios::UsuarioSesion;
+use crate::utils::cajas_utils::abrir_caja_and_return_value;
+use crate::utils::qr::OpcionesQR;
+use crate::utils::recibos::ReciboImpreso;
+use crate::utils::tiempo;
+use crate::utils::utils::get_info_by_mac_address;
+
+// Las rutas /v1 usan las mismas funciones de servicio que las rutas originales y
+// convierten su resultado en los DTOs de MACY con el sobre comun.
+
+fn kiosko(headers: &HeaderMap, state: &AppState) -> Result<KioskoInfo, ApiError> {
+    let mac_address = headers
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok())
+        .ok_or_else(|| ApiError::from(StatusCode::FORBIDDEN))?;
+
+    get_info_by_mac_address(state, mac_address)
+        .map_err(|_| ApiError::new(StatusCode::FORBIDDEN, "prohibido", "Sin acceso"))
+}
+
+/// Código de estado de Yappy si la operación fue exitosa; si Yappy la rechazó, un 502.
+fn exigir_yappy(respuesta: &Value) -> Result<Option<String>, ApiError> {
+    let yappy = RespuestaYappy::<Value>::de(respuesta);
+
+    if yappy.exitosa() {
+        return Ok(yappy.codigo().map(str::to_string));
+    }
+
+    // sin status: solo es error si lo armó MACY (`json_error`)
+    if yappy.status.is_none() && respuesta.get("success") != Some(&Value::Bool(false)) {
+        return Ok(None);
+    }
+
+    let message = yappy
+        .status
+        .as_ref()
+        .and_then(|status| status.description.clone())
+        .or_else(|| respuesta.get("error").and_then(|v| v.as_str()).map(str::to_string))
+        .unwrap_or_else(|| "Yappy rechazó la operación".to_string());
+
+    Err(ApiError {
+        status: StatusCode::BAD_GATEWAY,
+        error: ErrorApi {
+            code: "error_yappy".to_string(),
+            message,
+            detalles: Some(json!({ "codigo_yappy": yappy.codigo() })),
+        },
+    })
+}
+
+fn monto(valor: &BigDecimal) -> f64 {
+    valor.to_f64().unwrap_or_default()
+}
+
+fn error_interno<E: std::fmt::Display>(err: E) -> ApiError {
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "error_interno", err.to_string())
+}
+
+fn cierre_de(headers: &HeaderMap, state: &AppState, respuesta: &Value) -> Result<CierreCaja, ApiError> {
+    let codigo_yappy = exigir_yappy(respuesta)?;
+    let info = kiosko(headers, state)?;
+
+    let resumen = RespuestaYappy::<CuerpoCierreYappy>::de(respuesta)
+        .body
+        .unwrap_or_default()
+        .summary
+        .into_iter()
+        .map(|linea| LineaResumenCierre {
+            tipo: linea.tipo,
+            monto: linea.amount,
+            transacciones: linea.transactions,
+        })
+        .collect();
+
+    Ok(CierreCaja {
+        id_caja: info.id_caja,
+        caja: info.nombre_caja,
+        estado: info.estado,
+        codigo_yappy,
+        resumen,
+    })
+}
+
+fn reembolso_de(
+    transaccion: Transaccion,
+    reembolso: Reembolso,
+    saldo: &BigDecimal,
+    codigo_yappy: Option<String>,
+) -> ReembolsoCobro {
+    ReembolsoCobro {
+        id: reembolso.id,
+        id_transaccion: transaccion.id_transaccion_yappy,
+        monto: monto(&reembolso.monto),
+        motivo: reembolso.motivo,
+        estado_transaccion: transaccion.estado,
+        saldo_reembolsable: monto(saldo),
+        codigo_yappy,
+    }
+}
+
+#[utoipa::path(post, path = "/v1/sesion", tag = "v1",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Sesión abierta en Yappy", body = Respuesta<SesionCaja>),
+        (status = 409, description = "Transición de estado inválida", body = Respuesta<Value>),
+        (status = 502, description = "Yappy rechazó la apertura", body = Respuesta<Value>),
+    ))]
+pub async fn abrir_sesion(headers: HeaderMap, State(state): State<AppState>) -> ApiResult<SesionCaja> {
+    let respuesta = abrir_caja_and_return_value(headers.clone(), state.clone()).await?;
+    let codigo_yappy = exigir_yappy(&respuesta)?;
+    let info = kiosko(&headers, &state)?;
+
+    Ok(Respuesta::ok(SesionCaja {
+        id_caja: info.id_caja,
+        caja: info.nombre_caja,
+        estado: info.estado,
+        codigo_yappy,
+    }))
+}
+
+#[utoipa::path(delete, path = "/v1/sesion", tag = "v1",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Sesión cerrada con el resumen del cierre", body = Respuesta<CierreCaja>),
+        (status = 502, description = "Yappy rechazó el cierre", body = Respuesta<Value>),
+    ))]
+pub async fn cerrar_sesion(headers: HeaderMap, State(state): State<AppState>) -> ApiResult<CierreCaja> {
+    let respuesta = cerrar_sesion_yappy(&headers, state.clone()).await?;
+    Ok(Respuesta::ok(cierre_de(&headers, &state, &respuesta)?))
+}
+
+#[utoipa::path(post, path = "/v1/cobros", tag = "v1",
+    security(("mac_address" = [])),
+    request_body = GenerarQR,
+    responses(
+        (status = 200, description = "Cobro creado", body = Respuesta<Cobro>),
+        (status = 409, description = "La caja está cambiando de estado", body = Respuesta<Value>),
+        (status = 422, description = "Los montos no coinciden con los items", body = Respuesta<Value>),
+        (status = 502, description = "Yappy rechazó el cobro", body = Respuesta<Value>),
+    ))]
+pub async fn crear_cobro(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(payload): Json<GenerarQR>,
+) -> ApiResult<Cobro> {
+    let creado = generar_cobro(headers, &state, payload).await?;
+    let codigo_yappy = exigir_yappy(&creado.yappy)?;
+
+    let (Some(id), Some(id_transaccion)) = (creado.id, creado.id_transaccion) else {
+        return Err(ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            "error_yappy",
+            "Yappy no devolvió la transacción",
+        ));
+    };
+
+    // los montos son los que se enviaron a Yappy, ya calculados por MACY
+    let cobro = creado.cobro;
+
+    Ok(Respuesta::ok(Cobro {
+        id,
+        id_transaccion,
+        id_orden: cobro.id_orden,
+        tipo_qr: creado.tipo_qr,
+        estado: "PENDING".to_string(),
+        montos: Montos {
+            subtotal: cobro.subtotal,
+            impuesto: cobro.impuesto.unwrap_or_default(),
+            propina: cobro.propina,
+            descuento: cobro.descuento,
+            total: cobro.total,
+        },
+        hash_qr: creado.hash_qr,
+        qr: creado.qr.map(|imagen| ImagenQRCobro {
+            formato: imagen.formato,
+            content_type: imagen.content_type().to_string(),
+            base64: imagen.base64(),
+        }),
+        advertencias: creado.advertencias,
+        codigo_yappy,
+    }))
+}
+
+#[utoipa::path(get, path = "/v1/cobros/actual", tag = "v1",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Estado del cobro en curso", body = Respuesta<EstadoCobro>),
+        (status = 400, description = "No hay transacción activa", body = Respuesta<Value>),
+        (status = 502, description = "Yappy no devolvió el estado", body = Respuesta<Value>),
+    ))]
+pub async fn estado_cobro(headers: HeaderMap, State(state): State<AppState>) -> ApiResult<EstadoCobro> {
+    let consulta = consultar_transaccion(&headers, &state).await?;
+    let codigo_yappy = exigir_yappy(&consulta.yappy)?;
+
+    let estado = consulta.estado.ok_or_else(|| {
+        ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            "error_yappy",
+            "Yappy no devolvió el estado de la transacción",
+        )
+    })?;
+
+    // la fecha del pago solo se informa cuando terminó
+    let fecha = consulta.referencia.as_ref().map(|_| consulta.fecha);
+
+    Ok(Respuesta::ok(EstadoCobro {
+        id_transaccion: consulta.id_transaccion,
+        estado,
+        referencia: consulta.referencia,
+        fecha: fecha.as_ref().map(tiempo::rfc3339),
+        fecha_texto: fecha.as_ref().map(tiempo::texto_legado),
+        recibo: consulta.recibo,
+        codigo_yappy,
+    }))
+}
+
+#[utoipa::path(get, path = "/v1/cobros/actual/stream", tag = "v1",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Eventos SSE `estado` hasta que el cobro termina", content_type = "text/event-stream", body = String),
+        (status = 400, description = "No hay transacción activa", body = Respuesta<Value>),
+    ))]
+pub async fn stream_cobro(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(stream_transaccion(headers, State(state)).await?)
+}
+
+#[utoipa::path(get, path = "/v1/cobros/actual/qr", tag = "v1",
+    security(("mac_address" = [])),
+    params(OpcionesQR),
+    responses(
+        (status = 200, description = "Imagen del QR del cobro en curso", content(("image/png"), ("image/svg+xml"))),
+        (status = 400, description = "No hay transacción activa", body = Respuesta<Value>),
+    ))]
+pub async fn qr_cobro(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    opciones: Query<OpcionesQR>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(qr_transaccion(headers, State(state), opciones).await?)
+}
+
+#[utoipa::path(post, path = "/v1/cobros/actual/cancelacion", tag = "v1",
+    security(("mac_address" = [])),
+    request_body(content = Option<CancelarTransaccion>, description = "Motivo opcional"),
+    responses(
+        (status = 200, description = "Cobro cancelado y caja liberada", body = Respuesta<CancelacionCobro>),
+        (status = 409, description = "El cobro ya fue pagado", body = Respuesta<Value>),
+    ))]
+pub async fn cancelar_cobro(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    payload: Option<Json<CancelarTransaccion>>,
+) -> ApiResult<CancelacionCobro> {
+    let motivo = payload.and_then(|Json(payload)| payload.motivo);
+    let cancelacion = anular_transaccion(&headers, &state, motivo).await?;
+
+    Ok(Respuesta::ok(CancelacionCobro {
+        id_transaccion: cancelacion.transaccion,
+        estado: cancelacion.estado,
+        motivo: Some(cancelacion.motivo),
+    }))
+}
+
+#[utoipa::path(post, path = "/v1/cobros/actual/reembolso", tag = "v1",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Reembolso total del cobro en curso", body = Respuesta<ReembolsoCobro>),
+        (status = 400, description = "No hay transacción activa", body = Respuesta<Value>),
+        (status = 502, description = "Yappy rechazó el reembolso", body = Respuesta<Value>),
+    ))]
+pub async fn reembolsar_cobro(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> ApiResult<ReembolsoCobro> {
+    let consulta = devolver_transaccion(&headers, &state).await?;
+    let codigo_yappy = exigir_yappy(&consulta.yappy)?;
+
+    let reembolso = consulta
+        .reembolso
+        .ok_or_else(|| error_interno("Reembolso no registrado"))?;
+
+    // el reembolso pudo dejar la venta en REFUNDED
+    let transacciones = &state.repos.transacciones;
+    let transaccion = transacciones
+        .buscar_por_id(reembolso.id_transaccion)
+        .map_err(error_interno)?;
+    let saldo = transacciones
+        .saldo_reembolsable(&transaccion)
+        .map_err(error_interno)?;
+
+    Ok(Respuesta::ok(reembolso_de(transaccion, reembolso, &saldo, codigo_yappy)))
+}
+
+#[utoipa::path(post, path = "/v1/reembolsos", tag = "v1",
+    security(("mac_address" = [])),
+    request_body = SolicitudReembolso,
+    responses(
+        (status = 200, description = "Reembolso aplicado en Yappy", body = Respuesta<ReembolsoCobro>),
+        (status = 422, description = "El monto excede el saldo reembolsable", body = Respuesta<Value>),
+        (status = 502, description = "Yappy rechazó el reembolso", body = Respuesta<Value>),
+    ))]
+pub async fn reembolsar(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(payload): Json<SolicitudReembolso>,
+) -> ApiResult<ReembolsoCobro> {
+    let aplicado = reembolsar_venta(&headers, &state, payload).await?;
+    let codigo_yappy = exigir_yappy(&aplicado.yappy)?;
+
+    Ok(Respuesta::ok(reembolso_de(
+        aplicado.transaccion,
+        aplicado.reembolso,
+        &aplicado.saldo_reembolsable,
+        codigo_yappy,
+    )))
+}
+
+#[utoipa::path(get, path = "/v1/recibos/{id}", tag = "v1",
+    security(("mac_address" = [])),
+    params(("id" = i32, Path, description = "Id de la transacción"), OpcionesRecibo),
+    responses(
+        (status = 200, description = "Recibo; con `formato=json` va dentro del sobre", body = Respuesta<ReciboImpreso>),
+        (status = 404, description = "Transacción no encontrada", body = Respuesta<Value>),
+    ))]
+pub async fn recibo(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(opciones): Query<OpcionesRecibo>,
+) -> Result<Response, ApiError> {
+    if opciones.formato == FormatoRecibo::Json {
+        let recibo = buscar_recibo(&headers, &state, id)?;
+        return Ok(Respuesta::ok(recibo.impreso()).into_response());
+    }
+
+    Ok(reimprimir_recibo(headers, State(state), Path(id), Query(opciones)).await?)
+}
+
+#[utoipa::path(post, path = "/v1/cajero/login", tag = "v1",
+    security(("mac_address" = [])),
+    request_body = LoginCajero,
+    responses(
+        (status = 200, description = "Turno abierto", body = Respuesta<TurnoCajero>),
+        (status = 401, description = "Cajero o PIN inválido", body = Respuesta<Value>),
+        (status = 502, description = "Yappy no abrió la sesión", body = Respuesta<Value>),
+    ))]
+pub async fn entrar_cajero(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(payload): Json<LoginCajero>,
+) -> ApiResult<TurnoCajero> {
+    let turno = abrir_turno(headers, &state, payload).await?;
+    let codigo_yappy = exigir_yappy(&turno.yappy)?;
+
+    Ok(Respuesta::ok(TurnoCajero {
+        cajero: Some(turno.cajero),
+        caja: turno.caja,
+        abierta: true,
+        codigo_yappy,
+    }))
+}
+
+#[utoipa::path(post, path = "/v1/cajero/logout", tag = "v1",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Turno cerrado con su resumen", body = Respuesta<CierreCaja>),
+        (status = 400, description = "No hay cajero en turno", body = Respuesta<Value>),
+    ))]
+pub async fn salir_cajero(headers: HeaderMap, State(state): State<AppState>) -> ApiResult<CierreCaja> {
+    let respuesta = terminar_turno(&headers, state.clone()).await?;
+    Ok(Respuesta::ok(cierre_de(&headers, &state, &respuesta)?))
+}
+
+#[utoipa::path(post, path = "/v1/heartbeat", tag = "v1",
+    security(("mac_address" = [])),
+    request_body(content = Option<Heartbeat>, description = "Versión de la app del kiosko"),
+    responses(
+        (status = 200, description = "Latido registrado", body = Respuesta<Latido>),
+        (status = 403, description = "Kiosko sin acceso", body = Respuesta<Value>),
+    ))]
+pub async fn latido(
+    headers: HeaderMap,
+    addr: axum::extract::ConnectInfo<std::net::SocketAddr>,
+    State(state): State<AppState>,
+    payload: Option<Json<Heartbeat>>,
+) -> ApiResult<Latido> {
+    let Json(payload) = payload.unwrap_or_default();
+    Ok(Respuesta::ok(registrar_latido(&headers, addr.0, &state, payload)?))
+}
+
+#[utoipa::path(get, path = "/v1/grupos", tag = "v1",
+    security(("jwt" = [])),
+    responses(
+        (status = 200, description = "Grupos visibles con sus cajas y kioskos", body = Respuesta<Vec<GrupoConCajas>>),
+        (status = 401, description = "Sesión inválida", body = Respuesta<Value>),
+    ))]
+pub async fn grupos(sesion: UsuarioSesion, State(state): State<AppState>) -> ApiResult<Vec<GrupoConCajas>> {
+    let Json(grupos) = get_grupos(sesion, State(state)).await?;
+    Ok(Respuesta::ok(grupos))
+}
+
+#[utoipa::path(get, path = "/v1/auditoria", tag = "v1",
+    security(("jwt" = [])),
+    params(FiltroAuditoria),
+    responses(
+        (status = 200, description = "Registros de auditoría", body = Respuesta<Vec<Auditoria>>),
+        (status = 403, description = "Rol sin permiso", body = Respuesta<Value>),
+    ))]
+pub async fn auditoria(
+    sesion: UsuarioSesion,
+    State(state): State<AppState>,
+    filtro: Query<FiltroAuditoria>,
+) -> ApiResult<Vec<Auditoria>> {
+    let Json(registros) = get_auditoria(sesion, State(state), filtro).await?;
+    Ok(Respuesta::ok(registros))
+}
+
+#[utoipa::path(post, path = "/v1/admin/login", tag = "v1",
+    request_body = LoginUsuario,
+    responses(
+        (status = 200, description = "JWT y datos del usuario", body = Respuesta<SesionUsuario>),
+        (status = 401, description = "Email o contraseña inválidos", body = Respuesta<Value>),
+    ))]
+pub async fn admin_login(
+    State(state): State<AppState>,
+    Json(payload): Json<LoginUsuario>,
+) -> ApiResult<SesionUsuario> {
+    Ok(Respuesta::ok(iniciar_sesion(&state, payload)?))
+}
+
+#[utoipa::path(get, path = "/v1/admin/yo", tag = "v1",
+    security(("jwt" = [])),
+    responses(
+        (status = 200, description = "Usuario de la sesión", body = Respuesta<PerfilUsuario>),
+        (status = 401, description = "Sesión inválida", body = Respuesta<Value>),
+    ))]
+pub async fn admin_perfil(sesion: UsuarioSesion) -> ApiResult<PerfilUsuario> {
+    Ok(Respuesta::ok(perfil(sesion)))
+}
+
+#[utoipa::path(get, path = "/v1/admin/usuarios", tag = "v1",
+    security(("jwt" = [])),
+    responses(
+        (status = 200, description = "Usuarios visibles con sus grupos", body = Respuesta<Vec<UsuarioConGrupos>>),
+        (status = 403, description = "Rol sin permiso", body = Respuesta<Value>),
+    ))]
+pub async fn admin_usuarios(
+    sesion: UsuarioSesion,
+    State(state): State<AppState>,
+) -> ApiResult<Vec<UsuarioConGrupos>> {
+    Ok(Respuesta::ok(usuarios_visibles(&sesion, &state)?))
+}
+
+#[utoipa::path(post, path = "/v1/admin/usuarios", tag = "v1",
+    security(("jwt" = [])),
+    request_body = CrearUsuario,
+    responses(
+        (status = 201, description = "Usuario creado", body = Respuesta<UsuarioCreado>),
+        (status = 409, description = "Email repetido", body = Respuesta<Value>),
+    ))]
+pub async fn admin_crear_usuario(
+    sesion: UsuarioSesion,
+    State(state): State<AppState>,
+    Json(payload): Json<CrearUsuario>,
+) -> Result<(StatusCode, Json<Respuesta<UsuarioCreado>>), ApiError> {
+    let creado = registrar_usuario(&sesion, &state, payload)?;
+    Ok((StatusCode::CREATED, Respuesta::ok(creado)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repos::memoria::prueba;
+    use crate::db::types::enums::{CajasEstadoEnum, CajasTipoEnum};
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn exigir_yappy_distingue_exito_rechazo_y_errores_de_macy() {
+        let ok = json!({ "status": { "code": "YP-0000" }, "body": {} });
+        assert_eq!(exigir_yappy(&ok).ok().flatten().as_deref(), Some("YP-0000"));
+
+        let sin_status = json!({ "abierta": true });
+        assert!(matches!(exigir_yappy(&sin_status), Ok(None)));
+
+        let rechazo = json!({ "status": { "code": "YP-0009", "description": "Sesión vencida" } });
+        let Err(err) = exigir_yappy(&rechazo) else { panic!("debió rechazarse") };
+        assert_eq!(err.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(err.error.message, "Sesión vencida");
+
+        let macy = json!({ "success": false, "error": "Caja cerrada" });
+        let Err(err) = exigir_yappy(&macy) else { panic!("debió rechazarse") };
+        assert_eq!(err.error.message, "Caja cerrada");
+    }
+
+    #[tokio::test]
+    async fn estado_cobro_sin_transaccion_activa_es_400() {
+        let memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        let (state, _) = prueba::estado(memoria);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("mac-address", HeaderValue::from_static(prueba::MAC));
+
+        let err = estado_cobro(headers, State(state)).await.err().unwrap();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+}
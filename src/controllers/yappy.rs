@@ -1,32 +1,56 @@
 use crate::AppState;
-use crate::controllers::structs::yappy::GenerarQR;
-use crate::db::models::Caja;
-use crate::db::types::enums::CajasEstadoEnum;
-use crate::schema::cajas;
+use crate::controllers::structs::yappy::{
+    Cancelacion, CobroCreado, ConsultaTransaccion, CuerpoCobroYappy, CuerpoSesionYappy,
+    CuerpoTransaccionYappy, GenerarQR, RespuestaYappy,
+};
+use crate::db::models::{ErrorResponse, NewTransaccion};
+use crate::db::types::enums::{CajasEstadoEnum, CajasTipoEnum};
+use crate::middlewares::correlacion;
 use crate::utils::cajas_utils::{
-    abrir_caja_and_return_value, guardar_datos_caja, manage_transaction_response,
+    abrir_caja_and_return_value, consultar_transaccion_en_yappy, guardar_datos_caja,
+    manage_transaction_response,
 };
+use crate::utils::monitor_transacciones::es_estado_final;
+use crate::utils::qr::{OpcionesQR, renderizar_qr};
+use crate::utils::recibos::recibo_impreso;
+use crate::utils::tiempo;
+use crate::utils::totales::{ConfiguracionImpuestos, calcular_totales, difiere};
+use crate::utils::transacciones_utils::decimal;
 use crate::utils::utils::{get_info_by_mac_address, insert_auth_headers, json_error};
 use axum::{
     Json,
-    extract::{OriginalUri, State},
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
+    extract::{OriginalUri, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
-use chrono::prelude::*;
-use chrono_tz::America::Panama;
-use diesel::prelude::*;
+use std::convert::Infallible;
+use tokio_stream::{Stream, StreamExt, wrappers::WatchStream};
+use bigdecimal::ToPrimitive;
+use serde::Deserialize;
 use serde_json::{Value, json};
 use std::env;
+use utoipa::ToSchema;
 
+#[utoipa::path(get, path = "/", tag = "general",
+    responses((status = 200, description = "Nombre y versión del servicio", body = Value)))]
 pub async fn hello_world() -> Json<Value> {
     Json(json!({ "mensaje": "Manejador Automático de Cajas Yappy v1.0" }))
 }
 
+#[utoipa::path(get, path = "/abrir-sesion", tag = "kiosko",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Sesión abierta en Yappy", body = Value),
+        (status = 403, description = "Kiosko sin acceso", body = ErrorResponse),
+        (status = 409, description = "Transición de estado inválida", body = ErrorResponse),
+    ))]
 pub async fn abrir_caja(
     headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
     let json = abrir_caja_and_return_value(headers, state).await?;
     Ok(Json(json!({
         "success": true,
@@ -34,22 +58,137 @@ pub async fn abrir_caja(
     })))
 }
 
+#[utoipa::path(post, path = "/generar-qr", tag = "kiosko",
+    security(("mac_address" = [])),
+    request_body = GenerarQR,
+    responses(
+        (status = 200, description = "Cobro creado; incluye `qr` si se pidió `imagen_qr` y se pudo dibujar, y `advertencias` si hubo diferencias de impuesto o falló la imagen", body = Value),
+        (status = 403, description = "Caja deshabilitada o sin cajero en turno", body = ErrorResponse),
+        (status = 409, description = "La caja está cambiando de estado", body = ErrorResponse),
+        (status = 422, description = "Los montos no coinciden con los items", body = Value),
+    ))]
 pub async fn generar_qr(
     headers: HeaderMap,
     State(state): State<AppState>,
-    Json(mut payload): Json<GenerarQR>,
-) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    Json(payload): Json<GenerarQR>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let cobro = generar_cobro(headers, &state, payload).await?;
+
+    let mut response_data = json!({
+        "success": true,
+        "data": cobro.yappy
+    });
+
+    if let Some(imagen) = &cobro.qr {
+        response_data["qr"] = json!({
+            "formato": imagen.formato,
+            "content_type": imagen.content_type(),
+            "base64": imagen.base64(),
+        });
+    }
+
+    if !cobro.advertencias.is_empty() {
+        response_data["advertencias"] = json!(cobro.advertencias);
+    }
+
+    Ok(Json(response_data))
+}
+
+/// Crea el cobro en Yappy y lo registra; lo usan `/generar-qr` y `/v1/cobros`.
+pub async fn generar_cobro(
+    headers: HeaderMap,
+    state: &AppState,
+    mut payload: GenerarQR,
+) -> Result<CobroCreado, (StatusCode, Json<Value>)> {
 
     let mac_address = headers
         .get("mac-address")
         .and_then(|val| val.to_str().ok())
         .ok_or_else(|| json_error(StatusCode::FORBIDDEN, "Prohibido"))?;
 
-    let mut info = get_info_by_mac_address(&state, mac_address)
+    let mut info = get_info_by_mac_address(state, mac_address)
         .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?;
 
+    match info.estado {
+        CajasEstadoEnum::Deshabilitado => {
+            return Err(json_error(StatusCode::FORBIDDEN, "Caja deshabilitada"));
+        }
+        CajasEstadoEnum::Abriendo | CajasEstadoEnum::Cerrando => {
+            return Err(json_error(
+                StatusCode::CONFLICT,
+                "La caja está cambiando de estado, intente de nuevo",
+            ));
+        }
+        _ => {}
+    }
+
+    if info.tipo == CajasTipoEnum::Cajero && info.id_cajero.is_none() {
+        return Err(json_error(StatusCode::FORBIDDEN, "No hay cajero en turno"));
+    }
+
+    let impuestos =
+        ConfiguracionImpuestos::cargar(state.repos.grupos.as_ref(), info.id_grupo)
+            .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    // diferencias de impuesto en cobros sin items: se cobran igual pero quedan registradas
+    let mut advertencias: Vec<String> = Vec::new();
+
+    // con items los montos los calcula MACY; los del kiosko solo se validan
+    let totales = match &payload.items {
+        Some(items) => {
+            let totales = calcular_totales(items, payload.propina, &impuestos)
+                .map_err(|err| json_error(StatusCode::UNPROCESSABLE_ENTITY, err))?;
+
+            let discrepancias = totales.discrepancias(&payload);
+            if !discrepancias.is_empty() {
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Json(json!({
+                        "success": false,
+                        "error": "Los montos no coinciden con los items del pedido",
+                        "discrepancias": discrepancias,
+                    })),
+                ));
+            }
+
+            totales.aplicar(&mut payload);
+            Some(totales)
+        }
+        None => {
+            let calculado = impuestos.impuesto_de(payload.subtotal, payload.descuento);
+
+            match payload.impuesto {
+                Some(impuesto) if difiere(impuesto, &calculado) => {
+                    advertencias.push(format!(
+                        "impuesto: kiosko {:.2}, MACY {}",
+                        impuesto, calculado
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    // un kiosko que no manda impuesto cobra el que calcula MACY
+                    let total = decimal(payload.subtotal) - decimal(payload.descuento)
+                        + &calculado
+                        + decimal(payload.propina);
+
+                    payload.impuesto = calculado.to_f64();
+                    payload.total = total.to_f64().unwrap_or_default();
+                }
+            }
+
+            None
+        }
+    };
+
+    // la tasa que de verdad se cobró; NULL si hubo varias o el kiosko cobró otra
+    let tasa_impuesto = match &totales {
+        Some(totales) => totales.tasa_unica(),
+        None if advertencias.is_empty() => Some(impuestos.tasa_general.clone()),
+        None => None,
+    };
+
     if info.estado.eq(&CajasEstadoEnum::Cerrado) {
-        println!(
+        tracing::info!(
             "Caja {} está cerrada. Abriéndola automáticamente...",
             info.nombre
         );
@@ -58,11 +197,9 @@ pub async fn generar_qr(
             .await
             .map_err(|_| json_error(StatusCode::INTERNAL_SERVER_ERROR, "Error al abrir la caja"))?;
 
-        // access the token from JSON
-        info.token_autorizacion = info_caja_json
-            .pointer("/body/token")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string());
+        info.token_autorizacion = RespuestaYappy::<CuerpoSesionYappy>::de(&info_caja_json)
+            .body
+            .and_then(|cuerpo| cuerpo.token);
     }
 
     payload.descripcion = format!(
@@ -75,7 +212,7 @@ pub async fn generar_qr(
 
     let formatted = payload.to_payload();
 
-    println!(
+    tracing::debug!(
         "informacion formatiada: {}",
         serde_json::to_string_pretty(&formatted).unwrap()
     );
@@ -98,9 +235,9 @@ pub async fn generar_qr(
     let response = client
         .post(url)
         .headers(insert_auth_headers(
-            info.api_key,
-            info.secret_key,
-            info.token_autorizacion,
+            info.api_key.clone(),
+            info.secret_key.clone(),
+            info.token_autorizacion.clone(),
         ))
         .json(&formatted) // This automatically serializes `formatted` to JSON
         .send()
@@ -110,15 +247,95 @@ pub async fn generar_qr(
         .await
         .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?;
 
-    let response_json: serde_json::Value = serde_json::from_str(&response).unwrap();
+    let response_json: serde_json::Value = serde_json::from_str(&response)
+        .map_err(|err| json_error(StatusCode::BAD_GATEWAY, err))?;
+
+    let cuerpo = RespuestaYappy::<CuerpoCobroYappy>::de(&response_json).body;
+    let transaccion_id = cuerpo.as_ref().and_then(|c| c.transaction_id.clone());
+    let qr_hash = cuerpo.and_then(|c| c.hash);
+
+    let mut id = None;
+
+    if let Some(transaccion_id) = &transaccion_id {
+        let items = totales
+            .as_ref()
+            .map(|totales| totales.lineas.iter().map(|linea| linea.to_new()).collect())
+            .unwrap_or_default();
+
+        let registrada = state.repos.transacciones.registrar(
+            NewTransaccion {
+                id_caja: info.id_caja,
+                id_cajero: info.id_cajero,
+                id_transaccion_yappy: transaccion_id.to_string(),
+                id_orden: payload.id_orden.clone(),
+                tipo_qr: tipo_qr.to_string(),
+                subtotal: decimal(payload.subtotal),
+                impuesto: decimal(payload.impuesto.unwrap_or_default()),
+                propina: decimal(payload.propina),
+                descuento: decimal(payload.descuento),
+                total: decimal(payload.total),
+                qr_hash: qr_hash.clone(),
+                tasa_impuesto,
+                discrepancia_impuesto: (!advertencias.is_empty())
+                    .then(|| advertencias.join("; ")),
+                request_id: correlacion::actual(),
+            },
+            items,
+        );
 
-    let mut conn = state.db_pool.get().unwrap();
+        // sin la fila no hay conciliacion ni reembolso posible: el QR no se entrega
+        let registrada = registrada.map_err(|err| {
+            tracing::error!("no se pudo registrar la transaccion {}: {}", transaccion_id, err);
+            json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("No se pudo registrar la transacción {}: {}", transaccion_id, err),
+            )
+        })?;
 
-    let _ = diesel::update(cajas::table.filter(cajas::id.eq(info.id_caja)))
-        .set((cajas::transaccion_actual.eq(response_json
-            .pointer("/body/transactionId")
-            .and_then(|v| v.as_str())),))
-        .execute(&mut conn);
+        id = Some(registrada.id);
+    }
+
+    // la caja solo apunta a transacciones que quedaron registradas
+    let _ = state
+        .repos
+        .cajas
+        .fijar_transaccion_actual(info.id_caja, transaccion_id.as_deref());
+
+    // el cobro ya existe en Yappy: si la imagen falla, el kiosko puede dibujar el QR con el hash
+    let mut qr = None;
+    if let (Some(opciones), Some(hash)) = (&payload.imagen_qr, &qr_hash) {
+        match renderizar_qr(hash, opciones) {
+            Ok(imagen) => qr = Some(imagen),
+            Err(err) => {
+                tracing::warn!("no se pudo dibujar el QR de {:?}: {}", transaccion_id, err);
+                advertencias.push(format!("qr: {}", err));
+            }
+        }
+    }
+
+    Ok(CobroCreado {
+        yappy: response_json,
+        id,
+        id_transaccion: transaccion_id,
+        hash_qr: qr_hash,
+        tipo_qr: tipo_qr.to_string(),
+        cobro: payload,
+        qr,
+        advertencias,
+    })
+}
+
+#[utoipa::path(delete, path = "/cerrar-sesion", tag = "kiosko",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Sesión cerrada y resumen guardado", body = Value),
+        (status = 403, description = "Kiosko sin acceso", body = ErrorResponse),
+    ))]
+pub async fn cerrar_caja(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let response_json = cerrar_sesion_yappy(&headers, state).await?;
 
     Ok(Json(json!({
         "success": true,
@@ -126,10 +343,11 @@ pub async fn generar_qr(
     })))
 }
 
-pub async fn cerrar_caja(
-    headers: HeaderMap,
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+/// Cierra la sesión de Yappy de la caja del kiosko y devuelve la respuesta de Yappy con el resumen.
+pub async fn cerrar_sesion_yappy(
+    headers: &HeaderMap,
+    state: AppState,
+) -> Result<Value, (StatusCode, Json<Value>)> {
 
     let mac_address = headers
         .get("mac-address")
@@ -139,42 +357,115 @@ pub async fn cerrar_caja(
     let info = get_info_by_mac_address(&state, mac_address)
         .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?;
 
-    let response_json = guardar_datos_caja(
+    let actor = info.actor();
+
+    guardar_datos_caja(
         state,
         info.api_key,
         info.secret_key,
         info.token_autorizacion,
         info.id_caja,
         info.nombre_caja,
+        actor,
     )
-    .await?;
-
-    Ok(Json(json!({
-        "success": true,
-        "data": response_json
-    })))
+    .await
 }
 
+#[utoipa::path(get, path = "/estado-transaccion", tag = "kiosko",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Estado de la transacción en curso; incluye `recibo` al completarse", body = Value),
+        (status = 400, description = "No hay transacción activa", body = ErrorResponse),
+    ))]
 pub async fn handle_transaccion(
     headers: HeaderMap,
     State(state): State<AppState>,
     OriginalUri(uri): OriginalUri,
-) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+
+    let path = uri.path();
+
+    let consulta = if path.contains("estado-transaccion") {
+        consultar_transaccion(&headers, &state).await?
+    } else if path.contains("retornar-transaccion") {
+        devolver_transaccion(&headers, &state).await?
+    } else {
+        return Err(json_error(StatusCode::BAD_REQUEST, "Ruta invalida"));
+    };
+
+    // Build the base response object
+    let mut response_data = json!({
+        "success": true,
+        "data": consulta.yappy,
+    });
+
+    // si referencia existe, entonce se incrusta en el JSON de respuesta
+    if let Some(ref_str) = consulta.referencia {
+        if let Some(obj) = response_data.as_object_mut() {
+            obj.insert("referencia".to_string(), json!(ref_str));
+            obj.insert("id_caja".to_string(), json!(consulta.id_caja));
+            obj.insert("nombre_caja".to_string(), json!(consulta.nombre_caja));
+            obj.insert("fecha".to_string(), json!(tiempo::rfc3339(&consulta.fecha)));
+            obj.insert("fecha_texto".to_string(), json!(tiempo::texto_legado(&consulta.fecha)));
+
+            if let Some(recibo) = consulta.recibo {
+                obj.insert("recibo".to_string(), json!(recibo));
+            }
+        }
+    }
+
+    Ok(Json(response_data))
+}
+
+#[utoipa::path(get, path = "/retornar-transaccion", tag = "kiosko",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Transacción en curso devuelta en Yappy y reembolso registrado", body = Value),
+        (status = 400, description = "No hay transacción activa", body = ErrorResponse),
+    ))]
+pub async fn retornar_transaccion(
+    headers: HeaderMap,
+    state: State<AppState>,
+    uri: OriginalUri,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    handle_transaccion(headers, state, uri).await
+}
+
+/// Estado en Yappy de la transacción en curso; si se completó, la caja queda libre y
+/// se incluye el recibo.
+pub async fn consultar_transaccion(
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<ConsultaTransaccion, (StatusCode, Json<Value>)> {
+    operar_transaccion(headers, state, false).await
+}
+
+/// Devuelve en Yappy la transacción en curso por el saldo completo y registra el reembolso.
+pub async fn devolver_transaccion(
+    headers: &HeaderMap,
+    state: &AppState,
+) -> Result<ConsultaTransaccion, (StatusCode, Json<Value>)> {
+    operar_transaccion(headers, state, true).await
+}
+
+async fn operar_transaccion(
+    headers: &HeaderMap,
+    state: &AppState,
+    devolver: bool,
+) -> Result<ConsultaTransaccion, (StatusCode, Json<Value>)> {
 
     let mac_address = headers
         .get("mac-address")
         .and_then(|val| val.to_str().ok())
         .ok_or_else(|| json_error(StatusCode::FORBIDDEN, "Prohibido"))?; // return early if header missing or invalid
 
-    let info = get_info_by_mac_address(&state, mac_address)
+    let info = get_info_by_mac_address(state, mac_address)
         .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?; // or map the diesel error more precisely
 
-    let mut conn = state.db_pool.get().unwrap();
-
-    let caja = cajas::table
-        .filter(cajas::id.eq(info.id_caja))
-        .select(Caja::as_select())
-        .first::<Caja>(&mut conn)
+    let caja = state
+        .repos
+        .cajas
+        .buscar(info.id_caja)
         .map_err(|_| json_error(StatusCode::CONFLICT, "Caja no encontrada"))?;
 
     // chequea si la caja tiene una transaccion o no, de no tener, devuelve un bad request
@@ -185,72 +476,426 @@ pub async fn handle_transaccion(
         )
     })?;
 
-    let path = uri.path();
+    let transacciones = &state.repos.transacciones;
 
-    //println!("{}", path);
+    // el reembolso por esta ruta siempre es por el saldo completo; se aparta antes de ir
+    // a Yappy para que otro reembolso de la misma venta no pase del total
+    let reserva = if devolver {
+        let transaccion = transacciones
+            .buscar(&transaccion_id)
+            .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
 
-    let method = if path.contains("estado-transaccion") {
-        "GET"
-    } else if path.contains("retornar-transaccion") {
-        "PUT"
+        if transaccion.estado != "COMPLETED" {
+            return Err(json_error(
+                StatusCode::CONFLICT,
+                format!("No se puede reembolsar una transacción {}", transaccion.estado),
+            ));
+        }
+
+        Some(transacciones.reservar_reembolso(
+            transaccion.id,
+            None,
+            "Reembolso total desde el kiosko".to_string(),
+            info.actor(),
+            info.id_cajero,
+        )?)
     } else {
-        return Err(json_error(StatusCode::BAD_REQUEST, "Ruta invalida"));
+        None
     };
 
-    let client = reqwest::Client::new();
+    let response_json = async {
+        let client = reqwest::Client::new();
 
-    let url = format!(
-        "{}/transaction/{}",
-        env::var("YAPPY_ENDPOINT")
-            .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err,))?,
-        transaccion_id.to_string()
-    );
+        let url = format!(
+            "{}/transaction/{}",
+            env::var("YAPPY_ENDPOINT")
+                .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err,))?,
+            transaccion_id
+        );
+
+        let request_builder = if devolver {
+            client.put(url)
+        } else {
+            client.get(url)
+        }
+        .headers(insert_auth_headers(
+            info.api_key.clone(),
+            info.secret_key.clone(),
+            info.token_autorizacion.clone(),
+        ));
 
-    let request_builder = match method {
-        "GET" => client.get(url),
-        "PUT" => client.put(url),
-        _ => unreachable!("No hay metodo"),
+        let response = request_builder
+            .send()
+            .await
+            .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?
+            .text()
+            .await
+            .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?;
+
+        serde_json::from_str::<Value>(&response)
+            .map_err(|err| json_error(StatusCode::BAD_GATEWAY, err))
+    }
+    .await;
+
+    // la reserva se cierra con lo que haya respondido Yappy; sin respuesta queda fallida
+    let mut reembolso = None;
+    if let Some(reserva) = &reserva {
+        let registrado = match &response_json {
+            Ok(json) => json.clone(),
+            Err((_status, err_json)) => err_json.0.clone(),
+        };
+
+        let completado = transacciones
+            .completar_reembolso(reserva, &registrado)
+            .map_err(|err| {
+                tracing::error!("no se pudo completar el reembolso {}: {}", reserva.id, err);
+                json_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("No se pudo registrar el reembolso de {}: {}", transaccion_id, err),
+                )
+            })?;
+        reembolso = Some(completado);
     }
-    .headers(insert_auth_headers(
-        info.api_key,
-        info.secret_key,
-        info.token_autorizacion,
-    ));
 
-    let response = request_builder
-        .send()
-        .await
-        .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?
-        .text()
-        .await
-        .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?;
+    let response_json = response_json?;
 
-    let response_json: serde_json::Value = serde_json::from_str(&response).unwrap();
+    let path = if devolver {
+        "retornar-transaccion"
+    } else {
+        "estado-transaccion"
+    };
 
-    let referencia = manage_transaction_response(&path, &response_json, info.id_caja, &state)
-        .await
-        .unwrap();
+    let referencia =
+        manage_transaction_response(path, &response_json, info.id_caja, &transaccion_id, state)
+            .await?;
 
-    // Build the base response object
-    let mut response_data = json!({
+    let recibo = match &referencia {
+        Some(referencia) if !devolver => recibo_impreso(&state.repos, referencia),
+        _ => None,
+    };
+
+    let estado = RespuestaYappy::<CuerpoTransaccionYappy>::de(&response_json)
+        .body
+        .and_then(|cuerpo| cuerpo.status);
+
+    Ok(ConsultaTransaccion {
+        yappy: response_json,
+        id_caja: info.id_caja,
+        nombre_caja: info.nombre_caja,
+        id_transaccion: transaccion_id,
+        estado,
+        referencia,
+        fecha: tiempo::ahora(),
+        recibo,
+        reembolso,
+    })
+}
+
+#[utoipa::path(get, path = "/estado-transaccion/stream", tag = "kiosko",
+    security(("mac_address" = [])),
+    responses(
+        (status = 200, description = "Eventos SSE `estado` hasta que la transacción termina", content_type = "text/event-stream", body = String),
+        (status = 400, description = "No hay transacción activa", body = ErrorResponse),
+    ))]
+pub async fn stream_transaccion(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<Value>)> {
+
+    let mac_address = headers
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok())
+        .ok_or_else(|| json_error(StatusCode::FORBIDDEN, "Prohibido"))?;
+
+    let info = get_info_by_mac_address(&state, mac_address)
+        .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?;
+
+    let transaccion_id = state
+        .repos
+        .cajas
+        .buscar(info.id_caja)
+        .map_err(|_| json_error(StatusCode::CONFLICT, "Caja no encontrada"))?
+        .transaccion_actual
+        .ok_or_else(|| {
+            json_error(
+                StatusCode::BAD_REQUEST,
+                "Actualmente no hay transacción activa en esta caja",
+            )
+        })?;
+
+    let rx = state.monitor.suscribir(&state, info, transaccion_id);
+
+    // el valor inicial del canal es Null hasta la primera respuesta de Yappy
+    let stream = WatchStream::new(rx)
+        .filter(|evento| !evento.is_null())
+        .map(|evento| {
+            Ok(Event::default()
+                .event("estado")
+                .json_data(evento)
+                .unwrap_or_default())
+        });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct CancelarTransaccion {
+    pub motivo: Option<String>,
+}
+
+#[utoipa::path(post, path = "/cancelar-transaccion", tag = "kiosko",
+    security(("mac_address" = [])),
+    request_body(content = Option<CancelarTransaccion>, description = "Motivo opcional"),
+    responses(
+        (status = 200, description = "Transacción cancelada y caja liberada", body = Value),
+        (status = 409, description = "La transacción ya fue pagada", body = ErrorResponse),
+    ))]
+pub async fn cancelar_transaccion(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    payload: Option<Json<CancelarTransaccion>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let Json(payload) = payload.unwrap_or_default();
+
+    let cancelacion = anular_transaccion(&headers, &state, payload.motivo).await?;
+
+    Ok(Json(json!({
         "success": true,
-        "data": response_json,
-    });
+        "data": cancelacion
+    })))
+}
 
-    // si referencia existe, entonce se incrusta en el JSON de respuesta
-    if let Some(ref_str) = referencia {
-        if let Some(obj) = response_data.as_object_mut() {
-            obj.insert("referencia".to_string(), json!(ref_str));
-            obj.insert("id_caja".to_string(), json!(info.id_caja));
-            obj.insert("nombre_caja".to_string(), json!(info.nombre_caja));
-            let now_in_panama = Panama.from_utc_datetime(&Utc::now().naive_utc());
-            let formatted_time = now_in_panama
-                .format("%m/%d/%Y %I:%M:%S %p")
-                .to_string()
-                .to_uppercase();
-            obj.insert("fecha".to_string(), json!(formatted_time));
-        }
+/// Abandona la transacción en curso si el cliente no la pagó y libera la caja.
+pub async fn anular_transaccion(
+    headers: &HeaderMap,
+    state: &AppState,
+    motivo: Option<String>,
+) -> Result<Cancelacion, (StatusCode, Json<Value>)> {
+
+    let mac_address = headers
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok())
+        .ok_or_else(|| json_error(StatusCode::FORBIDDEN, "Prohibido"))?;
+
+    let info = get_info_by_mac_address(state, mac_address)
+        .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?;
+
+    let transaccion_id = state
+        .repos
+        .cajas
+        .buscar(info.id_caja)
+        .map_err(|_| json_error(StatusCode::CONFLICT, "Caja no encontrada"))?
+        .transaccion_actual
+        .ok_or_else(|| {
+            json_error(
+                StatusCode::BAD_REQUEST,
+                "Actualmente no hay transacción activa en esta caja",
+            )
+        })?;
+
+    // ultima consulta para no cancelar algo que el cliente ya pago
+    let response_json = consultar_transaccion_en_yappy(
+        info.api_key.clone(),
+        info.secret_key.clone(),
+        info.token_autorizacion.clone(),
+        &transaccion_id,
+    )
+    .await?;
+
+    let estado = RespuestaYappy::<CuerpoTransaccionYappy>::de(&response_json)
+        .body
+        .and_then(|cuerpo| cuerpo.status)
+        .unwrap_or_else(|| "PENDING".to_string());
+
+    if estado == "COMPLETED" {
+        manage_transaction_response(
+            "estado-transaccion",
+            &response_json,
+            info.id_caja,
+            &transaccion_id,
+            state,
+        )
+        .await?;
+
+        return Err(json_error(
+            StatusCode::CONFLICT,
+            "La transacción ya fue pagada, debe reembolsarse",
+        ));
     }
 
-    Ok(Json(response_data))
+    // Yappy no tiene forma de anular un QR pendiente: se abandona localmente y el job de
+    // transacciones lo sigue consultando hasta que vence, por si el cliente lo paga igual
+    let estado_final = if es_estado_final(&estado) {
+        estado
+    } else {
+        "CANCELLED".to_string()
+    };
+
+    let motivo = motivo.unwrap_or_else(|| "Cancelada desde el kiosko".to_string());
+
+    state
+        .repos
+        .transacciones
+        .finalizar(info.id_caja, &transaccion_id, &estado_final, Some(&motivo))
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    let cancelacion = Cancelacion {
+        transaccion: transaccion_id,
+        estado: estado_final,
+        motivo,
+    };
+
+    state.monitor.finalizar(&cancelacion.transaccion, json!(cancelacion));
+
+    Ok(cancelacion)
+}
+
+#[utoipa::path(get, path = "/qr-transaccion", tag = "kiosko",
+    security(("mac_address" = [])),
+    params(OpcionesQR),
+    responses(
+        (status = 200, description = "Imagen del QR de la transacción en curso", content(("image/png"), ("image/svg+xml"))),
+        (status = 400, description = "No hay transacción activa", body = ErrorResponse),
+    ))]
+pub async fn qr_transaccion(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Query(opciones): Query<OpcionesQR>,
+) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+
+    let mac_address = headers
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok())
+        .ok_or_else(|| json_error(StatusCode::FORBIDDEN, "Prohibido"))?;
+
+    let info = get_info_by_mac_address(&state, mac_address)
+        .map_err(|_err| json_error(StatusCode::FORBIDDEN, "Sin acceso"))?;
+
+    let qr_hash = state
+        .repos
+        .cajas
+        .buscar(info.id_caja)
+        .ok()
+        .and_then(|caja| caja.transaccion_actual)
+        .and_then(|id| state.repos.transacciones.buscar(&id).ok())
+        .and_then(|transaccion| transaccion.qr_hash)
+        .ok_or_else(|| {
+            json_error(
+                StatusCode::NOT_FOUND,
+                "Actualmente no hay un QR activo en esta caja",
+            )
+        })?;
+
+    let imagen = renderizar_qr(&qr_hash, &opciones)
+        .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+    Ok(([(header::CONTENT_TYPE, imagen.content_type())], imagen.bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repos::memoria::{Memoria, prueba};
+    use axum::http::{HeaderValue, Uri};
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("mac-address", HeaderValue::from_static(prueba::MAC));
+        headers
+    }
+
+    fn cobro(valor: Value) -> Json<GenerarQR> {
+        Json(serde_json::from_value(valor).unwrap())
+    }
+
+    async fn generar(memoria: Memoria, valor: Value) -> StatusCode {
+        let (state, _) = prueba::estado(memoria);
+        generar_qr(headers(), State(state), cobro(valor))
+            .await
+            .map(|_| StatusCode::OK)
+            .unwrap_or_else(|(status, _)| status)
+    }
+
+    fn cobro_simple() -> Value {
+        json!({ "tipo_qr": "DYN", "subtotal": 10.0, "total": 10.7 })
+    }
+
+    #[tokio::test]
+    async fn generar_qr_respeta_el_estado_de_la_caja() {
+        let abriendo = prueba::memoria(CajasEstadoEnum::Abriendo, CajasTipoEnum::Kiosko);
+        assert_eq!(generar(abriendo, cobro_simple()).await, StatusCode::CONFLICT);
+
+        let deshabilitada = prueba::memoria(CajasEstadoEnum::Deshabilitado, CajasTipoEnum::Kiosko);
+        assert_eq!(generar(deshabilitada, cobro_simple()).await, StatusCode::FORBIDDEN);
+
+        let sin_cajero = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Cajero);
+        assert_eq!(generar(sin_cajero, cobro_simple()).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn generar_qr_rechaza_montos_que_no_cuadran_con_los_items() {
+        let memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        let valor = json!({
+            "tipo_qr": "DYN",
+            "subtotal": 10.0,
+            "total": 5.0,
+            "items": [{ "sku": "A1", "descripcion": "Café", "cantidad": 1, "precio_unitario": 10.0 }],
+        });
+
+        assert_eq!(generar(memoria, valor).await, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn generar_qr_rechaza_descuentos_negativos() {
+        let memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        let valor = json!({
+            "tipo_qr": "DYN",
+            "subtotal": 10.0,
+            "total": 12.0,
+            "items": [{ "sku": "A1", "descripcion": "Café", "cantidad": 1, "precio_unitario": 10.0, "descuento": -2.0 }],
+        });
+
+        assert_eq!(generar(memoria, valor).await, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn sin_transaccion_activa_no_se_consulta_ni_se_cancela() {
+        let memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        let (state, _) = prueba::estado(memoria);
+
+        let uri = Uri::from_static("/estado-transaccion");
+        let (status, _) = handle_transaccion(headers(), State(state.clone()), OriginalUri(uri))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = cancelar_transaccion(headers(), State(state), None)
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn solo_se_devuelve_una_venta_completada() {
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        prueba::transaccion(&mut memoria, "pendiente", "PENDING", tiempo::ahora());
+        memoria.cajas[0].transaccion_actual = Some("pendiente".to_string());
+        let (state, repos) = prueba::estado(memoria);
+
+        let Err((status, _)) = devolver_transaccion(&headers(), &state).await else {
+            panic!("debió rechazarse");
+        };
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(repos.datos().reembolsos.is_empty());
+    }
+
+    #[tokio::test]
+    async fn un_kiosko_desconocido_no_tiene_acceso() {
+        let (state, _) = prueba::estado(Memoria::default());
+
+        let (status, _) = generar_qr(headers(), State(state), cobro(cobro_simple()))
+            .await
+            .unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
 }
@@ -1,16 +1,456 @@
 use std::env;
 
-use diesel::{
-    mysql::MysqlConnection,
-    r2d2::{ConnectionManager, Pool}
-};
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Error, Pool};
 
-pub type MySqlPool = Pool<ConnectionManager<MysqlConnection>>;
+#[cfg(not(any(feature = "mysql", feature = "sqlite")))]
+compile_error!("activar el feature `mysql` (por defecto) o `sqlite`");
 
-pub fn create_pool() -> MySqlPool {
+/// Conexión de la base según el feature: MariaDB/MySQL por defecto, SQLite con `sqlite`.
+#[cfg(not(feature = "sqlite"))]
+pub type Conexion = diesel::mysql::MysqlConnection;
+#[cfg(feature = "sqlite")]
+pub type Conexion = diesel::sqlite::SqliteConnection;
+
+pub type Backend = <Conexion as diesel::Connection>::Backend;
+
+pub type DbPool = Pool<ConnectionManager<Conexion>>;
+
+/// Las columnas `TIMESTAMP` se leen y escriben en UTC sin importar la zona del servidor,
+/// igual que `utils::tiempo::ahora()` del lado de Rust.
+#[derive(Debug)]
+struct SesionUtc;
+
+#[cfg(not(feature = "sqlite"))]
+impl CustomizeConnection<Conexion, Error> for SesionUtc {
+    fn on_acquire(&self, conn: &mut Conexion) -> Result<(), Error> {
+        use diesel::RunQueryDsl;
+
+        diesel::sql_query("SET time_zone = '+00:00'")
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::QueryError)
+    }
+}
+
+// SQLite no tiene zona horaria, CURRENT_TIMESTAMP ya es UTC; solo hace falta
+// activar las llaves foráneas, que vienen apagadas por conexión
+#[cfg(feature = "sqlite")]
+impl CustomizeConnection<Conexion, Error> for SesionUtc {
+    fn on_acquire(&self, conn: &mut Conexion) -> Result<(), Error> {
+        use diesel::connection::SimpleConnection;
+
+        conn.batch_execute("PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;")
+            .map_err(Error::QueryError)
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn create_pool() -> DbPool {
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let manager = ConnectionManager::<MysqlConnection>::new(db_url);
+    let manager = ConnectionManager::<Conexion>::new(db_url);
     Pool::builder()
+        .connection_customizer(Box::new(SesionUtc))
         .build(manager)
         .expect("Failed to create MySQL connection pool")
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "sqlite")]
+const MIGRACIONES_SQLITE: diesel_migrations::EmbeddedMigrations =
+    diesel_migrations::embed_migrations!("migrations_sqlite");
+
+/// Con SQLite `DATABASE_URL` es la ruta del archivo o `:memory:`. Las migraciones de
+/// `migrations_sqlite` se corren al crear el pool, así una base nueva queda lista.
+#[cfg(feature = "sqlite")]
+pub fn create_pool() -> DbPool {
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    pool_sqlite(db_url)
+}
+
+#[cfg(feature = "sqlite")]
+pub(crate) fn pool_sqlite(db_url: String) -> DbPool {
+    use diesel_migrations::MigrationHarness;
+
+    let en_memoria = db_url == ":memory:";
+    let manager = ConnectionManager::<Conexion>::new(db_url);
+
+    let mut builder = Pool::builder().connection_customizer(Box::new(SesionUtc));
+    if en_memoria {
+        // cada conexión a :memory: es una base distinta, el pool debe tener una sola y no soltarla
+        builder = builder.max_size(1).idle_timeout(None).max_lifetime(None);
+    }
+
+    let pool = builder
+        .build(manager)
+        .expect("Failed to create SQLite connection pool");
+
+    pool.get()
+        .expect("Failed to get SQLite connection")
+        .run_pending_migrations(MIGRACIONES_SQLITE)
+        .expect("Failed to run SQLite migrations");
+
+    pool
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::fs;
+    use std::path::Path;
+
+    use diesel::prelude::*;
+    use diesel::sql_types::{BigInt, Integer, Text};
+
+    use super::*;
+    use crate::db::repos::GrupoRepo;
+    use crate::db::repos::base::DieselRepos;
+    use crate::schema::*;
+
+    #[derive(QueryableByName)]
+    struct Nombre {
+        #[diesel(sql_type = Text)]
+        name: String,
+    }
+
+    #[derive(QueryableByName)]
+    struct Cuenta {
+        #[diesel(sql_type = BigInt)]
+        total: i64,
+    }
+
+    #[derive(QueryableByName)]
+    struct ColumnaSqlite {
+        #[diesel(sql_type = Text)]
+        name: String,
+        #[diesel(sql_type = Integer)]
+        notnull: i32,
+    }
+
+    #[derive(QueryableByName)]
+    struct IndiceSqlite {
+        #[diesel(sql_type = Text)]
+        name: String,
+        #[diesel(sql_type = Integer)]
+        unica: i32,
+    }
+
+    #[derive(QueryableByName)]
+    struct LlaveSqlite {
+        #[diesel(sql_type = Text)]
+        columna: String,
+        #[diesel(sql_type = Text)]
+        destino: String,
+    }
+
+    /// Forma de una tabla: columnas con su NOT NULL, índices (únicos o no) y llaves foráneas.
+    #[derive(Debug, Default, PartialEq)]
+    struct Tabla {
+        columnas: BTreeMap<String, bool>,
+        indices: BTreeSet<(bool, Vec<String>)>,
+        llaves: BTreeSet<(String, String)>,
+    }
+
+    // la primera migración de MySQL es el ejemplo de Diesel, la app no usa esa tabla
+    const SOLO_MYSQL: &[&str] = &["rocks"];
+
+    // migrations_sqlite junta en esta todo lo de migrations hasta la misma fecha
+    const ESQUEMA_INICIAL_SQLITE: &str = "2026-10-20-030000_esquema_inicial";
+
+    // columnas que espera schema.rs, sacadas del SELECT que arma Diesel
+    fn columnas_de_select(sql: &str) -> BTreeSet<String> {
+        let lista = sql.split(" FROM ").next().unwrap().trim_start_matches("SELECT ");
+
+        lista
+            .split(", ")
+            .map(|columna| columna.rsplit('.').next().unwrap().trim_matches('`').to_string())
+            .collect()
+    }
+
+    fn columnas_sqlite(conn: &mut Conexion, tabla: &str) -> BTreeSet<String> {
+        diesel::sql_query(format!("SELECT name FROM pragma_table_info('{}')", tabla))
+            .load::<Nombre>(conn)
+            .unwrap()
+            .into_iter()
+            .map(|columna| columna.name)
+            .collect()
+    }
+
+    fn entre_comillas(texto: &str) -> Vec<String> {
+        texto.split('`').skip(1).step_by(2).map(String::from).collect()
+    }
+
+    // una línea del cuerpo de CREATE TABLE o de ALTER TABLE, ya sin el ADD
+    fn aplicar_linea(tabla: &mut Tabla, linea: &str) {
+        let linea = linea.trim().trim_end_matches(',');
+        let linea = linea
+            .strip_prefix("ADD COLUMN ")
+            .or_else(|| linea.strip_prefix("ADD "))
+            .or_else(|| linea.strip_prefix("MODIFY "))
+            .unwrap_or(linea);
+
+        if let Some(resto) = linea.strip_prefix("INDEX ") {
+            tabla.indices.insert((false, entre_comillas(resto).split_off(1)));
+        } else if let Some((_, resto)) = linea.split_once("FOREIGN KEY") {
+            let nombres = entre_comillas(resto);
+            tabla.llaves.insert((nombres[0].clone(), nombres[1].clone()));
+        } else if linea.starts_with('`') {
+            let columna = entre_comillas(linea).remove(0);
+            let no_nula = linea.contains("NOT NULL") || linea.contains("PRIMARY KEY");
+            if linea.contains(" UNIQUE") {
+                tabla.indices.insert((true, vec![columna.clone()]));
+            }
+            tabla.columnas.insert(columna, no_nula);
+        }
+    }
+
+    /// Corre en orden los `up.sql` de `migrations` sobre un esquema vacío. Devuelve la forma
+    /// de cada tabla y cuáles se crearon ahí; las demás (grupos, cajas, ...) ya existían en
+    /// la base de MySQL y de ellas solo se conoce lo que las migraciones les agregan.
+    fn esquema_mysql() -> (BTreeMap<String, Tabla>, BTreeSet<String>) {
+        let mut tablas = BTreeMap::<String, Tabla>::new();
+        let mut creadas = BTreeSet::new();
+
+        for migracion in migraciones("migrations") {
+            let ruta = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("migrations")
+                .join(&migracion)
+                .join("up.sql");
+            let sql = fs::read_to_string(ruta).unwrap();
+            let sql = sql
+                .lines()
+                .filter(|linea| !linea.trim_start().starts_with("--"))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            for sentencia in sql.split(';').map(str::trim) {
+                let (creada, resto) = if let Some(resto) = sentencia.strip_prefix("CREATE TABLE ") {
+                    (true, resto)
+                } else if let Some(resto) = sentencia.strip_prefix("ALTER TABLE ") {
+                    (false, resto)
+                } else {
+                    continue;
+                };
+
+                let mut partes = resto.splitn(3, '`').skip(1);
+                let nombre = partes.next().unwrap().to_string();
+                let cuerpo = partes.next().unwrap();
+
+                if creada {
+                    creadas.insert(nombre.clone());
+                }
+                let tabla = tablas.entry(nombre).or_default();
+                for linea in cuerpo.lines() {
+                    aplicar_linea(tabla, linea);
+                }
+            }
+        }
+
+        for tabla in SOLO_MYSQL {
+            tablas.remove(*tabla);
+            creadas.remove(*tabla);
+        }
+        (tablas, creadas)
+    }
+
+    fn migraciones(carpeta: &str) -> BTreeSet<String> {
+        fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join(carpeta))
+            .unwrap()
+            .map(|entrada| entrada.unwrap().file_name().into_string().unwrap())
+            .collect()
+    }
+
+    fn tabla_sqlite(conn: &mut Conexion, nombre: &str) -> Tabla {
+        let columnas = diesel::sql_query(format!(
+            "SELECT name, \"notnull\" FROM pragma_table_info('{}')",
+            nombre
+        ))
+        .load::<ColumnaSqlite>(conn)
+        .unwrap()
+        .into_iter()
+        .map(|columna| (columna.name, columna.notnull != 0))
+        .collect();
+
+        // los índices de la llave primaria no cuentan, MySQL no los declara
+        let indices = diesel::sql_query(format!(
+            "SELECT name, \"unique\" AS unica FROM pragma_index_list('{}') WHERE origin != 'pk'",
+            nombre
+        ))
+        .load::<IndiceSqlite>(conn)
+        .unwrap()
+        .into_iter()
+        .map(|indice| {
+            let columnas = diesel::sql_query(format!(
+                "SELECT name FROM pragma_index_info('{}') ORDER BY seqno",
+                indice.name
+            ))
+            .load::<Nombre>(conn)
+            .unwrap()
+            .into_iter()
+            .map(|columna| columna.name)
+            .collect();
+            (indice.unica != 0, columnas)
+        })
+        .collect();
+
+        let llaves = diesel::sql_query(format!(
+            "SELECT \"from\" AS columna, \"table\" AS destino FROM pragma_foreign_key_list('{}')",
+            nombre
+        ))
+        .load::<LlaveSqlite>(conn)
+        .unwrap()
+        .into_iter()
+        .map(|llave| (llave.columna, llave.destino))
+        .collect();
+
+        Tabla {
+            columnas,
+            indices,
+            llaves,
+        }
+    }
+
+    macro_rules! comparar_tablas {
+        ($conn:expr, $($tabla:ident),+ $(,)?) => {{
+            let mut revisadas = 0;
+            $(
+                assert_eq!(
+                    columnas_sqlite($conn, stringify!($tabla)),
+                    columnas_de_select(
+                        &diesel::debug_query::<Backend, _>(
+                            &$tabla::table.select($tabla::all_columns)
+                        )
+                        .to_string()
+                    ),
+                    "migrations_sqlite y schema.rs no coinciden en `{}`",
+                    stringify!($tabla)
+                );
+                revisadas += 1;
+            )+
+            revisadas
+        }};
+    }
+
+    #[test]
+    fn las_migraciones_sqlite_coinciden_con_schema_rs() {
+        let pool = pool_sqlite(":memory:".to_string());
+        let conn = &mut pool.get().unwrap();
+
+        let revisadas = comparar_tablas!(
+            conn,
+            auditoria,
+            caja_cierre_errores,
+            caja_transiciones,
+            caja_cierre_resumen,
+            cajas,
+            cajeros,
+            grupos,
+            reembolsos,
+            sesiones_cajero,
+            impuestos_grupo,
+            kioskos,
+            transaccion_items,
+            transacciones,
+            usuarios,
+            usuarios_grupos,
+            webhooks,
+            webhooks_outbox,
+        );
+
+        // una tabla nueva en las migraciones tambien debe estar en la lista de arriba
+        let tablas = diesel::sql_query(
+            "SELECT COUNT(*) AS total FROM sqlite_master WHERE type = 'table' \
+             AND name NOT LIKE 'sqlite_%' AND name != '__diesel_schema_migrations'",
+        )
+        .get_result::<Cuenta>(conn)
+        .unwrap();
+        assert_eq!(tablas.total, revisadas);
+    }
+
+    #[test]
+    fn las_migraciones_sqlite_siguen_a_las_de_mysql() {
+        let mysql = migraciones("migrations");
+        let sqlite = migraciones("migrations_sqlite");
+
+        // después del esquema inicial cada migración de MySQL tiene su copia con el mismo nombre
+        let fecha_inicial = &ESQUEMA_INICIAL_SQLITE[..17];
+        let posteriores = mysql
+            .iter()
+            .filter(|nombre| &nombre[..17] > fecha_inicial)
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let copias = sqlite
+            .iter()
+            .filter(|nombre| nombre.as_str() != ESQUEMA_INICIAL_SQLITE)
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(copias, posteriores);
+
+        let (esperadas, creadas) = esquema_mysql();
+        let pool = pool_sqlite(":memory:".to_string());
+        let conn = &mut pool.get().unwrap();
+
+        let tablas = diesel::sql_query(
+            "SELECT name FROM sqlite_master WHERE type = 'table' \
+             AND name NOT LIKE 'sqlite_%' AND name != '__diesel_schema_migrations'",
+        )
+        .load::<Nombre>(conn)
+        .unwrap()
+        .into_iter()
+        .map(|tabla| tabla.name)
+        .collect::<BTreeSet<_>>();
+        assert_eq!(tablas, esperadas.keys().cloned().collect());
+
+        for (nombre, esperada) in &esperadas {
+            let actual = tabla_sqlite(conn, nombre);
+
+            if creadas.contains(nombre) {
+                assert_eq!(
+                    actual, *esperada,
+                    "migrations y migrations_sqlite no coinciden en `{}`",
+                    nombre
+                );
+                continue;
+            }
+
+            for (columna, no_nula) in &esperada.columnas {
+                assert_eq!(
+                    actual.columnas.get(columna),
+                    Some(no_nula),
+                    "`{}.{}` no coincide entre migrations y migrations_sqlite",
+                    nombre,
+                    columna
+                );
+            }
+            assert!(
+                esperada.indices.is_subset(&actual.indices),
+                "faltan índices de migrations en `{}`",
+                nombre
+            );
+            assert!(
+                esperada.llaves.is_subset(&actual.llaves),
+                "faltan llaves foráneas de migrations en `{}`",
+                nombre
+            );
+        }
+    }
+
+    #[test]
+    fn el_pool_en_memoria_comparte_una_sola_base() {
+        let pool = pool_sqlite(":memory:".to_string());
+
+        diesel::insert_into(grupos::table)
+            .values((
+                grupos::id_yappy.eq("grupo-1"),
+                grupos::nombre.eq("Grupo 1"),
+                grupos::api_key.eq("api"),
+                grupos::secret_key.eq("secret"),
+            ))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+
+        // el repositorio pide otra conexión del pool y debe ver la misma base
+        let grupos = DieselRepos::new(pool).listar(None).unwrap();
+        assert_eq!(grupos.len(), 1);
+        assert_eq!(grupos[0].nombre, "Grupo 1");
+        assert_eq!(grupos[0].version_credenciales, 0);
+    }
+}
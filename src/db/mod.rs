@@ -1,3 +1,4 @@
 pub mod conection;
 pub mod types;
-pub mod models;
\ No newline at end of file
+pub mod models;
+pub mod repos;
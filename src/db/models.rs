@@ -1,10 +1,11 @@
-use crate::schema::{cajas, grupos, kioskos, caja_cierre_errores, caja_cierre_resumen};
+use crate::schema::{auditoria, cajas, cajeros, grupos, impuestos_grupo, kioskos, caja_cierre_errores, caja_cierre_resumen, caja_transiciones, reembolsos, sesiones_cajero, transaccion_items, transacciones, usuarios, usuarios_grupos, webhooks, webhooks_outbox};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use crate::db::types::enums::CajasEstadoEnum;
+use crate::db::types::enums::{ActorEnum, CajasEstadoEnum, CajasTipoEnum, ModoRedondeoEnum, RolUsuarioEnum};
 use bigdecimal::BigDecimal;
 use serde_json::{Value};
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Error, Debug)]
 pub enum DbError {
@@ -12,8 +13,26 @@ pub enum DbError {
     DbError(#[from] diesel::result::Error),
 }
 
-#[derive(Debug,Serialize)]
+#[derive(Error, Debug)]
+pub enum TransicionError {
+    #[error("Database error: {0}")]
+    DbError(#[from] diesel::result::Error),
+    #[error("Transición inválida de {0:?} a {1:?}")]
+    Invalida(CajasEstadoEnum, CajasEstadoEnum),
+}
+
+#[derive(Error, Debug)]
+pub enum ReembolsoError {
+    #[error("Database error: {0}")]
+    DbError(#[from] diesel::result::Error),
+    #[error("El monto debe ser mayor a 0 y no exceder el saldo reembolsable ({0})")]
+    ExcedeSaldo(BigDecimal),
+}
+
+/// Cuerpo de error de la API, el mismo que arma `json_error`.
+#[derive(Debug,Serialize, ToSchema)]
 pub struct ErrorResponse {
+    pub success: bool,
     pub error: String,
 }
 
@@ -26,23 +45,37 @@ pub struct Kiosko {
     pub id_caja: i32,
     pub nombre: String,
     pub mac_address: String,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub ultima_conexion: Option<chrono::NaiveDateTime>,
+    pub version_app: Option<String>,
+    pub ip: Option<String>,
+    pub en_linea: bool,
 }
 
-#[derive(Debug,Queryable, Associations, Selectable, Serialize, Deserialize)]
+#[derive(Insertable)]
+#[diesel(table_name = kioskos)]
+pub struct NewKiosko {
+    pub id_caja: i32,
+    pub nombre: String,
+    pub mac_address: String,
+}
+
+#[derive(Debug,Queryable, Associations, Selectable, Serialize, Deserialize, Clone)]
 #[diesel(table_name = cajas)]
 #[diesel(belongs_to(Grupo, foreign_key = id_grupo))]
-#[diesel(check_for_backend(diesel::mysql::Mysql))]
+#[diesel(check_for_backend(crate::db::conection::Backend))]
 pub struct Caja {
     pub id: i32,
     pub id_grupo: i32,
     pub nombre_caja: String,
-    pub tipo: String,
+    pub tipo: CajasTipoEnum,
     pub token_autorizacion: Option<String>,
     pub transaccion_actual: Option<String>,
     pub estado: CajasEstadoEnum,
+    pub id_cajero_actual: Option<i32>,
 }
 
-#[derive(Debug,Queryable, Selectable, Identifiable, Serialize, Deserialize)]
+#[derive(Debug,Queryable, Selectable, Identifiable, Serialize, Deserialize, Clone)]
 #[diesel(table_name = grupos)]
 pub struct Grupo {
     pub id: i32,
@@ -50,6 +83,28 @@ pub struct Grupo {
     pub nombre: String,
     pub api_key: String,
     pub secret_key: String,
+    pub plantilla_recibo: Option<String>,
+    pub correos_cierre: Option<Value>,
+    // sube cada vez que cambian las credenciales, para que otros procesos las vuelvan a leer
+    pub version_credenciales: i32,
+}
+
+#[derive(Debug, Queryable, Selectable, Identifiable, Associations, Clone)]
+#[diesel(table_name = cajeros)]
+#[diesel(belongs_to(Grupo, foreign_key = id_grupo))]
+pub struct Cajero {
+    pub id: i32,
+    pub id_grupo: i32,
+    pub nombre: String,
+    pub pin_hash: String,
+    pub activo: bool,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = sesiones_cajero)]
+pub struct NewSesionCajero {
+    pub id_caja: i32,
+    pub id_cajero: i32,
 }
 
 #[derive(Insertable)]
@@ -59,6 +114,7 @@ pub struct NewCajaCierreResumen {
     pub tipo: String,
     pub monto: BigDecimal,
     pub transacciones: i32,
+    pub id_cajero: Option<i32>,
 }
 
 #[derive(Insertable)]
@@ -66,4 +122,416 @@ pub struct NewCajaCierreResumen {
 pub struct NewCajaCierreError {
     pub id_caja: i32,
     pub respuesta_json: Value,
-}
\ No newline at end of file
+    pub id_cajero: Option<i32>,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize, Clone)]
+#[diesel(table_name = caja_transiciones)]
+pub struct CajaTransicion {
+    pub id: i32,
+    pub id_caja: i32,
+    pub estado_anterior: CajasEstadoEnum,
+    pub estado_nuevo: CajasEstadoEnum,
+    pub actor: ActorEnum,
+    pub motivo: String,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub fecha: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = caja_transiciones)]
+pub struct NewCajaTransicion {
+    pub id_caja: i32,
+    pub estado_anterior: CajasEstadoEnum,
+    pub estado_nuevo: CajasEstadoEnum,
+    pub actor: ActorEnum,
+    pub motivo: String,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize, ToSchema, Clone)]
+#[diesel(table_name = auditoria)]
+pub struct Auditoria {
+    pub id: i32,
+    pub actor: ActorEnum,
+    pub id_kiosko: Option<i32>,
+    pub id_caja: Option<i32>,
+    pub accion: String,
+    pub resumen: Value,
+    pub codigo_http: Option<i32>,
+    pub codigo_yappy: Option<String>,
+    pub resultado: String,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub fecha: Option<chrono::NaiveDateTime>,
+    pub id_cajero: Option<i32>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FiltroAuditoria {
+    pub actor: Option<ActorEnum>,
+    pub id_caja: Option<i32>,
+    pub id_kiosko: Option<i32>,
+    pub accion: Option<String>,
+    /// `X-Request-Id` de la solicitud auditada.
+    pub request_id: Option<String>,
+    /// RFC 3339; sin zona se toma como hora de Panamá.
+    #[serde(default, deserialize_with = "crate::utils::tiempo::deserializar_opcion")]
+    pub desde: Option<chrono::NaiveDateTime>,
+    #[serde(default, deserialize_with = "crate::utils::tiempo::deserializar_opcion")]
+    pub hasta: Option<chrono::NaiveDateTime>,
+    /// Día completo de Panamá (`AAAA-MM-DD`).
+    pub dia: Option<chrono::NaiveDate>,
+    pub limite: Option<i64>,
+}
+
+
+#[derive(Insertable)]
+#[diesel(table_name = auditoria)]
+pub struct NewAuditoria {
+    pub actor: ActorEnum,
+    pub id_kiosko: Option<i32>,
+    pub id_caja: Option<i32>,
+    pub accion: String,
+    pub resumen: Value,
+    pub codigo_http: Option<i32>,
+    pub codigo_yappy: Option<String>,
+    pub resultado: String,
+    pub id_cajero: Option<i32>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Queryable, Selectable, Identifiable, Associations, Serialize, Clone)]
+#[diesel(table_name = transacciones)]
+#[diesel(belongs_to(Caja, foreign_key = id_caja))]
+pub struct Transaccion {
+    pub id: i32,
+    pub id_caja: i32,
+    pub id_cajero: Option<i32>,
+    pub id_transaccion_yappy: String,
+    pub id_orden: Option<String>,
+    pub tipo_qr: String,
+    pub subtotal: BigDecimal,
+    pub impuesto: BigDecimal,
+    pub propina: BigDecimal,
+    pub descuento: BigDecimal,
+    pub total: BigDecimal,
+    pub estado: String,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub fecha: Option<chrono::NaiveDateTime>,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub fecha_actualizacion: Option<chrono::NaiveDateTime>,
+    pub motivo_cancelacion: Option<String>,
+    #[serde(skip_serializing)]
+    pub qr_hash: Option<String>,
+    pub tasa_impuesto: Option<BigDecimal>,
+    pub discrepancia_impuesto: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = transacciones)]
+pub struct NewTransaccion {
+    pub id_caja: i32,
+    pub id_cajero: Option<i32>,
+    pub id_transaccion_yappy: String,
+    pub id_orden: Option<String>,
+    pub tipo_qr: String,
+    pub subtotal: BigDecimal,
+    pub impuesto: BigDecimal,
+    pub propina: BigDecimal,
+    pub descuento: BigDecimal,
+    pub total: BigDecimal,
+    pub qr_hash: Option<String>,
+    pub tasa_impuesto: Option<BigDecimal>,
+    pub discrepancia_impuesto: Option<String>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Queryable, Selectable, Identifiable, Associations, Serialize, Clone)]
+#[diesel(table_name = transaccion_items)]
+#[diesel(belongs_to(Transaccion, foreign_key = id_transaccion))]
+pub struct TransaccionItem {
+    pub id: i32,
+    pub id_transaccion: i32,
+    pub sku: String,
+    pub descripcion: String,
+    pub cantidad: i32,
+    pub precio_unitario: BigDecimal,
+    pub descuento: BigDecimal,
+    pub exento_itbms: bool,
+    pub impuesto: BigDecimal,
+    pub total: BigDecimal,
+    pub categoria: Option<String>,
+    pub tasa_impuesto: BigDecimal,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = transaccion_items)]
+pub struct NewTransaccionItem {
+    pub id_transaccion: i32,
+    pub sku: String,
+    pub descripcion: String,
+    pub cantidad: i32,
+    pub precio_unitario: BigDecimal,
+    pub descuento: BigDecimal,
+    pub exento_itbms: bool,
+    pub impuesto: BigDecimal,
+    pub total: BigDecimal,
+    pub categoria: Option<String>,
+    pub tasa_impuesto: BigDecimal,
+}
+
+#[derive(Debug, Queryable, Selectable, Identifiable, Associations, Serialize, Clone)]
+#[diesel(table_name = reembolsos)]
+#[diesel(belongs_to(Transaccion, foreign_key = id_transaccion))]
+pub struct Reembolso {
+    pub id: i32,
+    pub id_transaccion: i32,
+    pub monto: BigDecimal,
+    pub motivo: String,
+    pub actor: ActorEnum,
+    pub id_cajero: Option<i32>,
+    pub exitoso: bool,
+    pub codigo_yappy: Option<String>,
+    pub respuesta_json: Value,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub fecha: Option<chrono::NaiveDateTime>,
+    pub request_id: Option<String>,
+    /// Monto apartado mientras Yappy responde; cuenta contra el saldo reembolsable.
+    pub pendiente: bool,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = reembolsos)]
+pub struct NewReembolso {
+    pub id_transaccion: i32,
+    pub monto: BigDecimal,
+    pub motivo: String,
+    pub actor: ActorEnum,
+    pub id_cajero: Option<i32>,
+    pub exitoso: bool,
+    pub codigo_yappy: Option<String>,
+    pub respuesta_json: Value,
+    pub request_id: Option<String>,
+    pub pendiente: bool,
+}
+
+#[derive(Debug, Queryable, Selectable, Identifiable, Serialize, Clone)]
+#[diesel(table_name = impuestos_grupo)]
+#[diesel(primary_key(id_grupo))]
+pub struct ImpuestosGrupo {
+    pub id_grupo: i32,
+    pub tasa_general: BigDecimal,
+    pub tasas_categoria: Value,
+    pub categorias_exentas: Value,
+    pub modo_redondeo: ModoRedondeoEnum,
+}
+
+#[derive(Debug, Queryable, Selectable, Identifiable, Serialize, ToSchema, Clone)]
+#[diesel(table_name = usuarios)]
+pub struct Usuario {
+    pub id: i32,
+    pub nombre: String,
+    pub email: String,
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    pub rol: RolUsuarioEnum,
+    pub activo: bool,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub creado: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = usuarios)]
+pub struct NewUsuario {
+    pub nombre: String,
+    pub email: String,
+    pub password_hash: String,
+    pub rol: RolUsuarioEnum,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = usuarios_grupos)]
+pub struct NewUsuarioGrupo {
+    pub id_usuario: i32,
+    pub id_grupo: i32,
+}
+
+#[derive(Debug, Queryable, Selectable, Identifiable, Serialize, Clone)]
+#[diesel(table_name = webhooks)]
+pub struct Webhook {
+    pub id: i32,
+    pub id_grupo: i32,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secreto: String,
+    pub eventos: Value,
+    pub activo: bool,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub creado: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhooks)]
+pub struct NewWebhook {
+    pub id_grupo: i32,
+    pub url: String,
+    pub secreto: String,
+    pub eventos: Value,
+}
+
+#[derive(Debug, Queryable, Selectable, Identifiable, Serialize, Clone)]
+#[diesel(table_name = webhooks_outbox)]
+pub struct WebhookOutbox {
+    pub id: i32,
+    pub id_webhook: i32,
+    pub evento: String,
+    pub payload: Value,
+    pub estado: String,
+    pub intentos: i32,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar")]
+    pub proximo_intento: chrono::NaiveDateTime,
+    pub ultimo_error: Option<String>,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub creado: Option<chrono::NaiveDateTime>,
+    #[serde(serialize_with = "crate::utils::tiempo::serializar_opcion")]
+    pub entregado: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhooks_outbox)]
+pub struct NewWebhookOutbox {
+    pub id_webhook: i32,
+    pub evento: String,
+    pub payload: Value,
+    pub proximo_intento: chrono::NaiveDateTime,
+}
+
+/// Venta pendiente con las credenciales de su grupo, para consultarla en Yappy.
+#[derive(Queryable, Debug)]
+pub struct TransaccionPendiente {
+    pub id_transaccion_yappy: String,
+    pub id_caja: i32,
+    pub api_key: String,
+    pub secret_key: String,
+    pub token_autorizacion: Option<String>,
+}
+
+#[derive(Queryable, Debug, Serialize)]
+pub struct CajaWithCreds {
+    pub id: i32,
+    pub nombre_caja: String,
+    pub estado: CajasEstadoEnum,
+    pub api_key: String,
+    pub secret_key: String,
+    pub token_autorizacion: Option<String>,
+}
+
+/// Qué cajas entran en un cierre.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlcanceCierre {
+    Todas,
+    Grupos(Vec<i32>),
+    Caja(i32),
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct KioskoInfo {
+    // From kiosko
+    pub id_kiosko: i32,
+    pub nombre: String,
+
+    // From caja
+    pub id_caja: i32,
+    pub id_grupo: i32,
+    pub nombre_caja: String,
+    pub token_autorizacion: Option<String>,
+    pub estado: CajasEstadoEnum, // or String if you serialize it as string
+    pub tipo: CajasTipoEnum,
+
+    // From cajero en turno
+    pub id_cajero: Option<i32>,
+    pub nombre_cajero: Option<String>,
+
+    // From grupo
+    pub id_yappy: String,
+    pub nombre_grupo: String,
+    pub api_key: String,
+    pub secret_key: String,
+    pub version_credenciales: i32,
+}
+
+impl KioskoInfo {
+    pub fn armar(kiosko: Kiosko, caja: Caja, grupo: Grupo, nombre_cajero: Option<String>) -> Self {
+        KioskoInfo {
+            id_kiosko: kiosko.id,
+            nombre: kiosko.nombre,
+            id_caja: caja.id,
+            id_grupo: caja.id_grupo,
+            nombre_caja: caja.nombre_caja,
+            token_autorizacion: caja.token_autorizacion,
+            estado: caja.estado,
+            tipo: caja.tipo,
+            id_cajero: caja.id_cajero_actual,
+            nombre_cajero,
+            id_yappy: grupo.id_yappy,
+            nombre_grupo: grupo.nombre,
+            api_key: grupo.api_key,
+            secret_key: grupo.secret_key,
+            version_credenciales: grupo.version_credenciales,
+        }
+    }
+
+    /// Quién actúa cuando la petición llega desde este dispositivo.
+    pub fn actor(&self) -> ActorEnum {
+        match self.tipo {
+            CajasTipoEnum::Cajero => ActorEnum::Cajero,
+            CajasTipoEnum::Kiosko => ActorEnum::Kiosko,
+        }
+    }
+}
+
+/// Eventos que se pueden suscribir por grupo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventoWebhook {
+    PagoCompletado,
+    PagoReembolsado,
+    CajaAbierta,
+    CierreFinalizado,
+}
+
+impl EventoWebhook {
+    pub const TODOS: [EventoWebhook; 4] = [
+        EventoWebhook::PagoCompletado,
+        EventoWebhook::PagoReembolsado,
+        EventoWebhook::CajaAbierta,
+        EventoWebhook::CierreFinalizado,
+    ];
+
+    pub fn nombre(&self) -> &'static str {
+        match self {
+            EventoWebhook::PagoCompletado => "pago.completado",
+            EventoWebhook::PagoReembolsado => "pago.reembolsado",
+            EventoWebhook::CajaAbierta => "caja.abierta",
+            EventoWebhook::CierreFinalizado => "cierre.finalizado",
+        }
+    }
+
+    pub fn desde_nombre(nombre: &str) -> Option<Self> {
+        Self::TODOS.into_iter().find(|evento| evento.nombre() == nombre)
+    }
+}
+
+impl Webhook {
+    /// Una lista de eventos vacía suscribe el webhook a todos.
+    pub fn escucha(&self, evento: EventoWebhook) -> bool {
+        match self.eventos.as_array() {
+            Some(eventos) if !eventos.is_empty() => {
+                eventos.iter().any(|e| e.as_str() == Some(evento.nombre()))
+            }
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,1187 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde_json::{Value, json};
+
+use crate::db::conection::{Conexion, DbPool};
+use crate::db::models::{
+    AlcanceCierre, Auditoria, Caja, CajaWithCreds, Cajero, EventoWebhook, FiltroAuditoria, Grupo,
+    ImpuestosGrupo, Kiosko, KioskoInfo, NewAuditoria, NewCajaCierreError, NewCajaCierreResumen,
+    NewCajaTransicion, NewReembolso, NewSesionCajero, NewTransaccion, NewTransaccionItem,
+    NewUsuario, NewUsuarioGrupo, NewWebhookOutbox, Reembolso, ReembolsoError, Transaccion,
+    TransaccionItem, TransaccionPendiente, TransicionError, Usuario, Webhook, WebhookOutbox,
+};
+use crate::db::repos::{
+    AuditoriaRepo, CajaRepo, CierreRepo, ContextoVigente, GrupoRepo, KioskoRepo, ReferenciaVenta,
+    TransaccionRepo, UsuarioRepo, WebhookRepo,
+};
+use crate::db::types::enums::{ActorEnum, CajasEstadoEnum};
+use crate::middlewares::correlacion;
+use crate::schema::{
+    auditoria, caja_cierre_errores, caja_cierre_resumen, caja_transiciones, cajas, cajeros, grupos,
+    impuestos_grupo, kioskos, reembolsos, sesiones_cajero, transaccion_items, transacciones,
+    usuarios, usuarios_grupos, webhooks, webhooks_outbox,
+};
+use crate::utils::tiempo;
+
+/// Repositorios sobre la base del pool.
+pub struct DieselRepos {
+    pool: DbPool,
+}
+
+impl DieselRepos {
+    pub fn new(pool: DbPool) -> Self {
+        DieselRepos { pool }
+    }
+}
+
+impl KioskoRepo for DieselRepos {
+    fn info_por_mac(&self, mac_address: &str) -> QueryResult<KioskoInfo> {
+        let mut conn = self.pool.get().unwrap();
+
+        let (kiosko, caja, grupo, nombre_cajero) = kioskos::table
+            .inner_join(cajas::table.inner_join(grupos::table).left_join(cajeros::table))
+            .filter(kioskos::mac_address.eq(mac_address))
+            .select((
+                Kiosko::as_select(),
+                Caja::as_select(),
+                Grupo::as_select(),
+                cajeros::nombre.nullable(),
+            ))
+            .first::<(Kiosko, Caja, Grupo, Option<String>)>(&mut conn)?;
+
+        Ok(KioskoInfo::armar(kiosko, caja, grupo, nombre_cajero))
+    }
+
+    fn vigente_por_mac(&self, mac_address: &str) -> QueryResult<ContextoVigente> {
+        let mut conn = self.pool.get().unwrap();
+
+        let (id_caja, estado, token_autorizacion, id_cajero, version_credenciales) = kioskos::table
+            .inner_join(cajas::table.inner_join(grupos::table))
+            .filter(kioskos::mac_address.eq(mac_address))
+            .select((
+                cajas::id,
+                cajas::estado,
+                cajas::token_autorizacion,
+                cajas::id_cajero_actual,
+                grupos::version_credenciales,
+            ))
+            .first::<(i32, CajasEstadoEnum, Option<String>, Option<i32>, i32)>(&mut conn)?;
+
+        Ok(ContextoVigente {
+            id_caja,
+            estado,
+            token_autorizacion,
+            id_cajero,
+            version_credenciales,
+        })
+    }
+
+    fn listar(&self) -> QueryResult<Vec<Kiosko>> {
+        let mut conn = self.pool.get().unwrap();
+
+        kioskos::table.select(Kiosko::as_select()).load(&mut conn)
+    }
+
+    fn marcar_desconectados(&self, limite: NaiveDateTime) -> QueryResult<usize> {
+        let mut conn = self.pool.get().unwrap();
+
+        diesel::update(
+            kioskos::table
+                .filter(kioskos::en_linea.eq(true))
+                .filter(
+                    kioskos::ultima_conexion
+                        .is_null()
+                        .or(kioskos::ultima_conexion.lt(limite)),
+                ),
+        )
+        .set(kioskos::en_linea.eq(false))
+        .execute(&mut conn)
+    }
+
+    fn registrar_latido(
+        &self,
+        id_kiosko: i32,
+        ip: String,
+        version_app: Option<String>,
+        ahora: NaiveDateTime,
+    ) -> QueryResult<()> {
+        let mut conn = self.pool.get().unwrap();
+
+        diesel::update(kioskos::table.filter(kioskos::id.eq(id_kiosko)))
+            .set((
+                kioskos::ultima_conexion.eq(ahora),
+                version_app.map(|version| kioskos::version_app.eq(version)),
+                kioskos::ip.eq(ip),
+                kioskos::en_linea.eq(true),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+}
+
+impl CajaRepo for DieselRepos {
+    fn buscar(&self, id_caja: i32) -> QueryResult<Caja> {
+        let mut conn = self.pool.get().unwrap();
+
+        cajas::table
+            .filter(cajas::id.eq(id_caja))
+            .select(Caja::as_select())
+            .first(&mut conn)
+    }
+
+    fn listar(&self) -> QueryResult<Vec<Caja>> {
+        let mut conn = self.pool.get().unwrap();
+
+        cajas::table.select(Caja::as_select()).load(&mut conn)
+    }
+
+    fn fijar_transaccion_actual(&self, id_caja: i32, transaccion: Option<&str>) -> QueryResult<()> {
+        let mut conn = self.pool.get().unwrap();
+
+        diesel::update(cajas::table.filter(cajas::id.eq(id_caja)))
+            .set(cajas::transaccion_actual.eq(transaccion))
+            .execute(&mut conn)
+            .map(|_| ())
+    }
+
+    fn soltar_transaccion_actual(&self, id_caja: i32) -> QueryResult<Option<String>> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            let referencia: Option<String> = cajas::table
+                .filter(cajas::id.eq(id_caja))
+                .select(cajas::transaccion_actual)
+                .first(conn)?;
+
+            diesel::update(cajas::table.filter(cajas::id.eq(id_caja)))
+                .set(cajas::transaccion_actual.eq(None::<String>))
+                .execute(conn)?;
+
+            Ok(referencia)
+        })
+    }
+
+    fn buscar_cajero(&self, id_cajero: i32) -> QueryResult<Cajero> {
+        let mut conn = self.pool.get().unwrap();
+
+        cajeros::table
+            .filter(cajeros::id.eq(id_cajero))
+            .select(Cajero::as_select())
+            .first(&mut conn)
+    }
+
+    fn turno_en_otra_caja(&self, id_cajero: i32, id_caja: i32) -> QueryResult<bool> {
+        let mut conn = self.pool.get().unwrap();
+
+        let en_otra_caja: i64 = cajas::table
+            .filter(cajas::id_cajero_actual.eq(id_cajero))
+            .filter(cajas::id.ne(id_caja))
+            .count()
+            .get_result(&mut conn)?;
+
+        Ok(en_otra_caja > 0)
+    }
+
+    fn iniciar_turno(&self, id_caja: i32, id_cajero: i32) -> QueryResult<()> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            diesel::update(cajas::table.filter(cajas::id.eq(id_caja)))
+                .set(cajas::id_cajero_actual.eq(id_cajero))
+                .execute(conn)?;
+
+            diesel::insert_into(sesiones_cajero::table)
+                .values(&NewSesionCajero { id_caja, id_cajero })
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    fn revertir_turno(&self, id_caja: i32) -> QueryResult<()> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            diesel::update(cajas::table.filter(cajas::id.eq(id_caja)))
+                .set(cajas::id_cajero_actual.eq(None::<i32>))
+                .execute(conn)?;
+
+            diesel::update(sesiones_cajero::table)
+                .filter(sesiones_cajero::id_caja.eq(id_caja))
+                .filter(sesiones_cajero::fin.is_null())
+                .set(sesiones_cajero::fin.eq(tiempo::ahora()))
+                .execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    fn transicionar(
+        &self,
+        id_caja: i32,
+        nuevo: CajasEstadoEnum,
+        actor: ActorEnum,
+        motivo: String,
+        evento: Option<(EventoWebhook, Value)>,
+    ) -> Result<CajasEstadoEnum, TransicionError> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            let anterior = transicionar_caja(conn, id_caja, nuevo, actor, motivo)?;
+            if let Some((evento, datos)) = evento {
+                encolar_evento(conn, id_caja, evento, datos)?;
+            }
+            Ok(anterior)
+        })
+    }
+
+    fn recuperar(
+        &self,
+        id_caja: i32,
+        actor: ActorEnum,
+        motivo: String,
+    ) -> Result<Option<CajasEstadoEnum>, TransicionError> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            let Some(destino) = estado_bloqueado(conn, id_caja)?.recuperacion() else {
+                return Ok(None);
+            };
+
+            transicionar_caja(conn, id_caja, destino.clone(), actor, motivo)?;
+            Ok(Some(destino))
+        })
+    }
+
+    fn a_medias(&self) -> QueryResult<Vec<(i32, CajasEstadoEnum, Option<NaiveDateTime>)>> {
+        let mut conn = self.pool.get().unwrap();
+
+        let cajas: Vec<(i32, CajasEstadoEnum)> = cajas::table
+            .filter(cajas::estado.eq_any([CajasEstadoEnum::Abriendo, CajasEstadoEnum::Cerrando]))
+            .select((cajas::id, cajas::estado))
+            .load(&mut conn)?;
+
+        cajas
+            .into_iter()
+            .map(|(id_caja, estado)| {
+                let desde: Option<NaiveDateTime> = caja_transiciones::table
+                    .filter(caja_transiciones::id_caja.eq(id_caja))
+                    .select(diesel::dsl::max(caja_transiciones::fecha))
+                    .first(&mut conn)?;
+
+                Ok((id_caja, estado, desde))
+            })
+            .collect()
+    }
+
+    fn terminar_apertura(
+        &self,
+        id_caja: i32,
+        token: Option<&str>,
+        actor: ActorEnum,
+        motivo: String,
+        datos: Value,
+    ) -> Result<CajasEstadoEnum, TransicionError> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            diesel::update(cajas::table.filter(cajas::id.eq(id_caja)))
+                .set(cajas::token_autorizacion.eq(token))
+                .execute(conn)?;
+
+            // sin token la sesion no quedo abierta en Yappy
+            if token.is_some() {
+                transicionar_caja(conn, id_caja, CajasEstadoEnum::Abierto, actor, motivo)?;
+                encolar_evento(conn, id_caja, EventoWebhook::CajaAbierta, datos)?;
+                Ok(CajasEstadoEnum::Abierto)
+            } else {
+                transicionar_caja(conn, id_caja, CajasEstadoEnum::Cerrado, actor, motivo)?;
+                Ok(CajasEstadoEnum::Cerrado)
+            }
+        })
+    }
+
+    fn terminar_cierre(
+        &self,
+        id_caja: i32,
+        actor: ActorEnum,
+        motivo: String,
+        datos: Value,
+    ) -> Result<CajasEstadoEnum, TransicionError> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            diesel::update(cajas::table.filter(cajas::id.eq(id_caja)))
+                .set((
+                    cajas::token_autorizacion.eq(None::<String>),
+                    cajas::id_cajero_actual.eq(None::<i32>),
+                ))
+                .execute(conn)?;
+
+            // cerrar la sesion en Yappy termina el turno del cajero
+            diesel::update(sesiones_cajero::table)
+                .filter(sesiones_cajero::id_caja.eq(id_caja))
+                .filter(sesiones_cajero::fin.is_null())
+                .set(sesiones_cajero::fin.eq(tiempo::ahora()))
+                .execute(conn)?;
+
+            transicionar_caja(conn, id_caja, CajasEstadoEnum::Cerrado, actor, motivo)?;
+            encolar_evento(conn, id_caja, EventoWebhook::CierreFinalizado, datos)?;
+            Ok(CajasEstadoEnum::Cerrado)
+        })
+    }
+}
+
+impl GrupoRepo for DieselRepos {
+    fn buscar(&self, id_grupo: i32) -> QueryResult<Grupo> {
+        let mut conn = self.pool.get().unwrap();
+
+        grupos::table
+            .find(id_grupo)
+            .select(Grupo::as_select())
+            .first(&mut conn)
+    }
+
+    fn listar(&self, visibles: Option<&[i32]>) -> QueryResult<Vec<Grupo>> {
+        let mut conn = self.pool.get().unwrap();
+
+        let mut query = grupos::table.select(Grupo::as_select()).into_boxed();
+        if let Some(visibles) = visibles {
+            query = query.filter(grupos::id.eq_any(visibles));
+        }
+
+        query.load(&mut conn)
+    }
+
+    fn impuestos(&self, id_grupo: i32) -> QueryResult<Option<ImpuestosGrupo>> {
+        let mut conn = self.pool.get().unwrap();
+
+        impuestos_grupo::table
+            .find(id_grupo)
+            .select(ImpuestosGrupo::as_select())
+            .first(&mut conn)
+            .optional()
+    }
+}
+
+impl CierreRepo for DieselRepos {
+    fn cajas_por_cerrar(&self, alcance: &AlcanceCierre) -> QueryResult<Vec<CajaWithCreds>> {
+        let mut conn = self.pool.get().unwrap();
+
+        let mut query = cajas::table
+            .inner_join(grupos::table.on(grupos::id.eq(cajas::id_grupo)))
+            .filter(cajas::estado.eq_any([
+                CajasEstadoEnum::Abierto,
+                CajasEstadoEnum::ErrorCierre,
+            ]))
+            .select((
+                cajas::id,
+                cajas::nombre_caja,
+                cajas::estado,
+                grupos::api_key,
+                grupos::secret_key,
+                cajas::token_autorizacion,
+            ))
+            .into_boxed();
+
+        match alcance {
+            AlcanceCierre::Todas => {}
+            AlcanceCierre::Grupos(ids) => query = query.filter(cajas::id_grupo.eq_any(ids)),
+            AlcanceCierre::Caja(id_caja) => query = query.filter(cajas::id.eq(*id_caja)),
+        }
+
+        query.load(&mut conn)
+    }
+
+    fn guardar_resumen(&self, resumen: NewCajaCierreResumen) -> QueryResult<()> {
+        let mut conn = self.pool.get().unwrap();
+
+        diesel::insert_into(caja_cierre_resumen::table)
+            .values(&resumen)
+            .execute(&mut conn)
+            .map(|_| ())
+    }
+
+    fn guardar_error(&self, error: NewCajaCierreError) -> QueryResult<()> {
+        let mut conn = self.pool.get().unwrap();
+
+        diesel::insert_into(caja_cierre_errores::table)
+            .values(&error)
+            .execute(&mut conn)
+            .map(|_| ())
+    }
+    fn ultimos_ids(&self) -> QueryResult<(i32, i32)> {
+        let mut conn = self.pool.get().unwrap();
+
+        let resumen: Option<i32> = caja_cierre_resumen::table
+            .select(diesel::dsl::max(caja_cierre_resumen::id))
+            .first(&mut conn)?;
+        let errores: Option<i32> = caja_cierre_errores::table
+            .select(diesel::dsl::max(caja_cierre_errores::id))
+            .first(&mut conn)?;
+
+        Ok((resumen.unwrap_or(0), errores.unwrap_or(0)))
+    }
+
+    fn guardados_desde(
+        &self,
+        ids_cajas: &[i32],
+        (desde_resumen, desde_errores): (i32, i32),
+    ) -> QueryResult<(Vec<NewCajaCierreResumen>, Vec<NewCajaCierreError>)> {
+        let mut conn = self.pool.get().unwrap();
+
+        let resumen: Vec<(i32, String, BigDecimal, i32, Option<i32>)> = caja_cierre_resumen::table
+            .filter(caja_cierre_resumen::id.gt(desde_resumen))
+            .filter(caja_cierre_resumen::id_caja.eq_any(ids_cajas))
+            .select((
+                caja_cierre_resumen::id_caja,
+                caja_cierre_resumen::tipo,
+                caja_cierre_resumen::monto,
+                caja_cierre_resumen::transacciones,
+                caja_cierre_resumen::id_cajero,
+            ))
+            .order(caja_cierre_resumen::id)
+            .load(&mut conn)?;
+
+        let errores: Vec<(i32, Value, Option<i32>)> = caja_cierre_errores::table
+            .filter(caja_cierre_errores::id.gt(desde_errores))
+            .filter(caja_cierre_errores::id_caja.eq_any(ids_cajas))
+            .select((
+                caja_cierre_errores::id_caja,
+                caja_cierre_errores::respuesta_json,
+                caja_cierre_errores::id_cajero,
+            ))
+            .order(caja_cierre_errores::id)
+            .load(&mut conn)?;
+
+        Ok((
+            resumen
+                .into_iter()
+                .map(|(id_caja, tipo, monto, transacciones, id_cajero)| NewCajaCierreResumen {
+                    id_caja,
+                    tipo,
+                    monto,
+                    transacciones,
+                    id_cajero,
+                })
+                .collect(),
+            errores
+                .into_iter()
+                .map(|(id_caja, respuesta_json, id_cajero)| NewCajaCierreError {
+                    id_caja,
+                    respuesta_json,
+                    id_cajero,
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl TransaccionRepo for DieselRepos {
+    fn buscar(&self, id_transaccion_yappy: &str) -> QueryResult<Transaccion> {
+        let mut conn = self.pool.get().unwrap();
+
+        transacciones::table
+            .filter(transacciones::id_transaccion_yappy.eq(id_transaccion_yappy))
+            .select(Transaccion::as_select())
+            .first(&mut conn)
+    }
+
+    fn de_grupo(&self, id_grupo: i32, referencia: &ReferenciaVenta) -> QueryResult<Transaccion> {
+        let mut conn = self.pool.get().unwrap();
+
+        let query = transacciones::table
+            .inner_join(cajas::table)
+            .filter(cajas::id_grupo.eq(id_grupo))
+            .select(Transaccion::as_select())
+            .order(transacciones::id.desc())
+            .into_boxed();
+
+        let query = match referencia {
+            ReferenciaVenta::Id(id) => query.filter(transacciones::id.eq(*id)),
+            ReferenciaVenta::Yappy(id_transaccion) => {
+                query.filter(transacciones::id_transaccion_yappy.eq(id_transaccion.clone()))
+            }
+            ReferenciaVenta::Orden(id_orden) => query
+                .filter(transacciones::id_orden.eq(id_orden.clone()))
+                .filter(transacciones::estado.eq("COMPLETED")),
+        };
+
+        query.first(&mut conn)
+    }
+
+    fn buscar_por_id(&self, id: i32) -> QueryResult<Transaccion> {
+        let mut conn = self.pool.get().unwrap();
+
+        transacciones::table
+            .find(id)
+            .select(Transaccion::as_select())
+            .first(&mut conn)
+    }
+
+    fn items(&self, id_transaccion: i32) -> QueryResult<Vec<TransaccionItem>> {
+        let mut conn = self.pool.get().unwrap();
+
+        transaccion_items::table
+            .filter(transaccion_items::id_transaccion.eq(id_transaccion))
+            .order(transaccion_items::id)
+            .select(TransaccionItem::as_select())
+            .load(&mut conn)
+    }
+
+    fn registrar(&self, nueva: NewTransaccion, items: Vec<NewTransaccionItem>) -> QueryResult<Transaccion> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            diesel::insert_into(transacciones::table)
+                .values(&nueva)
+                .execute(conn)?;
+
+            let transaccion = transacciones::table
+                .filter(transacciones::id_transaccion_yappy.eq(&nueva.id_transaccion_yappy))
+                .select(Transaccion::as_select())
+                .first::<Transaccion>(conn)?;
+
+            if !items.is_empty() {
+                let items: Vec<_> = items
+                    .into_iter()
+                    .map(|item| NewTransaccionItem { id_transaccion: transaccion.id, ..item })
+                    .collect();
+
+                diesel::insert_into(transaccion_items::table)
+                    .values(&items)
+                    .execute(conn)?;
+            }
+
+            Ok(transaccion)
+        })
+    }
+
+    fn actualizar_estado(&self, id_transaccion_yappy: &str, estado: &str) -> QueryResult<usize> {
+        let mut conn = self.pool.get().unwrap();
+
+        actualizar_estado_transaccion(&mut conn, id_transaccion_yappy, estado)
+    }
+
+    fn finalizar(
+        &self,
+        id_caja: i32,
+        id_transaccion_yappy: &str,
+        estado: &str,
+        motivo: Option<&str>,
+    ) -> QueryResult<()> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            actualizar_estado_transaccion(conn, id_transaccion_yappy, estado)?;
+
+            if let Some(motivo) = motivo {
+                diesel::update(transacciones::table)
+                    .filter(transacciones::id_transaccion_yappy.eq(id_transaccion_yappy))
+                    .set(transacciones::motivo_cancelacion.eq(motivo))
+                    .execute(conn)?;
+            }
+
+            diesel::update(
+                cajas::table
+                    .filter(cajas::id.eq(id_caja))
+                    .filter(cajas::transaccion_actual.eq(id_transaccion_yappy)),
+            )
+            .set(cajas::transaccion_actual.eq(None::<String>))
+            .execute(conn)
+            .map(|_| ())
+        })
+    }
+
+    fn pendientes(&self, limite: NaiveDateTime) -> QueryResult<Vec<TransaccionPendiente>> {
+        let mut conn = self.pool.get().unwrap();
+
+        transacciones::table
+            .inner_join(cajas::table.inner_join(grupos::table))
+            .filter(transacciones::estado.eq("PENDING"))
+            .filter(transacciones::fecha.lt(limite))
+            .select((
+                transacciones::id_transaccion_yappy,
+                transacciones::id_caja,
+                grupos::api_key,
+                grupos::secret_key,
+                cajas::token_autorizacion,
+            ))
+            .load(&mut conn)
+    }
+
+    fn canceladas(&self, desde: NaiveDateTime) -> QueryResult<Vec<TransaccionPendiente>> {
+        let mut conn = self.pool.get().unwrap();
+
+        transacciones::table
+            .inner_join(cajas::table.inner_join(grupos::table))
+            .filter(transacciones::estado.eq("CANCELLED"))
+            .filter(transacciones::fecha.ge(desde))
+            .select((
+                transacciones::id_transaccion_yappy,
+                transacciones::id_caja,
+                grupos::api_key,
+                grupos::secret_key,
+                cajas::token_autorizacion,
+            ))
+            .load(&mut conn)
+    }
+
+    
+fn saldo_reembolsable(&self, transaccion: &Transaccion) -> QueryResult<BigDecimal> {
+        let mut conn = self.pool.get().unwrap();
+
+        saldo_reembolsable(&mut conn, transaccion)
+    }
+
+    fn reembolsos(&self, id_transaccion: i32) -> QueryResult<Vec<Reembolso>> {
+        let mut conn = self.pool.get().unwrap();
+
+        reembolsos::table
+            .filter(reembolsos::id_transaccion.eq(id_transaccion))
+            .order(reembolsos::id)
+            .select(Reembolso::as_select())
+            .load(&mut conn)
+    }
+
+    fn reservar_reembolso(
+        &self,
+        id_transaccion: i32,
+        monto: Option<BigDecimal>,
+        motivo: String,
+        actor: ActorEnum,
+        id_cajero: Option<i32>,
+    ) -> Result<Reembolso, ReembolsoError> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            // otro reembolso de la misma venta espera aqui hasta el commit y ya ve esta reserva
+            let transaccion = transaccion_bloqueada(conn, id_transaccion)?;
+            let saldo = saldo_reembolsable(conn, &transaccion)?;
+            let monto = monto.unwrap_or_else(|| saldo.clone());
+
+            if monto <= BigDecimal::zero() || monto > saldo {
+                return Err(ReembolsoError::ExcedeSaldo(saldo));
+            }
+
+            diesel::insert_into(reembolsos::table)
+                .values(&NewReembolso {
+                    id_transaccion,
+                    monto,
+                    motivo,
+                    actor,
+                    id_cajero,
+                    exitoso: false,
+                    codigo_yappy: None,
+                    respuesta_json: json!({}),
+                    request_id: correlacion::actual(),
+                    pendiente: true,
+                })
+                .execute(conn)?;
+
+            Ok(reembolsos::table
+                .filter(reembolsos::id_transaccion.eq(id_transaccion))
+                .order(reembolsos::id.desc())
+                .select(Reembolso::as_select())
+                .first(conn)?)
+        })
+    }
+
+    fn completar_reembolso(&self, reembolso: &Reembolso, respuesta_json: &Value) -> QueryResult<Reembolso> {
+        let mut conn = self.pool.get().unwrap();
+
+        let codigo_yappy = respuesta_json
+            .pointer("/status/code")
+            .and_then(|c| c.as_str())
+            .map(|s| s.to_string());
+        let exitoso = codigo_yappy.as_deref() == Some("YP-0000");
+
+        conn.transaction(|conn| {
+            diesel::update(reembolsos::table.find(reembolso.id))
+                .set((
+                    reembolsos::pendiente.eq(false),
+                    reembolsos::exitoso.eq(exitoso),
+                    reembolsos::codigo_yappy.eq(&codigo_yappy),
+                    reembolsos::respuesta_json.eq(respuesta_json),
+                ))
+                .execute(conn)?;
+
+            if exitoso {
+                let transaccion = transacciones::table
+                    .find(reembolso.id_transaccion)
+                    .select(Transaccion::as_select())
+                    .first(conn)?;
+
+                // REFUNDED solo con lo que Yappy ya confirmó; un pendiente todavía puede fallar
+                if saldo_confirmado(conn, &transaccion)? <= BigDecimal::zero() {
+                    actualizar_estado_transaccion(conn, &transaccion.id_transaccion_yappy, "REFUNDED")?;
+                }
+
+                let evento = json!({
+                    "id_transaccion": transaccion.id_transaccion_yappy,
+                    "id_orden": transaccion.id_orden,
+                    "monto": reembolso.monto,
+                    "motivo": reembolso.motivo,
+                    "actor": reembolso.actor,
+                    "saldo_reembolsable": saldo_reembolsable(conn, &transaccion)?,
+                });
+                encolar_evento(conn, transaccion.id_caja, EventoWebhook::PagoReembolsado, evento)?;
+            }
+
+            reembolsos::table
+                .find(reembolso.id)
+                .select(Reembolso::as_select())
+                .first(conn)
+        })
+    }
+}
+
+// SQLite no tiene FOR UPDATE; ahí una sola transacción escribe a la vez en toda la base
+impl AuditoriaRepo for DieselRepos {
+    fn registrar(&self, registro: NewAuditoria) -> QueryResult<()> {
+        // sin conexión se devuelve el error: auditar nunca debe tumbar la operación
+        let mut conn = self.pool.get().map_err(|err| {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UnableToSendCommand,
+                Box::new(err.to_string()),
+            )
+        })?;
+
+        diesel::insert_into(auditoria::table)
+            .values(&registro)
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn listar(&self, filtro: &FiltroAuditoria, grupos: Option<&[i32]>) -> QueryResult<Vec<Auditoria>> {
+        let mut conn = self.pool.get().unwrap();
+
+        let mut query = auditoria::table
+            .select(Auditoria::as_select())
+            .order(auditoria::id.desc())
+            .limit(filtro.limite.unwrap_or(100).clamp(1, 1000))
+            .into_boxed();
+
+        if let Some(grupos) = grupos {
+            let cajas_visibles = cajas::table
+                .filter(cajas::id_grupo.eq_any(grupos.to_vec()))
+                .select(cajas::id);
+            query = query.filter(auditoria::id_caja.eq_any(cajas_visibles.nullable()));
+        }
+        if let Some(actor) = &filtro.actor {
+            query = query.filter(auditoria::actor.eq(actor.clone()));
+        }
+        if let Some(id_caja) = filtro.id_caja {
+            query = query.filter(auditoria::id_caja.eq(id_caja));
+        }
+        if let Some(id_kiosko) = filtro.id_kiosko {
+            query = query.filter(auditoria::id_kiosko.eq(id_kiosko));
+        }
+        if let Some(accion) = &filtro.accion {
+            query = query.filter(auditoria::accion.eq(accion.clone()));
+        }
+        if let Some(request_id) = &filtro.request_id {
+            query = query.filter(auditoria::request_id.eq(request_id.clone()));
+        }
+        if let Some(desde) = filtro.desde {
+            query = query.filter(auditoria::fecha.ge(desde));
+        }
+        if let Some(hasta) = filtro.hasta {
+            query = query.filter(auditoria::fecha.le(hasta));
+        }
+        if let Some(dia) = filtro.dia {
+            let (inicio, fin) = tiempo::limites_dia(dia);
+            query = query.filter(auditoria::fecha.ge(inicio).and(auditoria::fecha.lt(fin)));
+        }
+
+        query.load(&mut conn)
+    }
+}
+
+impl UsuarioRepo for DieselRepos {
+    fn activo_por_email(&self, email: &str) -> QueryResult<Usuario> {
+        let mut conn = self.pool.get().unwrap();
+
+        usuarios::table
+            .filter(usuarios::email.eq(email))
+            .filter(usuarios::activo.eq(true))
+            .select(Usuario::as_select())
+            .first(&mut conn)
+    }
+
+    fn activo(&self, id_usuario: i32) -> QueryResult<Usuario> {
+        let mut conn = self.pool.get().unwrap();
+
+        usuarios::table
+            .find(id_usuario)
+            .filter(usuarios::activo.eq(true))
+            .select(Usuario::as_select())
+            .first(&mut conn)
+    }
+
+    fn grupos(&self, id_usuario: i32) -> QueryResult<Vec<i32>> {
+        let mut conn = self.pool.get().unwrap();
+
+        usuarios_grupos::table
+            .filter(usuarios_grupos::id_usuario.eq(id_usuario))
+            .select(usuarios_grupos::id_grupo)
+            .load(&mut conn)
+    }
+
+    fn listar(&self, grupos: Option<&[i32]>) -> QueryResult<Vec<Usuario>> {
+        let mut conn = self.pool.get().unwrap();
+
+        let mut query = usuarios::table
+            .select(Usuario::as_select())
+            .order(usuarios::id)
+            .into_boxed();
+
+        if let Some(grupos) = grupos {
+            let ids = usuarios_grupos::table
+                .filter(usuarios_grupos::id_grupo.eq_any(grupos.to_vec()))
+                .select(usuarios_grupos::id_usuario);
+            query = query.filter(usuarios::id.eq_any(ids));
+        }
+
+        query.load(&mut conn)
+    }
+
+    fn contar(&self) -> QueryResult<i64> {
+        let mut conn = self.pool.get().unwrap();
+
+        usuarios::table.count().get_result(&mut conn)
+    }
+
+    fn crear(&self, nuevo: NewUsuario, grupos: &[i32]) -> QueryResult<i32> {
+        let mut conn = self.pool.get().unwrap();
+
+        conn.transaction(|conn| {
+            diesel::insert_into(usuarios::table)
+                .values(&nuevo)
+                .execute(conn)?;
+
+            let id_usuario: i32 = usuarios::table
+                .filter(usuarios::email.eq(&nuevo.email))
+                .select(usuarios::id)
+                .first(conn)?;
+
+            if !grupos.is_empty() {
+                let filas: Vec<_> = grupos
+                    .iter()
+                    .map(|id_grupo| NewUsuarioGrupo {
+                        id_usuario,
+                        id_grupo: *id_grupo,
+                    })
+                    .collect();
+
+                diesel::insert_into(usuarios_grupos::table)
+                    .values(&filas)
+                    .execute(conn)?;
+            }
+
+            Ok(id_usuario)
+        })
+    }
+}
+
+impl WebhookRepo for DieselRepos {
+    fn entregas_pendientes(&self, ahora: NaiveDateTime, lote: i64) -> QueryResult<Vec<(WebhookOutbox, Webhook)>> {
+        let mut conn = self.pool.get().unwrap();
+
+        webhooks_outbox::table
+            .inner_join(webhooks::table)
+            .filter(webhooks_outbox::estado.eq("pendiente"))
+            .filter(webhooks_outbox::proximo_intento.le(ahora))
+            .filter(webhooks::activo.eq(true))
+            .order(webhooks_outbox::id)
+            .limit(lote)
+            .select((WebhookOutbox::as_select(), Webhook::as_select()))
+            .load(&mut conn)
+    }
+
+    fn marcar_entregada(&self, id_entrega: i32, intentos: i32, ahora: NaiveDateTime) -> QueryResult<()> {
+        let mut conn = self.pool.get().unwrap();
+
+        diesel::update(webhooks_outbox::table.find(id_entrega))
+            .set((
+                webhooks_outbox::estado.eq("entregado"),
+                webhooks_outbox::intentos.eq(intentos),
+                webhooks_outbox::entregado.eq(ahora),
+                webhooks_outbox::ultimo_error.eq(None::<String>),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+
+    fn reprogramar_entrega(
+        &self,
+        id_entrega: i32,
+        intentos: i32,
+        proximo_intento: NaiveDateTime,
+        error: String,
+    ) -> QueryResult<()> {
+        let mut conn = self.pool.get().unwrap();
+
+        diesel::update(webhooks_outbox::table.find(id_entrega))
+            .set((
+                webhooks_outbox::intentos.eq(intentos),
+                webhooks_outbox::proximo_intento.eq(proximo_intento),
+                webhooks_outbox::ultimo_error.eq(error),
+            ))
+            .execute(&mut conn)?;
+
+        Ok(())
+    }
+}
+
+/// Deja el evento en `webhooks_outbox` para cada webhook activo del grupo de la caja.
+/// Debe llamarse dentro de la misma transacción que el cambio que lo origina,
+/// así el evento existe si y solo si el cambio quedó guardado.
+fn encolar_evento(
+    conn: &mut Conexion,
+    id_caja: i32,
+    evento: EventoWebhook,
+    datos: Value,
+) -> QueryResult<usize> {
+    let id_grupo: i32 = cajas::table
+        .find(id_caja)
+        .select(cajas::id_grupo)
+        .first(conn)?;
+
+    let suscritos: Vec<Webhook> = webhooks::table
+        .filter(webhooks::id_grupo.eq(id_grupo))
+        .filter(webhooks::activo.eq(true))
+        .select(Webhook::as_select())
+        .load(conn)?;
+
+    let ahora = tiempo::ahora();
+
+    let filas: Vec<NewWebhookOutbox> = suscritos
+        .iter()
+        .filter(|webhook| webhook.escucha(evento))
+        .map(|webhook| NewWebhookOutbox {
+            id_webhook: webhook.id,
+            evento: evento.nombre().to_string(),
+            payload: json!({
+                "evento": evento.nombre(),
+                "fecha": tiempo::rfc3339(&ahora),
+                "id_grupo": id_grupo,
+                "id_caja": id_caja,
+                "datos": datos,
+            }),
+            proximo_intento: ahora,
+        })
+        .collect();
+
+    if filas.is_empty() {
+        return Ok(0);
+    }
+
+    diesel::insert_into(webhooks_outbox::table)
+        .values(&filas)
+        .execute(conn)
+}
+
+fn estado_bloqueado(conn: &mut Conexion, id_caja: i32) -> QueryResult<CajasEstadoEnum> {
+    let query = cajas::table
+        .filter(cajas::id.eq(id_caja))
+        .select(cajas::estado);
+
+    #[cfg(not(feature = "sqlite"))]
+    let query = query.for_update();
+
+    query.first(conn)
+}
+
+fn transaccion_bloqueada(conn: &mut Conexion, id_transaccion: i32) -> QueryResult<Transaccion> {
+    let query = transacciones::table
+        .find(id_transaccion)
+        .select(Transaccion::as_select());
+
+    #[cfg(not(feature = "sqlite"))]
+    let query = query.for_update();
+
+    query.first(conn)
+}
+
+fn transicionar_caja(
+    conn: &mut Conexion,
+    id_caja: i32,
+    nuevo: CajasEstadoEnum,
+    actor: ActorEnum,
+    motivo: String,
+) -> Result<CajasEstadoEnum, TransicionError> {
+    conn.transaction(|conn| {
+        let anterior: CajasEstadoEnum = estado_bloqueado(conn, id_caja)?;
+
+        if !anterior.puede_transicionar(&nuevo) {
+            return Err(TransicionError::Invalida(anterior, nuevo));
+        }
+
+        diesel::update(cajas::table.filter(cajas::id.eq(id_caja)))
+            .set(cajas::estado.eq(&nuevo))
+            .execute(conn)?;
+
+        diesel::insert_into(caja_transiciones::table)
+            .values(&NewCajaTransicion {
+                id_caja,
+                estado_anterior: anterior.clone(),
+                estado_nuevo: nuevo,
+                actor,
+                motivo,
+            })
+            .execute(conn)?;
+
+        Ok(anterior)
+    })
+}
+
+fn actualizar_estado_transaccion(
+    conn: &mut Conexion,
+    id_transaccion_yappy: &str,
+    estado: &str,
+) -> QueryResult<usize> {
+    conn.transaction(|conn| {
+        let actualizadas = diesel::update(transacciones::table)
+            .filter(transacciones::id_transaccion_yappy.eq(id_transaccion_yappy))
+            .filter(transacciones::estado.ne(estado))
+            .set(transacciones::estado.eq(estado))
+            .execute(conn)?;
+
+        // el filtro por estado garantiza un solo evento aunque varios caminos vean el pago
+        if actualizadas > 0 && estado == "COMPLETED" {
+            let transaccion: Transaccion = transacciones::table
+                .filter(transacciones::id_transaccion_yappy.eq(id_transaccion_yappy))
+                .select(Transaccion::as_select())
+                .first(conn)?;
+
+            encolar_evento(
+                conn,
+                transaccion.id_caja,
+                EventoWebhook::PagoCompletado,
+                json!(transaccion),
+            )?;
+        }
+
+        Ok(actualizadas)
+    })
+}
+
+// lo que se puede apartar: los pendientes ya estan comprometidos
+fn saldo_reembolsable(conn: &mut Conexion, transaccion: &Transaccion) -> QueryResult<BigDecimal> {
+    let reembolsado: Option<BigDecimal> = reembolsos::table
+        .filter(reembolsos::id_transaccion.eq(transaccion.id))
+        .filter(reembolsos::exitoso.eq(true).or(reembolsos::pendiente.eq(true)))
+        .select(diesel::dsl::sum(reembolsos::monto))
+        .first(conn)?;
+
+    Ok(&transaccion.total - reembolsado.unwrap_or_else(BigDecimal::zero))
+}
+
+fn saldo_confirmado(conn: &mut Conexion, transaccion: &Transaccion) -> QueryResult<BigDecimal> {
+    let reembolsado: Option<BigDecimal> = reembolsos::table
+        .filter(reembolsos::id_transaccion.eq(transaccion.id))
+        .filter(reembolsos::exitoso.eq(true))
+        .select(diesel::dsl::sum(reembolsos::monto))
+        .first(conn)?;
+
+    Ok(&transaccion.total - reembolsado.unwrap_or_else(BigDecimal::zero))
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::conection::pool_sqlite;
+
+    fn venta_completada() -> (DieselRepos, Transaccion) {
+        let repos = DieselRepos::new(pool_sqlite(":memory:".to_string()));
+        let mut conn = repos.pool.get().unwrap();
+
+        diesel::insert_into(grupos::table)
+            .values((
+                grupos::id_yappy.eq("grupo-1"),
+                grupos::nombre.eq("Grupo 1"),
+                grupos::api_key.eq("api"),
+                grupos::secret_key.eq("secret"),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+        diesel::insert_into(cajas::table)
+            .values((cajas::id_grupo.eq(1), cajas::nombre_caja.eq("Caja 1")))
+            .execute(&mut conn)
+            .unwrap();
+        drop(conn);
+
+        let transaccion = TransaccionRepo::registrar(
+            &repos,
+            NewTransaccion {
+                id_caja: 1,
+                id_cajero: None,
+                id_transaccion_yappy: "venta".to_string(),
+                id_orden: None,
+                tipo_qr: "DYN".to_string(),
+                subtotal: "10.00".parse().unwrap(),
+                impuesto: "0.70".parse().unwrap(),
+                propina: BigDecimal::zero(),
+                descuento: BigDecimal::zero(),
+                total: "10.70".parse().unwrap(),
+                qr_hash: None,
+                tasa_impuesto: None,
+                discrepancia_impuesto: None,
+                request_id: None,
+            },
+            Vec::new(),
+        )
+        .unwrap();
+        repos.actualizar_estado("venta", "COMPLETED").unwrap();
+
+        (repos, transaccion)
+    }
+
+    // sqlite devuelve los DECIMAL como REAL, asi que se compara al centavo
+    fn centavos(monto: &BigDecimal) -> BigDecimal {
+        monto.round(2)
+    }
+
+    fn reservar(repos: &DieselRepos, id: i32, monto: Option<&str>) -> Result<Reembolso, ReembolsoError> {
+        let monto = monto.map(|m| m.parse().unwrap());
+        repos.reservar_reembolso(id, monto, "prueba".to_string(), ActorEnum::Kiosko, None)
+    }
+
+    #[test]
+    fn una_reserva_pendiente_no_deja_pasar_del_total() {
+        let (repos, venta) = venta_completada();
+
+        let primera = reservar(&repos, venta.id, Some("8.00")).unwrap();
+        assert!(primera.pendiente);
+
+        // mientras Yappy no responde la primera, la segunda ya no cabe
+        let segunda = reservar(&repos, venta.id, Some("5.00"));
+        assert!(matches!(segunda, Err(ReembolsoError::ExcedeSaldo(saldo)) if centavos(&saldo) == "2.70".parse().unwrap()));
+
+        // si Yappy la rechaza el monto vuelve al saldo
+        let rechazo = json!({ "status": { "code": "YP-0099" } });
+        let primera = repos.completar_reembolso(&primera, &rechazo).unwrap();
+        assert!(!primera.pendiente && !primera.exitoso);
+        assert_eq!(centavos(&repos.saldo_reembolsable(&venta).unwrap()), "10.70".parse::<BigDecimal>().unwrap());
+    }
+
+    #[test]
+    fn el_reembolso_total_aceptado_deja_la_venta_en_refunded() {
+        let (repos, venta) = venta_completada();
+
+        let reserva = reservar(&repos, venta.id, None).unwrap();
+        assert_eq!(centavos(&reserva.monto), "10.70".parse::<BigDecimal>().unwrap());
+
+        let aceptado = json!({ "status": { "code": "YP-0000" } });
+        let reembolso = repos.completar_reembolso(&reserva, &aceptado).unwrap();
+        assert!(reembolso.exitoso);
+
+        assert_eq!(repos.buscar_por_id(venta.id).unwrap().estado, "REFUNDED");
+        assert!(matches!(reservar(&repos, venta.id, None), Err(ReembolsoError::ExcedeSaldo(_))));
+    }
+}
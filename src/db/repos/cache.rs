@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+use diesel::QueryResult;
+
+use crate::db::models::{Kiosko, KioskoInfo};
+use crate::db::repos::{ContextoVigente, KioskoRepo};
+
+/// Guarda en memoria la parte fija del contexto de cada kiosko (kiosko, caja, grupo y
+/// credenciales) para que el polling no repita el join en cada solicitud.
+///
+/// El estado y el token de la caja se leen siempre con `vigente_por_mac`, una sola fila. Si
+/// cambió la caja del kiosko, el cajero en turno o `version_credenciales` del grupo (macy-admin
+/// la sube al cambiar las llaves) se vuelve a leer todo aunque la entrada no haya vencido.
+/// Las entradas vencen a los `ttl`; `invalidar_caja` las descarta antes.
+pub struct KioskosEnCache {
+    base: Arc<dyn KioskoRepo>,
+    ttl: Duration,
+    cache: Mutex<Entradas>,
+}
+
+#[derive(Default)]
+struct Entradas {
+    por_mac: HashMap<String, (Instant, KioskoInfo)>,
+    // sube con cada invalidación; lo leído antes de una invalidación no se guarda
+    generacion: u64,
+}
+
+impl KioskosEnCache {
+    pub fn new(base: Arc<dyn KioskoRepo>, ttl: Duration) -> Self {
+        KioskosEnCache {
+            base,
+            ttl,
+            cache: Mutex::new(Entradas::default()),
+        }
+    }
+}
+
+impl KioskoRepo for KioskosEnCache {
+    fn info_por_mac(&self, mac_address: &str) -> QueryResult<KioskoInfo> {
+        let (generacion, guardada) = {
+            let cache = self.cache.lock().unwrap();
+            let guardada = cache
+                .por_mac
+                .get(mac_address)
+                .filter(|(guardado, _)| guardado.elapsed() < self.ttl)
+                .map(|(_, info)| info.clone());
+            (cache.generacion, guardada)
+        };
+
+        if let Some(mut info) = guardada {
+            let vigente = self.base.vigente_por_mac(mac_address)?;
+
+            if vigente.id_caja == info.id_caja
+                && vigente.id_cajero == info.id_cajero
+                && vigente.version_credenciales == info.version_credenciales
+            {
+                info.estado = vigente.estado;
+                info.token_autorizacion = vigente.token_autorizacion;
+                return Ok(info);
+            }
+        }
+
+        let info = self.base.info_por_mac(mac_address)?;
+
+        let mut cache = self.cache.lock().unwrap();
+        if cache.generacion == generacion {
+            cache
+                .por_mac
+                .insert(mac_address.to_string(), (Instant::now(), info.clone()));
+        }
+
+        Ok(info)
+    }
+
+    fn vigente_por_mac(&self, mac_address: &str) -> QueryResult<ContextoVigente> {
+        self.base.vigente_por_mac(mac_address)
+    }
+
+    fn listar(&self) -> QueryResult<Vec<Kiosko>> {
+        self.base.listar()
+    }
+
+    fn marcar_desconectados(&self, limite: NaiveDateTime) -> QueryResult<usize> {
+        self.base.marcar_desconectados(limite)
+    }
+
+    fn registrar_latido(
+        &self,
+        id_kiosko: i32,
+        ip: String,
+        version_app: Option<String>,
+        ahora: NaiveDateTime,
+    ) -> QueryResult<()> {
+        self.base.registrar_latido(id_kiosko, ip, version_app, ahora)
+    }
+
+    fn invalidar_caja(&self, id_caja: i32) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.por_mac.retain(|_, (_, info)| info.id_caja != id_caja);
+        cache.generacion += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repos::memoria::{MemoriaRepos, prueba};
+    use crate::db::types::enums::{CajasEstadoEnum, CajasTipoEnum};
+
+    fn en_cache() -> (KioskosEnCache, MemoriaRepos) {
+        let repos = MemoriaRepos::nuevo(prueba::memoria(CajasEstadoEnum::Cerrado, CajasTipoEnum::Kiosko));
+        let cache = KioskosEnCache::new(Arc::new(repos.clone()), Duration::from_secs(60));
+        (cache, repos)
+    }
+
+    #[test]
+    fn el_estado_y_el_token_se_leen_siempre() {
+        let (cache, repos) = en_cache();
+        cache.info_por_mac(prueba::MAC).unwrap();
+
+        {
+            let mut datos = repos.datos();
+            datos.cajas[0].estado = CajasEstadoEnum::Abierto;
+            datos.cajas[0].token_autorizacion = Some("token".to_string());
+        }
+
+        let info = cache.info_por_mac(prueba::MAC).unwrap();
+        assert_eq!(info.estado, CajasEstadoEnum::Abierto);
+        assert_eq!(info.token_autorizacion.as_deref(), Some("token"));
+    }
+
+    #[test]
+    fn las_credenciales_se_releen_cuando_sube_la_version() {
+        let (cache, repos) = en_cache();
+        cache.info_por_mac(prueba::MAC).unwrap();
+
+        repos.datos().grupos[0].api_key = "nueva".to_string();
+        assert_eq!(cache.info_por_mac(prueba::MAC).unwrap().api_key, "api");
+
+        repos.datos().grupos[0].version_credenciales += 1;
+        assert_eq!(cache.info_por_mac(prueba::MAC).unwrap().api_key, "nueva");
+    }
+
+    #[test]
+    fn un_kiosko_borrado_deja_de_tener_acceso() {
+        let (cache, repos) = en_cache();
+        cache.info_por_mac(prueba::MAC).unwrap();
+
+        repos.datos().kioskos.clear();
+        assert!(cache.info_por_mac(prueba::MAC).is_err());
+    }
+}
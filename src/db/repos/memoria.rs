@@ -0,0 +1,1063 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDateTime;
+use diesel::QueryResult;
+use diesel::result::Error::NotFound;
+use serde_json::{Value, json};
+
+use crate::db::models::{
+    AlcanceCierre, Auditoria, Caja, CajaTransicion, CajaWithCreds, Cajero, EventoWebhook,
+    FiltroAuditoria, Grupo, ImpuestosGrupo, Kiosko, KioskoInfo, NewAuditoria, NewCajaCierreError,
+    NewCajaCierreResumen, NewSesionCajero, NewTransaccion, NewTransaccionItem, NewUsuario,
+    NewUsuarioGrupo, Reembolso, ReembolsoError, Transaccion, TransaccionItem, TransaccionPendiente,
+    TransicionError, Usuario, Webhook, WebhookOutbox,
+};
+use crate::db::repos::{
+    AuditoriaRepo, CajaRepo, CierreRepo, ContextoVigente, GrupoRepo, KioskoRepo, ReferenciaVenta,
+    TransaccionRepo, UsuarioRepo, WebhookRepo,
+};
+use crate::db::types::enums::{ActorEnum, CajasEstadoEnum};
+use crate::middlewares::correlacion;
+use crate::utils::tiempo;
+
+/// Filas que ven los repositorios en memoria; lo que se guarda (cierres, transiciones,
+/// transacciones, reembolsos, auditoría y eventos de webhooks) queda aquí también.
+#[derive(Default)]
+pub struct Memoria {
+    pub grupos: Vec<Grupo>,
+    pub impuestos: Vec<ImpuestosGrupo>,
+    pub cajas: Vec<Caja>,
+    pub kioskos: Vec<Kiosko>,
+    pub cajeros: Vec<Cajero>,
+    /// Turnos de cajero con su fin, si ya terminaron.
+    pub sesiones_cajero: Vec<(NewSesionCajero, Option<NaiveDateTime>)>,
+    pub cierres_resumen: Vec<NewCajaCierreResumen>,
+    pub cierres_errores: Vec<NewCajaCierreError>,
+    pub transiciones: Vec<CajaTransicion>,
+    pub transacciones: Vec<Transaccion>,
+    pub transaccion_items: Vec<TransaccionItem>,
+    pub reembolsos: Vec<Reembolso>,
+    pub eventos: Vec<(i32, EventoWebhook, Value)>,
+    pub webhooks: Vec<Webhook>,
+    /// Las entregas no salen de `eventos`: cada prueba carga las que necesita.
+    pub entregas: Vec<WebhookOutbox>,
+    pub auditoria: Vec<Auditoria>,
+    pub usuarios: Vec<Usuario>,
+    pub usuarios_grupos: Vec<NewUsuarioGrupo>,
+}
+
+impl Memoria {
+    fn caja(&mut self, id_caja: i32) -> QueryResult<&mut Caja> {
+        self.cajas.iter_mut().find(|c| c.id == id_caja).ok_or(NotFound)
+    }
+
+    fn terminar_sesiones(&mut self, id_caja: i32) {
+        let ahora = tiempo::ahora();
+        for (sesion, fin) in self.sesiones_cajero.iter_mut() {
+            if sesion.id_caja == id_caja && fin.is_none() {
+                *fin = Some(ahora);
+            }
+        }
+    }
+
+    fn transaccion(&mut self, id_transaccion_yappy: &str) -> QueryResult<&mut Transaccion> {
+        self.transacciones
+            .iter_mut()
+            .find(|t| t.id_transaccion_yappy == id_transaccion_yappy)
+            .ok_or(NotFound)
+    }
+
+    // mismas reglas que la implementación de Diesel, sin transacción: el lock de la
+    // memoria ya hace que nadie vea el cambio a medias
+    fn transicionar(
+        &mut self,
+        id_caja: i32,
+        nuevo: CajasEstadoEnum,
+        actor: ActorEnum,
+        motivo: String,
+    ) -> Result<CajasEstadoEnum, TransicionError> {
+        let caja = self.caja(id_caja)?;
+        let anterior = caja.estado.clone();
+
+        if !anterior.puede_transicionar(&nuevo) {
+            return Err(TransicionError::Invalida(anterior, nuevo));
+        }
+
+        caja.estado = nuevo.clone();
+
+        let id = self.transiciones.len() as i32 + 1;
+        self.transiciones.push(CajaTransicion {
+            id,
+            id_caja,
+            estado_anterior: anterior.clone(),
+            estado_nuevo: nuevo,
+            actor,
+            motivo,
+            fecha: Some(tiempo::ahora()),
+        });
+
+        Ok(anterior)
+    }
+
+    fn actualizar_estado(&mut self, id_transaccion_yappy: &str, estado: &str) -> QueryResult<usize> {
+        let transaccion = self.transaccion(id_transaccion_yappy)?;
+
+        if transaccion.estado == estado {
+            return Ok(0);
+        }
+
+        transaccion.estado = estado.to_string();
+        transaccion.fecha_actualizacion = Some(tiempo::ahora());
+
+        if estado == "COMPLETED" {
+            let evento = (transaccion.id_caja, EventoWebhook::PagoCompletado, json!(transaccion));
+            self.eventos.push(evento);
+        }
+
+        Ok(1)
+    }
+
+    fn saldo_reembolsable(&self, transaccion: &Transaccion) -> BigDecimal {
+        let reembolsado: BigDecimal = self
+            .reembolsos
+            .iter()
+            .filter(|r| r.id_transaccion == transaccion.id && (r.exitoso || r.pendiente))
+            .map(|r| r.monto.clone())
+            .sum();
+
+        &transaccion.total - reembolsado
+    }
+
+    // como el join de Diesel: transacciones que cumplen `filtro`, con las credenciales de su caja
+    fn con_credenciales(&self, filtro: impl Fn(&Transaccion) -> bool) -> Vec<TransaccionPendiente> {
+        self.transacciones
+            .iter()
+            .filter(|t| filtro(t))
+            .filter_map(|t| {
+                let caja = self.cajas.iter().find(|c| c.id == t.id_caja)?;
+                let grupo = self.grupos.iter().find(|g| g.id == caja.id_grupo)?;
+                Some(TransaccionPendiente {
+                    id_transaccion_yappy: t.id_transaccion_yappy.clone(),
+                    id_caja: t.id_caja,
+                    api_key: grupo.api_key.clone(),
+                    secret_key: grupo.secret_key.clone(),
+                    token_autorizacion: caja.token_autorizacion.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MemoriaRepos(Arc<Mutex<Memoria>>);
+
+impl MemoriaRepos {
+    pub fn nuevo(memoria: Memoria) -> Self {
+        MemoriaRepos(Arc::new(Mutex::new(memoria)))
+    }
+
+    /// Acceso directo a las filas, para preparar o revisar el estado.
+    pub fn datos(&self) -> MutexGuard<'_, Memoria> {
+        self.0.lock().unwrap()
+    }
+}
+
+impl KioskoRepo for MemoriaRepos {
+    fn info_por_mac(&self, mac_address: &str) -> QueryResult<KioskoInfo> {
+        let datos = self.datos();
+
+        let kiosko = datos
+            .kioskos
+            .iter()
+            .find(|k| k.mac_address == mac_address)
+            .ok_or(NotFound)?;
+        let caja = datos
+            .cajas
+            .iter()
+            .find(|c| c.id == kiosko.id_caja)
+            .ok_or(NotFound)?;
+        let grupo = datos
+            .grupos
+            .iter()
+            .find(|g| g.id == caja.id_grupo)
+            .ok_or(NotFound)?;
+        let nombre_cajero = match caja.id_cajero_actual {
+            Some(id_cajero) => Some(
+                datos
+                    .cajeros
+                    .iter()
+                    .find(|c| c.id == id_cajero)
+                    .ok_or(NotFound)?
+                    .nombre
+                    .clone(),
+            ),
+            None => None,
+        };
+
+        Ok(KioskoInfo::armar(
+            kiosko.clone(),
+            caja.clone(),
+            grupo.clone(),
+            nombre_cajero,
+        ))
+    }
+
+    fn vigente_por_mac(&self, mac_address: &str) -> QueryResult<ContextoVigente> {
+        let info = self.info_por_mac(mac_address)?;
+
+        Ok(ContextoVigente {
+            id_caja: info.id_caja,
+            estado: info.estado,
+            token_autorizacion: info.token_autorizacion,
+            id_cajero: info.id_cajero,
+            version_credenciales: info.version_credenciales,
+        })
+    }
+
+    fn listar(&self) -> QueryResult<Vec<Kiosko>> {
+        Ok(self.datos().kioskos.clone())
+    }
+
+    fn marcar_desconectados(&self, limite: NaiveDateTime) -> QueryResult<usize> {
+        let mut desconectados = 0;
+
+        for kiosko in self.datos().kioskos.iter_mut() {
+            if kiosko.en_linea && kiosko.ultima_conexion.is_none_or(|fecha| fecha < limite) {
+                kiosko.en_linea = false;
+                desconectados += 1;
+            }
+        }
+
+        Ok(desconectados)
+    }
+
+    fn registrar_latido(
+        &self,
+        id_kiosko: i32,
+        ip: String,
+        version_app: Option<String>,
+        ahora: NaiveDateTime,
+    ) -> QueryResult<()> {
+        let mut datos = self.datos();
+        let kiosko = datos.kioskos.iter_mut().find(|k| k.id == id_kiosko).ok_or(NotFound)?;
+
+        kiosko.ultima_conexion = Some(ahora);
+        if let Some(version) = version_app {
+            kiosko.version_app = Some(version);
+        }
+        kiosko.ip = Some(ip);
+        kiosko.en_linea = true;
+
+        Ok(())
+    }
+}
+
+impl CajaRepo for MemoriaRepos {
+    fn buscar(&self, id_caja: i32) -> QueryResult<Caja> {
+        self.datos()
+            .cajas
+            .iter()
+            .find(|c| c.id == id_caja)
+            .cloned()
+            .ok_or(NotFound)
+    }
+
+    fn listar(&self) -> QueryResult<Vec<Caja>> {
+        Ok(self.datos().cajas.clone())
+    }
+
+    // igual que un UPDATE, no falla si la caja no existe
+    fn fijar_transaccion_actual(&self, id_caja: i32, transaccion: Option<&str>) -> QueryResult<()> {
+        if let Some(caja) = self.datos().cajas.iter_mut().find(|c| c.id == id_caja) {
+            caja.transaccion_actual = transaccion.map(str::to_string);
+        }
+        Ok(())
+    }
+
+    fn soltar_transaccion_actual(&self, id_caja: i32) -> QueryResult<Option<String>> {
+        let mut datos = self.datos();
+        let caja = datos.caja(id_caja)?;
+        Ok(caja.transaccion_actual.take())
+    }
+
+    fn buscar_cajero(&self, id_cajero: i32) -> QueryResult<Cajero> {
+        self.datos()
+            .cajeros
+            .iter()
+            .find(|c| c.id == id_cajero)
+            .cloned()
+            .ok_or(NotFound)
+    }
+
+    fn turno_en_otra_caja(&self, id_cajero: i32, id_caja: i32) -> QueryResult<bool> {
+        Ok(self
+            .datos()
+            .cajas
+            .iter()
+            .any(|c| c.id_cajero_actual == Some(id_cajero) && c.id != id_caja))
+    }
+
+    fn iniciar_turno(&self, id_caja: i32, id_cajero: i32) -> QueryResult<()> {
+        let mut datos = self.datos();
+
+        datos.caja(id_caja)?.id_cajero_actual = Some(id_cajero);
+        datos.sesiones_cajero.push((NewSesionCajero { id_caja, id_cajero }, None));
+
+        Ok(())
+    }
+
+    fn revertir_turno(&self, id_caja: i32) -> QueryResult<()> {
+        let mut datos = self.datos();
+
+        datos.caja(id_caja)?.id_cajero_actual = None;
+        datos.terminar_sesiones(id_caja);
+
+        Ok(())
+    }
+
+    fn transicionar(
+        &self,
+        id_caja: i32,
+        nuevo: CajasEstadoEnum,
+        actor: ActorEnum,
+        motivo: String,
+        evento: Option<(EventoWebhook, Value)>,
+    ) -> Result<CajasEstadoEnum, TransicionError> {
+        let mut datos = self.datos();
+        let anterior = datos.transicionar(id_caja, nuevo, actor, motivo)?;
+
+        if let Some((evento, valor)) = evento {
+            datos.eventos.push((id_caja, evento, valor));
+        }
+
+        Ok(anterior)
+    }
+
+    fn recuperar(
+        &self,
+        id_caja: i32,
+        actor: ActorEnum,
+        motivo: String,
+    ) -> Result<Option<CajasEstadoEnum>, TransicionError> {
+        let mut datos = self.datos();
+
+        let Some(destino) = datos.caja(id_caja)?.estado.recuperacion() else {
+            return Ok(None);
+        };
+
+        datos.transicionar(id_caja, destino.clone(), actor, motivo)?;
+        Ok(Some(destino))
+    }
+
+    fn a_medias(&self) -> QueryResult<Vec<(i32, CajasEstadoEnum, Option<NaiveDateTime>)>> {
+        let datos = self.datos();
+
+        Ok(datos
+            .cajas
+            .iter()
+            .filter(|c| matches!(c.estado, CajasEstadoEnum::Abriendo | CajasEstadoEnum::Cerrando))
+            .map(|c| {
+                let desde = datos
+                    .transiciones
+                    .iter()
+                    .filter(|t| t.id_caja == c.id)
+                    .filter_map(|t| t.fecha)
+                    .max();
+                (c.id, c.estado.clone(), desde)
+            })
+            .collect())
+    }
+
+    fn terminar_apertura(
+        &self,
+        id_caja: i32,
+        token: Option<&str>,
+        actor: ActorEnum,
+        motivo: String,
+        datos_evento: Value,
+    ) -> Result<CajasEstadoEnum, TransicionError> {
+        let mut datos = self.datos();
+
+        let nuevo = match token {
+            Some(_) => CajasEstadoEnum::Abierto,
+            None => CajasEstadoEnum::Cerrado,
+        };
+
+        datos.transicionar(id_caja, nuevo.clone(), actor, motivo)?;
+        datos.caja(id_caja)?.token_autorizacion = token.map(str::to_string);
+
+        if token.is_some() {
+            datos.eventos.push((id_caja, EventoWebhook::CajaAbierta, datos_evento));
+        }
+
+        Ok(nuevo)
+    }
+
+    // en memoria no hay turnos; basta con soltar al cajero de la caja
+    fn terminar_cierre(
+        &self,
+        id_caja: i32,
+        actor: ActorEnum,
+        motivo: String,
+        datos_evento: Value,
+    ) -> Result<CajasEstadoEnum, TransicionError> {
+        let mut datos = self.datos();
+
+        datos.transicionar(id_caja, CajasEstadoEnum::Cerrado, actor, motivo)?;
+
+        let caja = datos.caja(id_caja)?;
+        caja.token_autorizacion = None;
+        caja.id_cajero_actual = None;
+        datos.terminar_sesiones(id_caja);
+
+        datos.eventos.push((id_caja, EventoWebhook::CierreFinalizado, datos_evento));
+
+        Ok(CajasEstadoEnum::Cerrado)
+    }
+}
+
+impl GrupoRepo for MemoriaRepos {
+    fn buscar(&self, id_grupo: i32) -> QueryResult<Grupo> {
+        self.datos()
+            .grupos
+            .iter()
+            .find(|g| g.id == id_grupo)
+            .cloned()
+            .ok_or(NotFound)
+    }
+
+    fn listar(&self, visibles: Option<&[i32]>) -> QueryResult<Vec<Grupo>> {
+        Ok(self
+            .datos()
+            .grupos
+            .iter()
+            .filter(|g| visibles.is_none_or(|ids| ids.contains(&g.id)))
+            .cloned()
+            .collect())
+    }
+
+    fn impuestos(&self, id_grupo: i32) -> QueryResult<Option<ImpuestosGrupo>> {
+        Ok(self
+            .datos()
+            .impuestos
+            .iter()
+            .find(|i| i.id_grupo == id_grupo)
+            .cloned())
+    }
+}
+
+impl CierreRepo for MemoriaRepos {
+    fn cajas_por_cerrar(&self, alcance: &AlcanceCierre) -> QueryResult<Vec<CajaWithCreds>> {
+        let datos = self.datos();
+
+        Ok(datos
+            .cajas
+            .iter()
+            .filter(|c| matches!(c.estado, CajasEstadoEnum::Abierto | CajasEstadoEnum::ErrorCierre))
+            .filter(|c| match alcance {
+                AlcanceCierre::Todas => true,
+                AlcanceCierre::Grupos(ids) => ids.contains(&c.id_grupo),
+                AlcanceCierre::Caja(id_caja) => c.id == *id_caja,
+            })
+            .filter_map(|c| {
+                let grupo = datos.grupos.iter().find(|g| g.id == c.id_grupo)?;
+                Some(CajaWithCreds {
+                    id: c.id,
+                    nombre_caja: c.nombre_caja.clone(),
+                    estado: c.estado.clone(),
+                    api_key: grupo.api_key.clone(),
+                    secret_key: grupo.secret_key.clone(),
+                    token_autorizacion: c.token_autorizacion.clone(),
+                })
+            })
+            .collect())
+    }
+
+    fn guardar_resumen(&self, resumen: NewCajaCierreResumen) -> QueryResult<()> {
+        self.datos().cierres_resumen.push(resumen);
+        Ok(())
+    }
+
+    fn guardar_error(&self, error: NewCajaCierreError) -> QueryResult<()> {
+        self.datos().cierres_errores.push(error);
+        Ok(())
+    }
+
+    // los ids en memoria son la posición en la lista
+    fn ultimos_ids(&self) -> QueryResult<(i32, i32)> {
+        let datos = self.datos();
+        Ok((datos.cierres_resumen.len() as i32, datos.cierres_errores.len() as i32))
+    }
+
+    fn guardados_desde(
+        &self,
+        ids_cajas: &[i32],
+        (desde_resumen, desde_errores): (i32, i32),
+    ) -> QueryResult<(Vec<NewCajaCierreResumen>, Vec<NewCajaCierreError>)> {
+        let datos = self.datos();
+
+        let resumen = datos
+            .cierres_resumen
+            .iter()
+            .skip(desde_resumen as usize)
+            .filter(|r| ids_cajas.contains(&r.id_caja))
+            .map(|r| NewCajaCierreResumen {
+                id_caja: r.id_caja,
+                tipo: r.tipo.clone(),
+                monto: r.monto.clone(),
+                transacciones: r.transacciones,
+                id_cajero: r.id_cajero,
+            })
+            .collect();
+        let errores = datos
+            .cierres_errores
+            .iter()
+            .skip(desde_errores as usize)
+            .filter(|e| ids_cajas.contains(&e.id_caja))
+            .map(|e| NewCajaCierreError {
+                id_caja: e.id_caja,
+                respuesta_json: e.respuesta_json.clone(),
+                id_cajero: e.id_cajero,
+            })
+            .collect();
+
+        Ok((resumen, errores))
+    }
+}
+
+impl TransaccionRepo for MemoriaRepos {
+    fn buscar(&self, id_transaccion_yappy: &str) -> QueryResult<Transaccion> {
+        self.datos()
+            .transaccion(id_transaccion_yappy)
+            .map(|t| t.clone())
+    }
+
+    fn de_grupo(&self, id_grupo: i32, referencia: &ReferenciaVenta) -> QueryResult<Transaccion> {
+        let datos = self.datos();
+        let del_grupo = |t: &Transaccion| {
+            datos.cajas.iter().any(|c| c.id == t.id_caja && c.id_grupo == id_grupo)
+        };
+
+        datos
+            .transacciones
+            .iter()
+            .rev()
+            .filter(|t| del_grupo(t))
+            .find(|t| match referencia {
+                ReferenciaVenta::Id(id) => t.id == *id,
+                ReferenciaVenta::Yappy(id_transaccion) => &t.id_transaccion_yappy == id_transaccion,
+                ReferenciaVenta::Orden(id_orden) => {
+                    t.id_orden.as_ref() == Some(id_orden) && t.estado == "COMPLETED"
+                }
+            })
+            .cloned()
+            .ok_or(NotFound)
+    }
+
+    fn buscar_por_id(&self, id: i32) -> QueryResult<Transaccion> {
+        self.datos()
+            .transacciones
+            .iter()
+            .find(|t| t.id == id)
+            .cloned()
+            .ok_or(NotFound)
+    }
+
+    fn items(&self, id_transaccion: i32) -> QueryResult<Vec<TransaccionItem>> {
+        Ok(self
+            .datos()
+            .transaccion_items
+            .iter()
+            .filter(|i| i.id_transaccion == id_transaccion)
+            .cloned()
+            .collect())
+    }
+
+    fn registrar(&self, nueva: NewTransaccion, items: Vec<NewTransaccionItem>) -> QueryResult<Transaccion> {
+        let mut datos = self.datos();
+
+        let id = datos.transacciones.len() as i32 + 1;
+        let transaccion = Transaccion {
+            id,
+            id_caja: nueva.id_caja,
+            id_cajero: nueva.id_cajero,
+            id_transaccion_yappy: nueva.id_transaccion_yappy,
+            id_orden: nueva.id_orden,
+            tipo_qr: nueva.tipo_qr,
+            subtotal: nueva.subtotal,
+            impuesto: nueva.impuesto,
+            propina: nueva.propina,
+            descuento: nueva.descuento,
+            total: nueva.total,
+            estado: "PENDING".to_string(),
+            fecha: Some(tiempo::ahora()),
+            fecha_actualizacion: None,
+            motivo_cancelacion: None,
+            qr_hash: nueva.qr_hash,
+            tasa_impuesto: nueva.tasa_impuesto,
+            discrepancia_impuesto: nueva.discrepancia_impuesto,
+            request_id: nueva.request_id,
+        };
+        datos.transacciones.push(transaccion.clone());
+
+        for item in items {
+            let id_item = datos.transaccion_items.len() as i32 + 1;
+            datos.transaccion_items.push(TransaccionItem {
+                id: id_item,
+                id_transaccion: id,
+                sku: item.sku,
+                descripcion: item.descripcion,
+                cantidad: item.cantidad,
+                precio_unitario: item.precio_unitario,
+                descuento: item.descuento,
+                exento_itbms: item.exento_itbms,
+                impuesto: item.impuesto,
+                total: item.total,
+                categoria: item.categoria,
+                tasa_impuesto: item.tasa_impuesto,
+            });
+        }
+
+        Ok(transaccion)
+    }
+
+    fn actualizar_estado(&self, id_transaccion_yappy: &str, estado: &str) -> QueryResult<usize> {
+        self.datos().actualizar_estado(id_transaccion_yappy, estado)
+    }
+
+    fn finalizar(
+        &self,
+        id_caja: i32,
+        id_transaccion_yappy: &str,
+        estado: &str,
+        motivo: Option<&str>,
+    ) -> QueryResult<()> {
+        let mut datos = self.datos();
+
+        datos.actualizar_estado(id_transaccion_yappy, estado)?;
+        if let Some(motivo) = motivo {
+            datos.transaccion(id_transaccion_yappy)?.motivo_cancelacion = Some(motivo.to_string());
+        }
+
+        if let Some(caja) = datos.cajas.iter_mut().find(|c| c.id == id_caja)
+            && caja.transaccion_actual.as_deref() == Some(id_transaccion_yappy)
+        {
+            caja.transaccion_actual = None;
+        }
+
+        Ok(())
+    }
+
+    fn pendientes(&self, limite: NaiveDateTime) -> QueryResult<Vec<TransaccionPendiente>> {
+        Ok(self
+            .datos()
+            .con_credenciales(|t| t.estado == "PENDING" && t.fecha.is_some_and(|fecha| fecha < limite)))
+    }
+
+    fn canceladas(&self, desde: NaiveDateTime) -> QueryResult<Vec<TransaccionPendiente>> {
+        Ok(self
+            .datos()
+            .con_credenciales(|t| t.estado == "CANCELLED" && t.fecha.is_some_and(|fecha| fecha >= desde)))
+    }
+
+    fn saldo_reembolsable(&self, transaccion: &Transaccion) -> QueryResult<BigDecimal> {
+        Ok(self.datos().saldo_reembolsable(transaccion))
+    }
+
+    fn reembolsos(&self, id_transaccion: i32) -> QueryResult<Vec<Reembolso>> {
+        Ok(self
+            .datos()
+            .reembolsos
+            .iter()
+            .filter(|r| r.id_transaccion == id_transaccion)
+            .cloned()
+            .collect())
+    }
+
+    fn reservar_reembolso(
+        &self,
+        id_transaccion: i32,
+        monto: Option<BigDecimal>,
+        motivo: String,
+        actor: ActorEnum,
+        id_cajero: Option<i32>,
+    ) -> Result<Reembolso, ReembolsoError> {
+        let mut datos = self.datos();
+
+        let transaccion = datos
+            .transacciones
+            .iter()
+            .find(|t| t.id == id_transaccion)
+            .cloned()
+            .ok_or(NotFound)?;
+        let saldo = datos.saldo_reembolsable(&transaccion);
+        let monto = monto.unwrap_or_else(|| saldo.clone());
+
+        if monto <= BigDecimal::zero() || monto > saldo {
+            return Err(ReembolsoError::ExcedeSaldo(saldo));
+        }
+
+        let reembolso = Reembolso {
+            id: datos.reembolsos.len() as i32 + 1,
+            id_transaccion,
+            monto,
+            motivo,
+            actor,
+            id_cajero,
+            exitoso: false,
+            codigo_yappy: None,
+            respuesta_json: json!({}),
+            fecha: Some(tiempo::ahora()),
+            request_id: correlacion::actual(),
+            pendiente: true,
+        };
+        datos.reembolsos.push(reembolso.clone());
+
+        Ok(reembolso)
+    }
+
+    fn completar_reembolso(&self, reembolso: &Reembolso, respuesta_json: &Value) -> QueryResult<Reembolso> {
+        let mut datos = self.datos();
+
+        let codigo_yappy = respuesta_json
+            .pointer("/status/code")
+            .and_then(|c| c.as_str())
+            .map(|s| s.to_string());
+        let exitoso = codigo_yappy.as_deref() == Some("YP-0000");
+
+        let guardado = datos
+            .reembolsos
+            .iter_mut()
+            .find(|r| r.id == reembolso.id)
+            .ok_or(NotFound)?;
+        guardado.pendiente = false;
+        guardado.exitoso = exitoso;
+        guardado.codigo_yappy = codigo_yappy;
+        guardado.respuesta_json = respuesta_json.clone();
+        let guardado = guardado.clone();
+
+        if exitoso {
+            let transaccion = datos
+                .transacciones
+                .iter()
+                .find(|t| t.id == reembolso.id_transaccion)
+                .cloned()
+                .ok_or(NotFound)?;
+
+            let confirmado: BigDecimal = datos
+                .reembolsos
+                .iter()
+                .filter(|r| r.id_transaccion == transaccion.id && r.exitoso)
+                .map(|r| r.monto.clone())
+                .sum();
+
+            if &transaccion.total - confirmado <= BigDecimal::zero() {
+                datos.actualizar_estado(&transaccion.id_transaccion_yappy, "REFUNDED")?;
+            }
+
+            let saldo = datos.saldo_reembolsable(&transaccion);
+            datos.eventos.push((
+                transaccion.id_caja,
+                EventoWebhook::PagoReembolsado,
+                json!({
+                    "id_transaccion": transaccion.id_transaccion_yappy,
+                    "id_orden": transaccion.id_orden,
+                    "monto": guardado.monto,
+                    "motivo": guardado.motivo,
+                    "actor": guardado.actor,
+                    "saldo_reembolsable": saldo,
+                }),
+            ));
+        }
+
+        Ok(guardado)
+    }
+}
+
+impl AuditoriaRepo for MemoriaRepos {
+    fn registrar(&self, registro: NewAuditoria) -> QueryResult<()> {
+        let mut datos = self.datos();
+
+        let id = datos.auditoria.len() as i32 + 1;
+        datos.auditoria.push(Auditoria {
+            id,
+            actor: registro.actor,
+            id_kiosko: registro.id_kiosko,
+            id_caja: registro.id_caja,
+            accion: registro.accion,
+            resumen: registro.resumen,
+            codigo_http: registro.codigo_http,
+            codigo_yappy: registro.codigo_yappy,
+            resultado: registro.resultado,
+            fecha: Some(tiempo::ahora()),
+            id_cajero: registro.id_cajero,
+            request_id: registro.request_id,
+        });
+
+        Ok(())
+    }
+
+    fn listar(&self, filtro: &FiltroAuditoria, grupos: Option<&[i32]>) -> QueryResult<Vec<Auditoria>> {
+        let datos = self.datos();
+        let visible = |id_caja: Option<i32>| match grupos {
+            None => true,
+            Some(grupos) => datos
+                .cajas
+                .iter()
+                .any(|c| Some(c.id) == id_caja && grupos.contains(&c.id_grupo)),
+        };
+        let dia = filtro.dia.map(tiempo::limites_dia);
+
+        Ok(datos
+            .auditoria
+            .iter()
+            .rev()
+            .filter(|a| visible(a.id_caja))
+            .filter(|a| filtro.actor.as_ref().is_none_or(|actor| &a.actor == actor))
+            .filter(|a| filtro.id_caja.is_none_or(|id| a.id_caja == Some(id)))
+            .filter(|a| filtro.id_kiosko.is_none_or(|id| a.id_kiosko == Some(id)))
+            .filter(|a| filtro.accion.as_ref().is_none_or(|accion| &a.accion == accion))
+            .filter(|a| filtro.request_id.is_none() || a.request_id == filtro.request_id)
+            .filter(|a| filtro.desde.is_none_or(|desde| a.fecha.is_some_and(|f| f >= desde)))
+            .filter(|a| filtro.hasta.is_none_or(|hasta| a.fecha.is_some_and(|f| f <= hasta)))
+            .filter(|a| dia.is_none_or(|(inicio, fin)| a.fecha.is_some_and(|f| f >= inicio && f < fin)))
+            .take(filtro.limite.unwrap_or(100).clamp(1, 1000) as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+impl UsuarioRepo for MemoriaRepos {
+    fn activo_por_email(&self, email: &str) -> QueryResult<Usuario> {
+        self.datos()
+            .usuarios
+            .iter()
+            .find(|u| u.email == email && u.activo)
+            .cloned()
+            .ok_or(NotFound)
+    }
+
+    fn activo(&self, id_usuario: i32) -> QueryResult<Usuario> {
+        self.datos()
+            .usuarios
+            .iter()
+            .find(|u| u.id == id_usuario && u.activo)
+            .cloned()
+            .ok_or(NotFound)
+    }
+
+    fn grupos(&self, id_usuario: i32) -> QueryResult<Vec<i32>> {
+        Ok(self
+            .datos()
+            .usuarios_grupos
+            .iter()
+            .filter(|ug| ug.id_usuario == id_usuario)
+            .map(|ug| ug.id_grupo)
+            .collect())
+    }
+
+    fn listar(&self, grupos: Option<&[i32]>) -> QueryResult<Vec<Usuario>> {
+        let datos = self.datos();
+        let visible = |id_usuario: i32| match grupos {
+            None => true,
+            Some(grupos) => datos
+                .usuarios_grupos
+                .iter()
+                .any(|ug| ug.id_usuario == id_usuario && grupos.contains(&ug.id_grupo)),
+        };
+
+        Ok(datos.usuarios.iter().filter(|u| visible(u.id)).cloned().collect())
+    }
+
+    fn contar(&self) -> QueryResult<i64> {
+        Ok(self.datos().usuarios.len() as i64)
+    }
+
+    fn crear(&self, nuevo: NewUsuario, grupos: &[i32]) -> QueryResult<i32> {
+        let mut datos = self.datos();
+
+        // igual que el índice único de la base
+        if datos.usuarios.iter().any(|u| u.email == nuevo.email) {
+            return Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                Box::new("usuarios.email".to_string()),
+            ));
+        }
+
+        let id = datos.usuarios.len() as i32 + 1;
+        datos.usuarios.push(Usuario {
+            id,
+            nombre: nuevo.nombre,
+            email: nuevo.email,
+            password_hash: nuevo.password_hash,
+            rol: nuevo.rol,
+            activo: true,
+            creado: Some(tiempo::ahora()),
+        });
+        datos.usuarios_grupos.extend(grupos.iter().map(|id_grupo| NewUsuarioGrupo {
+            id_usuario: id,
+            id_grupo: *id_grupo,
+        }));
+
+        Ok(id)
+    }
+}
+
+impl WebhookRepo for MemoriaRepos {
+    fn entregas_pendientes(&self, ahora: NaiveDateTime, lote: i64) -> QueryResult<Vec<(WebhookOutbox, Webhook)>> {
+        let datos = self.datos();
+
+        Ok(datos
+            .entregas
+            .iter()
+            .filter(|e| e.estado == "pendiente" && e.proximo_intento <= ahora)
+            .filter_map(|e| {
+                let webhook = datos.webhooks.iter().find(|w| w.id == e.id_webhook && w.activo)?;
+                Some((e.clone(), webhook.clone()))
+            })
+            .take(lote as usize)
+            .collect())
+    }
+
+    fn marcar_entregada(&self, id_entrega: i32, intentos: i32, ahora: NaiveDateTime) -> QueryResult<()> {
+        let mut datos = self.datos();
+        let entrega = datos.entregas.iter_mut().find(|e| e.id == id_entrega).ok_or(NotFound)?;
+
+        entrega.estado = "entregado".to_string();
+        entrega.intentos = intentos;
+        entrega.entregado = Some(ahora);
+        entrega.ultimo_error = None;
+
+        Ok(())
+    }
+
+    fn reprogramar_entrega(
+        &self,
+        id_entrega: i32,
+        intentos: i32,
+        proximo_intento: NaiveDateTime,
+        error: String,
+    ) -> QueryResult<()> {
+        let mut datos = self.datos();
+        let entrega = datos.entregas.iter_mut().find(|e| e.id == id_entrega).ok_or(NotFound)?;
+
+        entrega.intentos = intentos;
+        entrega.proximo_intento = proximo_intento;
+        entrega.ultimo_error = Some(error);
+
+        Ok(())
+    }
+}
+
+/// Datos y estado de la aplicación para las pruebas de handlers y jobs: un grupo, una caja
+/// y su kiosko (`AA:BB:CC:DD:EE:FF`). El pool nunca se toca; si algo lo usa, la prueba falla.
+#[cfg(test)]
+pub mod prueba {
+    use std::time::Duration;
+
+    use diesel::r2d2::{ConnectionManager, Pool};
+
+    use super::*;
+    use crate::AppState;
+    use crate::db::repos::Repos;
+    use crate::db::types::enums::CajasTipoEnum;
+    use crate::utils::monitor_transacciones::MonitorTransacciones;
+
+    pub const MAC: &str = "AA:BB:CC:DD:EE:FF";
+
+    pub fn memoria(estado: CajasEstadoEnum, tipo: CajasTipoEnum) -> Memoria {
+        Memoria {
+            grupos: vec![Grupo {
+                id: 1,
+                id_yappy: "grupo-1".to_string(),
+                nombre: "Grupo 1".to_string(),
+                api_key: "api".to_string(),
+                secret_key: "secret".to_string(),
+                plantilla_recibo: None,
+                correos_cierre: None,
+                version_credenciales: 0,
+            }],
+            cajas: vec![Caja {
+                id: 1,
+                id_grupo: 1,
+                nombre_caja: "Caja 1".to_string(),
+                tipo,
+                token_autorizacion: None,
+                transaccion_actual: None,
+                estado,
+                id_cajero_actual: None,
+            }],
+            kioskos: vec![Kiosko {
+                id: 1,
+                id_caja: 1,
+                nombre: "Kiosko 1".to_string(),
+                mac_address: MAC.to_string(),
+                ultima_conexion: None,
+                version_app: None,
+                ip: None,
+                en_linea: true,
+            }],
+            ..Default::default()
+        }
+    }
+
+    pub fn estado(memoria: Memoria) -> (AppState, MemoriaRepos) {
+        let repos = MemoriaRepos::nuevo(memoria);
+
+        let db_pool = Pool::builder()
+            .max_size(1)
+            .min_idle(Some(0))
+            .connection_timeout(Duration::from_millis(100))
+            .build_unchecked(ConnectionManager::new("sin-base"));
+
+        let state = AppState {
+            db_pool,
+            monitor: MonitorTransacciones::default(),
+            repos: Repos::en_memoria(repos.clone()),
+        };
+
+        (state, repos)
+    }
+
+    /// Deja una caja en `estado` con su última transición en `fecha`.
+    pub fn transicion(memoria: &mut Memoria, id_caja: i32, estado: CajasEstadoEnum, fecha: NaiveDateTime) {
+        let id = memoria.transiciones.len() as i32 + 1;
+        memoria.transiciones.push(CajaTransicion {
+            id,
+            id_caja,
+            estado_anterior: CajasEstadoEnum::Cerrado,
+            estado_nuevo: estado.clone(),
+            actor: ActorEnum::Kiosko,
+            motivo: "prueba".to_string(),
+            fecha: Some(fecha),
+        });
+        memoria.caja(id_caja).unwrap().estado = estado;
+    }
+
+    /// Agrega a la caja 1 una venta de 10.70 en `estado`, creada en `fecha`.
+    pub fn transaccion(memoria: &mut Memoria, id_transaccion_yappy: &str, estado: &str, fecha: NaiveDateTime) {
+        let id = memoria.transacciones.len() as i32 + 1;
+        memoria.transacciones.push(Transaccion {
+            id,
+            id_caja: 1,
+            id_cajero: None,
+            id_transaccion_yappy: id_transaccion_yappy.to_string(),
+            id_orden: None,
+            tipo_qr: "DYN".to_string(),
+            subtotal: "10.00".parse().unwrap(),
+            impuesto: "0.70".parse().unwrap(),
+            propina: BigDecimal::zero(),
+            descuento: BigDecimal::zero(),
+            total: "10.70".parse().unwrap(),
+            estado: estado.to_string(),
+            fecha: Some(fecha),
+            fecha_actualizacion: None,
+            motivo_cancelacion: None,
+            qr_hash: None,
+            tasa_impuesto: None,
+            discrepancia_impuesto: None,
+            request_id: None,
+        });
+    }
+}
@@ -0,0 +1,320 @@
+//! Consultas de kioskos, cajas, grupos, cierres, transacciones, auditoría, usuarios y
+//! webhooks detrás de traits, para que los handlers y los schedulers no dependan de Diesel. `DieselRepos` usa la base;
+//! `MemoriaRepos` guarda todo en memoria y sirve para probar sin base.
+//!
+//! Lo que tiene que ir junto (una transición con su evento de webhook, un reembolso con
+//! el cambio de estado de la venta) es un solo método, así cada implementación decide
+//! cómo hacerlo atómico.
+
+pub mod base;
+pub mod cache;
+pub mod memoria;
+
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::QueryResult;
+use serde_json::Value;
+
+use crate::db::conection::DbPool;
+use crate::db::models::{
+    AlcanceCierre, Auditoria, Caja, CajaWithCreds, Cajero, EventoWebhook, FiltroAuditoria, Grupo,
+    ImpuestosGrupo, Kiosko, KioskoInfo, NewAuditoria, NewCajaCierreError, NewCajaCierreResumen,
+    NewTransaccion, NewTransaccionItem, NewUsuario, Reembolso, ReembolsoError, Transaccion,
+    TransaccionItem, TransaccionPendiente, TransicionError, Usuario, Webhook, WebhookOutbox,
+};
+use crate::db::types::enums::{ActorEnum, CajasEstadoEnum};
+
+pub use self::base::DieselRepos;
+pub use self::cache::KioskosEnCache;
+pub use self::memoria::{Memoria, MemoriaRepos};
+
+/// Cómo se indica la venta a reembolsar.
+#[derive(Debug, Clone)]
+pub enum ReferenciaVenta {
+    Id(i32),
+    Yappy(String),
+    /// La última venta COMPLETED con ese `id_orden`.
+    Orden(String),
+}
+
+/// Lo que puede cambiar entre dos solicitudes del mismo kiosko, aunque lo cambie otro proceso.
+#[derive(Debug, Clone)]
+pub struct ContextoVigente {
+    pub id_caja: i32,
+    pub estado: CajasEstadoEnum,
+    pub token_autorizacion: Option<String>,
+    pub id_cajero: Option<i32>,
+    pub version_credenciales: i32,
+}
+
+pub trait KioskoRepo: Send + Sync {
+    /// Kiosko con su caja, su grupo y el cajero en turno.
+    fn info_por_mac(&self, mac_address: &str) -> QueryResult<KioskoInfo>;
+
+    /// Una sola fila con el estado, el token y el cajero de la caja del kiosko y la versión
+    /// de las credenciales de su grupo.
+    fn vigente_por_mac(&self, mac_address: &str) -> QueryResult<ContextoVigente>;
+
+    fn listar(&self) -> QueryResult<Vec<Kiosko>>;
+
+    /// Marca fuera de línea los kioskos sin heartbeat desde `limite`; devuelve cuántos.
+    fn marcar_desconectados(&self, limite: NaiveDateTime) -> QueryResult<usize>;
+
+    /// Marca el kiosko en línea; sin `version_app` conserva la que ya se conoce.
+    fn registrar_latido(
+        &self,
+        id_kiosko: i32,
+        ip: String,
+        version_app: Option<String>,
+        ahora: NaiveDateTime,
+    ) -> QueryResult<()>;
+
+    /// Después de cambiar la caja en este proceso; sin cache no hace nada.
+    fn invalidar_caja(&self, _id_caja: i32) {}
+}
+
+pub trait CajaRepo: Send + Sync {
+    fn buscar(&self, id_caja: i32) -> QueryResult<Caja>;
+
+    fn listar(&self) -> QueryResult<Vec<Caja>>;
+
+    fn fijar_transaccion_actual(&self, id_caja: i32, transaccion: Option<&str>) -> QueryResult<()>;
+
+    /// Deja la caja sin transacción en curso y devuelve la que tenía.
+    fn soltar_transaccion_actual(&self, id_caja: i32) -> QueryResult<Option<String>>;
+
+    fn buscar_cajero(&self, id_cajero: i32) -> QueryResult<Cajero>;
+
+    /// Si el cajero está en turno en alguna caja distinta de `id_caja`.
+    fn turno_en_otra_caja(&self, id_cajero: i32, id_caja: i32) -> QueryResult<bool>;
+
+    /// Deja al cajero en turno en la caja y abre su fila en `sesiones_cajero`.
+    fn iniciar_turno(&self, id_caja: i32, id_cajero: i32) -> QueryResult<()>;
+
+    /// Saca al cajero en turno de la caja y cierra su sesión abierta.
+    fn revertir_turno(&self, id_caja: i32) -> QueryResult<()>;
+
+    /// Cambia el estado si la transición es válida y la registra en `caja_transiciones`;
+    /// con `evento` lo encola en la misma transacción. Devuelve el estado anterior.
+    fn transicionar(
+        &self,
+        id_caja: i32,
+        nuevo: CajasEstadoEnum,
+        actor: ActorEnum,
+        motivo: String,
+        evento: Option<(EventoWebhook, Value)>,
+    ) -> Result<CajasEstadoEnum, TransicionError>;
+
+    /// Saca a la caja de `Abriendo` o `Cerrando` hacia su estado de recuperación
+    /// (`CajasEstadoEnum::recuperacion`). Devuelve el estado nuevo, o `None` si la caja
+    /// no estaba a medias.
+    fn recuperar(
+        &self,
+        id_caja: i32,
+        actor: ActorEnum,
+        motivo: String,
+    ) -> Result<Option<CajasEstadoEnum>, TransicionError>;
+
+    /// Cajas en `Abriendo` o `Cerrando` con la fecha de su última transición.
+    fn a_medias(&self) -> QueryResult<Vec<(i32, CajasEstadoEnum, Option<NaiveDateTime>)>>;
+
+    /// Guarda el token de la apertura y pasa la caja a `Abierto` con `caja.abierta`;
+    /// sin token la devuelve a `Cerrado`. Devuelve el estado nuevo.
+    fn terminar_apertura(
+        &self,
+        id_caja: i32,
+        token: Option<&str>,
+        actor: ActorEnum,
+        motivo: String,
+        datos: Value,
+    ) -> Result<CajasEstadoEnum, TransicionError>;
+
+    /// Borra token y cajero, termina el turno abierto y pasa la caja a `Cerrado` con
+    /// `cierre.finalizado`, todo junto.
+    fn terminar_cierre(
+        &self,
+        id_caja: i32,
+        actor: ActorEnum,
+        motivo: String,
+        datos: Value,
+    ) -> Result<CajasEstadoEnum, TransicionError>;
+}
+
+pub trait GrupoRepo: Send + Sync {
+    fn buscar(&self, id_grupo: i32) -> QueryResult<Grupo>;
+
+    /// Con `None` devuelve todos los grupos.
+    fn listar(&self, visibles: Option<&[i32]>) -> QueryResult<Vec<Grupo>>;
+
+    /// Configuración de impuestos del grupo, si tiene una propia.
+    fn impuestos(&self, id_grupo: i32) -> QueryResult<Option<ImpuestosGrupo>>;
+}
+
+pub trait CierreRepo: Send + Sync {
+    /// Cajas abiertas o con error de cierre dentro del alcance, con las credenciales de su grupo.
+    fn cajas_por_cerrar(&self, alcance: &AlcanceCierre) -> QueryResult<Vec<CajaWithCreds>>;
+
+    fn guardar_resumen(&self, resumen: NewCajaCierreResumen) -> QueryResult<()>;
+
+    fn guardar_error(&self, error: NewCajaCierreError) -> QueryResult<()>;
+
+    /// Último id de `caja_cierre_resumen` y de `caja_cierre_errores`, para separar lo que
+    /// guarda una corrida de lo anterior.
+    fn ultimos_ids(&self) -> QueryResult<(i32, i32)>;
+
+    /// Resúmenes y errores de esas cajas guardados después de los ids de `ultimos_ids`,
+    /// en el orden en que se guardaron.
+    fn guardados_desde(
+        &self,
+        ids_cajas: &[i32],
+        desde: (i32, i32),
+    ) -> QueryResult<(Vec<NewCajaCierreResumen>, Vec<NewCajaCierreError>)>;
+}
+
+pub trait TransaccionRepo: Send + Sync {
+    fn buscar(&self, id_transaccion_yappy: &str) -> QueryResult<Transaccion>;
+
+    fn buscar_por_id(&self, id: i32) -> QueryResult<Transaccion>;
+
+    /// Venta de cualquier caja del grupo; si hay varias, la más nueva.
+    fn de_grupo(&self, id_grupo: i32, referencia: &ReferenciaVenta) -> QueryResult<Transaccion>;
+
+    fn items(&self, id_transaccion: i32) -> QueryResult<Vec<TransaccionItem>>;
+
+    /// Guarda la transacción y sus items en una sola transacción; devuelve la fila creada.
+    fn registrar(&self, nueva: NewTransaccion, items: Vec<NewTransaccionItem>) -> QueryResult<Transaccion>;
+
+    /// Cambia el estado; al pasar a COMPLETED encola `pago.completado` una sola vez.
+    fn actualizar_estado(&self, id_transaccion_yappy: &str, estado: &str) -> QueryResult<usize>;
+
+    /// Deja la transacción en `estado` y libera la caja si todavía apunta a ella.
+    fn finalizar(
+        &self,
+        id_caja: i32,
+        id_transaccion_yappy: &str,
+        estado: &str,
+        motivo: Option<&str>,
+    ) -> QueryResult<()>;
+
+    /// Transacciones PENDING creadas antes de `limite`, con las credenciales de su caja.
+    fn pendientes(&self, limite: NaiveDateTime) -> QueryResult<Vec<TransaccionPendiente>>;
+
+    /// Transacciones CANCELLED creadas desde `desde`: su QR todavía se puede pagar en Yappy.
+    fn canceladas(&self, desde: NaiveDateTime) -> QueryResult<Vec<TransaccionPendiente>>;
+
+    /// Lo que aún se puede devolver de una venta: el total menos los reembolsos exitosos
+    /// y los pendientes.
+    fn saldo_reembolsable(&self, transaccion: &Transaccion) -> QueryResult<BigDecimal>;
+
+    /// Reembolsos de la venta, del más viejo al más nuevo.
+    fn reembolsos(&self, id_transaccion: i32) -> QueryResult<Vec<Reembolso>>;
+
+    /// Aparta `monto` (con `None`, todo el saldo) en un reembolso pendiente antes de llamar
+    /// a Yappy. La venta queda bloqueada mientras se revisa el saldo, así dos reembolsos a
+    /// la vez no pasan del total.
+    fn reservar_reembolso(
+        &self,
+        id_transaccion: i32,
+        monto: Option<BigDecimal>,
+        motivo: String,
+        actor: ActorEnum,
+        id_cajero: Option<i32>,
+    ) -> Result<Reembolso, ReembolsoError>;
+
+    /// Guarda la respuesta de Yappy en el reembolso pendiente; si lo aceptó y no queda
+    /// saldo, marca la venta como REFUNDED. Si lo rechazó, el monto vuelve al saldo.
+    fn completar_reembolso(&self, reembolso: &Reembolso, respuesta_json: &Value) -> QueryResult<Reembolso>;
+}
+
+pub trait AuditoriaRepo: Send + Sync {
+    fn registrar(&self, registro: NewAuditoria) -> QueryResult<()>;
+
+    /// Más recientes primero; con `grupos`, solo los registros de cajas de esos grupos.
+    fn listar(&self, filtro: &FiltroAuditoria, grupos: Option<&[i32]>) -> QueryResult<Vec<Auditoria>>;
+}
+
+pub trait UsuarioRepo: Send + Sync {
+    /// Usuario activo con ese email, ya normalizado.
+    fn activo_por_email(&self, email: &str) -> QueryResult<Usuario>;
+
+    fn activo(&self, id_usuario: i32) -> QueryResult<Usuario>;
+
+    fn grupos(&self, id_usuario: i32) -> QueryResult<Vec<i32>>;
+
+    /// Por id; con `grupos`, solo los que comparten alguno de esos grupos.
+    fn listar(&self, grupos: Option<&[i32]>) -> QueryResult<Vec<Usuario>>;
+
+    fn contar(&self) -> QueryResult<i64>;
+
+    /// Crea el usuario y sus grupos en una sola transacción; devuelve su id.
+    fn crear(&self, nuevo: NewUsuario, grupos: &[i32]) -> QueryResult<i32>;
+}
+
+pub trait WebhookRepo: Send + Sync {
+    /// Entregas pendientes de webhooks activos con el próximo intento vencido, por id.
+    fn entregas_pendientes(&self, ahora: NaiveDateTime, lote: i64) -> QueryResult<Vec<(WebhookOutbox, Webhook)>>;
+
+    fn marcar_entregada(&self, id_entrega: i32, intentos: i32, ahora: NaiveDateTime) -> QueryResult<()>;
+
+    /// Deja la entrega pendiente para `proximo_intento` con el error del intento fallido.
+    fn reprogramar_entrega(
+        &self,
+        id_entrega: i32,
+        intentos: i32,
+        proximo_intento: NaiveDateTime,
+        error: String,
+    ) -> QueryResult<()>;
+}
+
+/// Los repositorios que usa la aplicación, en `AppState`.
+#[derive(Clone)]
+pub struct Repos {
+    pub kioskos: Arc<dyn KioskoRepo>,
+    pub cajas: Arc<dyn CajaRepo>,
+    pub grupos: Arc<dyn GrupoRepo>,
+    pub cierres: Arc<dyn CierreRepo>,
+    pub transacciones: Arc<dyn TransaccionRepo>,
+    pub auditoria: Arc<dyn AuditoriaRepo>,
+    pub usuarios: Arc<dyn UsuarioRepo>,
+    pub webhooks: Arc<dyn WebhookRepo>,
+}
+
+impl Repos {
+    /// El contexto de los kioskos se guarda `KIOSKOS_CACHE_SEGUNDOS` (60 por defecto, 0 lo apaga).
+    pub fn diesel(pool: DbPool) -> Self {
+        let ttl = env::var("KIOSKOS_CACHE_SEGUNDOS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(60);
+
+        let repos = Arc::new(DieselRepos::new(pool));
+        Repos {
+            kioskos: Arc::new(KioskosEnCache::new(repos.clone(), Duration::from_secs(ttl))),
+            cajas: repos.clone(),
+            grupos: repos.clone(),
+            cierres: repos.clone(),
+            transacciones: repos.clone(),
+            auditoria: repos.clone(),
+            usuarios: repos.clone(),
+            webhooks: repos,
+        }
+    }
+
+    pub fn en_memoria(repos: MemoriaRepos) -> Self {
+        let repos = Arc::new(repos);
+        Repos {
+            kioskos: repos.clone(),
+            cajas: repos.clone(),
+            grupos: repos.clone(),
+            cierres: repos.clone(),
+            transacciones: repos.clone(),
+            auditoria: repos.clone(),
+            usuarios: repos.clone(),
+            webhooks: repos,
+        }
+    }
+}
@@ -1,7 +1,150 @@
 use serde::{Deserialize, Serialize};
-#[derive(diesel_derive_enum::DbEnum, Debug, Serialize, Deserialize, Clone, PartialEq)]
-#[serde(rename_all = "lowercase")]
+use utoipa::ToSchema;
+#[derive(diesel_derive_enum::DbEnum, Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
 pub enum CajasEstadoEnum {
-    Cerrado,
+    Abriendo,
     Abierto,
-}
\ No newline at end of file
+    Cerrando,
+    Cerrado,
+    ErrorCierre,
+    Deshabilitado,
+}
+
+impl CajasEstadoEnum {
+    /// Indica si la caja puede pasar de este estado a `nuevo`.
+    pub fn puede_transicionar(&self, nuevo: &CajasEstadoEnum) -> bool {
+        use CajasEstadoEnum::*;
+
+        matches!(
+            (self, nuevo),
+            (Cerrado, Abriendo)
+                | (Cerrado, Deshabilitado)
+                // reabrir una sesion ya abierta renueva el token
+                | (Abierto, Abriendo)
+                | (Abriendo, Abierto)
+                | (Abriendo, Cerrado)
+                | (Abierto, Cerrando)
+                | (Cerrando, Cerrado)
+                | (Cerrando, ErrorCierre)
+                | (ErrorCierre, Cerrando)
+                | (ErrorCierre, Deshabilitado)
+                | (Deshabilitado, Cerrado)
+        )
+    }
+
+    /// Adónde vuelve una caja que quedó a mitad de una apertura o un cierre (kiosko caído,
+    /// solicitud vencida, respuesta ilegible de Yappy); `None` si no está en transición.
+    pub fn recuperacion(&self) -> Option<CajasEstadoEnum> {
+        use CajasEstadoEnum::*;
+
+        match self {
+            Abriendo => Some(Cerrado),
+            Cerrando => Some(ErrorCierre),
+            _ => None,
+        }
+    }
+}
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CajasTipoEnum {
+    Kiosko,
+    Cajero,
+}
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ActorEnum {
+    Kiosko,
+    Cajero,
+    Scheduler,
+    Admin,
+}
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RolUsuarioEnum {
+    Superadmin,
+    GrupoAdmin,
+    Finanzas,
+    Soporte,
+}
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModoRedondeoEnum {
+    HalfUp,
+    HalfEven,
+    Up,
+    Down,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CajasEstadoEnum::{self, *};
+
+    const ESTADOS: [CajasEstadoEnum; 6] =
+        [Abriendo, Abierto, Cerrando, Cerrado, ErrorCierre, Deshabilitado];
+
+    const VALIDAS: [(CajasEstadoEnum, CajasEstadoEnum); 11] = [
+        (Cerrado, Abriendo),
+        (Cerrado, Deshabilitado),
+        (Abierto, Abriendo),
+        (Abriendo, Abierto),
+        (Abriendo, Cerrado),
+        (Abierto, Cerrando),
+        (Cerrando, Cerrado),
+        (Cerrando, ErrorCierre),
+        (ErrorCierre, Cerrando),
+        (ErrorCierre, Deshabilitado),
+        (Deshabilitado, Cerrado),
+    ];
+
+    #[test]
+    fn tabla_de_transiciones() {
+        for anterior in &ESTADOS {
+            for nuevo in &ESTADOS {
+                let esperada = VALIDAS.contains(&(anterior.clone(), nuevo.clone()));
+                assert_eq!(
+                    anterior.puede_transicionar(nuevo),
+                    esperada,
+                    "{:?} -> {:?}",
+                    anterior,
+                    nuevo
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ningun_estado_se_repite() {
+        for estado in &ESTADOS {
+            assert!(!estado.puede_transicionar(estado), "{:?}", estado);
+        }
+    }
+
+    #[test]
+    fn todo_estado_tiene_salida() {
+        for estado in &ESTADOS {
+            assert!(
+                ESTADOS.iter().any(|nuevo| estado.puede_transicionar(nuevo)),
+                "{:?} no tiene salida",
+                estado
+            );
+        }
+    }
+
+    #[test]
+    fn recuperacion_de_estados_intermedios() {
+        assert_eq!(Abriendo.recuperacion(), Some(Cerrado));
+        assert_eq!(Cerrando.recuperacion(), Some(ErrorCierre));
+
+        for estado in &ESTADOS {
+            match estado.recuperacion() {
+                Some(destino) => assert!(estado.puede_transicionar(&destino)),
+                None => assert!(!matches!(estado, Abriendo | Cerrando)),
+            }
+        }
+    }
+}
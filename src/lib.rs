@@ -0,0 +1,18 @@
+pub mod schema;
+pub mod start_axum;
+pub mod db;
+pub mod controllers;
+pub mod middlewares;
+pub mod schedulers;
+pub mod utils;
+
+use crate::db::conection::DbPool;
+use crate::db::repos::Repos;
+use crate::utils::monitor_transacciones::MonitorTransacciones;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db_pool: DbPool,
+    pub monitor: MonitorTransacciones,
+    pub repos: Repos,
+}
@@ -1,30 +1,32 @@
-pub mod schema;
-pub mod start_axum;
-pub mod db;
-pub mod controllers;
-pub mod schedulers;
-pub mod utils;
-
 use dotenvy::dotenv;
-use start_axum::start_axum;
-use schedulers::cajas::cerrar_cajas_job;
-
-use crate::db::conection::{create_pool, MySqlPool};
+use macy_utp::AppState;
+use macy_utp::start_axum::start_axum;
+use macy_utp::schedulers::cajas::{cerrar_cajas_job, recuperar_cajas_job};
+use macy_utp::schedulers::kioskos::kioskos_desconectados_job;
+use macy_utp::schedulers::transacciones::expirar_transacciones_job;
+use macy_utp::schedulers::webhooks::entregar_webhooks_job;
 
-#[derive(Clone)]
-pub struct AppState {
-    pub db_pool: MySqlPool,
-}
+use macy_utp::db::conection::create_pool;
+use macy_utp::db::repos::Repos;
+use macy_utp::utils::monitor_transacciones::MonitorTransacciones;
+use macy_utp::utils::usuarios::crear_superadmin_inicial;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
     let db_pool = create_pool();
-    let state = AppState { db_pool };
+    let state = AppState {
+        repos: Repos::diesel(db_pool.clone()),
+        db_pool,
+        monitor: MonitorTransacciones::default(),
+    };
+
+    crear_superadmin_inicial(&state);
     
     cerrar_cajas_job(&state).await.unwrap();
+    recuperar_cajas_job(&state).await.unwrap();
+    kioskos_desconectados_job(&state).await.unwrap();
+    expirar_transacciones_job(&state).await.unwrap();
+    entregar_webhooks_job(&state).await.unwrap();
     start_axum(&state).await.unwrap();
 }
-
-
-
@@ -0,0 +1,79 @@
+use crate::AppState;
+use crate::db::models::NewAuditoria;
+use crate::db::types::enums::ActorEnum;
+use crate::middlewares::correlacion;
+use crate::utils::auditoria::{redactar, registrar_auditoria, resultado_de};
+use crate::utils::utils::{get_info_by_mac_address, json_error};
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Value, json};
+
+const LIMITE_CUERPO: usize = 64 * 1024;
+
+/// Registra en `auditoria` cada llamada de un kiosko: quién, qué pidió y qué respondió Yappy.
+pub async fn auditar_kiosko(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let accion = request.uri().path().trim_start_matches('/').to_string();
+    let metodo = request.method().to_string();
+    let info = request
+        .headers()
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok())
+        .and_then(|mac_address| get_info_by_mac_address(&state, mac_address).ok());
+
+    let (parts, body) = request.into_parts();
+    let cuerpo = match to_bytes(body, LIMITE_CUERPO).await {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return json_error(StatusCode::PAYLOAD_TOO_LARGE, "Cuerpo demasiado grande")
+                .into_response();
+        }
+    };
+    let mut cuerpo_json = serde_json::from_slice::<Value>(&cuerpo).unwrap_or(Value::Null);
+    redactar(&mut cuerpo_json);
+
+    let response = next.run(Request::from_parts(parts, Body::from(cuerpo))).await;
+
+    let (parts, body) = response.into_parts();
+    let respuesta = to_bytes(body, usize::MAX).await.unwrap_or_default();
+    let respuesta_json = serde_json::from_slice::<Value>(&respuesta).unwrap_or(Value::Null);
+
+    let codigo_yappy = respuesta_json
+        .pointer("/data/status/code")
+        .or_else(|| respuesta_json.pointer("/data/yappy/status/code"))
+        // las rutas /v1 lo devuelven ya normalizado
+        .or_else(|| respuesta_json.pointer("/data/codigo_yappy"))
+        .or_else(|| respuesta_json.pointer("/error/detalles/codigo_yappy"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+
+    registrar_auditoria(
+        &state,
+        NewAuditoria {
+            actor: info.as_ref().map_or(ActorEnum::Kiosko, |i| i.actor()),
+            id_kiosko: info.as_ref().map(|i| i.id_kiosko),
+            id_caja: info.as_ref().map(|i| i.id_caja),
+            id_cajero: info.as_ref().and_then(|i| i.id_cajero),
+            request_id: correlacion::actual(),
+            resultado: resultado_de(parts.status.is_success(), codigo_yappy.as_deref()),
+            resumen: json!({
+                "metodo": metodo,
+                "cuerpo": cuerpo_json,
+                "error": respuesta_json.get("error"),
+            }),
+            codigo_http: Some(parts.status.as_u16() as i32),
+            codigo_yappy,
+            accion,
+        },
+    );
+
+    Response::from_parts(parts, Body::from(respuesta))
+}
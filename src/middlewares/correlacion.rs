@@ -0,0 +1,47 @@
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+use uuid::Uuid;
+
+pub const ENCABEZADO: &str = "x-request-id";
+
+tokio::task_local! {
+    static ID_SOLICITUD: String;
+}
+
+/// Id de correlación de la solicitud, disponible en las extensiones del request.
+#[derive(Debug, Clone)]
+pub struct IdSolicitud(pub String);
+
+/// Id de la solicitud que se está atendiendo; `None` fuera de una solicitud (jobs, CLI).
+pub fn actual() -> Option<String> {
+    ID_SOLICITUD.try_with(|id| id.clone()).ok()
+}
+
+// se acepta el id del kiosko solo si es corto y sin caracteres raros, va a logs y a la base
+fn id_valido(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+/// Toma el `X-Request-Id` del cliente o genera uno, lo deja disponible con `actual()`
+/// mientras se atiende la solicitud y lo devuelve en la respuesta.
+pub async fn correlacionar(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(ENCABEZADO)
+        .and_then(|valor| valor.to_str().ok())
+        .filter(|id| id_valido(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    request.extensions_mut().insert(IdSolicitud(id.clone()));
+
+    let mut response = ID_SOLICITUD.scope(id.clone(), next.run(request)).await;
+
+    if let Ok(valor) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(ENCABEZADO, valor);
+    }
+    response
+}
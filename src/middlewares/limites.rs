@@ -0,0 +1,230 @@
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    num::NonZeroU32,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use governor::{
+    DefaultKeyedRateLimiter, Quota, RateLimiter,
+    clock::{Clock, DefaultClock},
+};
+use ipnet::IpNet;
+
+use crate::controllers::structs::v1::ApiError;
+use crate::utils::utils::json_error;
+
+/// Proxies de `PROXIES_CONFIABLES` (ips o rangos CIDR separados por coma) cuyo
+/// `X-Real-IP` se respeta.
+static PROXIES_CONFIABLES: LazyLock<Vec<IpNet>> = LazyLock::new(|| {
+    env::var("PROXIES_CONFIABLES")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|red| red_confiable(red.trim()))
+        .collect()
+});
+
+/// `10.0.0.0/8` o una ip suelta, que se toma como un rango de una sola ip.
+fn red_confiable(valor: &str) -> Option<IpNet> {
+    valor
+        .parse()
+        .ok()
+        .or_else(|| valor.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// IP de origen de la petición. `X-Real-IP` solo vale si la conexión viene de un proxy
+/// confiable; cualquier otro cliente podría inventarlo para esquivar los límites.
+pub fn ip_cliente(headers: &HeaderMap, addr: SocketAddr) -> IpAddr {
+    ip_detras_de(headers, addr, &PROXIES_CONFIABLES)
+}
+
+fn ip_detras_de(headers: &HeaderMap, addr: SocketAddr, proxies: &[IpNet]) -> IpAddr {
+    if !proxies.iter().any(|red| red.contains(&addr.ip())) {
+        return addr.ip();
+    }
+
+    headers
+        .get("x-real-ip")
+        .and_then(|val| val.to_str().ok())
+        .and_then(|ip| ip.trim().parse().ok())
+        .unwrap_or_else(|| addr.ip())
+}
+
+/// Cubeta de tokens por `mac-address` y otra por IP de origen, con la misma cuota.
+#[derive(Clone)]
+pub struct Limitador {
+    por_mac: Arc<DefaultKeyedRateLimiter<String>>,
+    por_ip: Arc<DefaultKeyedRateLimiter<String>>,
+}
+
+impl Limitador {
+    /// Lee la cuota de `LIMITE_<nombre>` con formato `rafaga/por_minuto`, p. ej. `5/20`.
+    pub fn desde_env(nombre: &str, rafaga: u32, por_minuto: u32) -> Self {
+        let (rafaga, por_minuto) = env::var(format!("LIMITE_{}", nombre))
+            .ok()
+            .and_then(|valor| {
+                let (r, m) = valor.split_once('/')?;
+                Some((r.trim().parse().ok()?, m.trim().parse().ok()?))
+            })
+            .unwrap_or((rafaga, por_minuto));
+
+        let quota = Quota::per_minute(NonZeroU32::new(por_minuto).unwrap_or(NonZeroU32::MIN))
+            .allow_burst(NonZeroU32::new(rafaga).unwrap_or(NonZeroU32::MIN));
+
+        Limitador {
+            por_mac: Arc::new(RateLimiter::keyed(quota)),
+            por_ip: Arc::new(RateLimiter::keyed(quota)),
+        }
+    }
+
+    /// `Err` con el tiempo de espera si la mac o la ip ya agotaron su cuota.
+    fn revisar(&self, mac_address: Option<&str>, ip: &str) -> Result<(), Duration> {
+        let reloj = DefaultClock::default();
+
+        if let Some(mac_address) = mac_address {
+            self.por_mac
+                .check_key(&mac_address.to_string())
+                .map_err(|espera| espera.wait_time_from(reloj.now()))?;
+        }
+
+        self.por_ip
+            .check_key(&ip.to_string())
+            .map_err(|espera| espera.wait_time_from(reloj.now()))
+    }
+
+    fn limpiar(&self) {
+        self.por_mac.retain_recent();
+        self.por_ip.retain_recent();
+    }
+}
+
+/// Limitadores por ruta; las rutas que no aparecen usan el general.
+#[derive(Clone)]
+pub struct Limites {
+    rutas: Vec<(&'static [&'static str], Limitador)>,
+    general: Limitador,
+}
+
+impl Limites {
+    pub fn new(general: Limitador) -> Self {
+        Limites {
+            rutas: Vec::new(),
+            general,
+        }
+    }
+
+    /// Las rutas listadas comparten el mismo limitador, así un alias no duplica la cuota.
+    pub fn ruta(mut self, rutas: &'static [&'static str], limitador: Limitador) -> Self {
+        self.rutas.push((rutas, limitador));
+        self
+    }
+
+    fn para(&self, ruta: &str) -> &Limitador {
+        self.rutas
+            .iter()
+            .find(|(rutas, _)| rutas.contains(&ruta))
+            .map_or(&self.general, |(_, limitador)| limitador)
+    }
+
+    /// Descarta cada minuto las llaves que ya recuperaron toda su cuota.
+    pub fn iniciar_limpieza(&self) {
+        let limites = self.clone();
+        tokio::spawn(async move {
+            let mut intervalo = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                intervalo.tick().await;
+                limites.general.limpiar();
+                for (_, limitador) in &limites.rutas {
+                    limitador.limpiar();
+                }
+            }
+        });
+    }
+}
+
+pub async fn limitar(
+    State(limites): State<Limites>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ruta = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|ruta| ruta.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let mac_address = request
+        .headers()
+        .get("mac-address")
+        .and_then(|val| val.to_str().ok());
+
+    let ip = ip_cliente(request.headers(), addr).to_string();
+
+    let Err(espera) = limites.para(&ruta).revisar(mac_address, &ip) else {
+        return next.run(request).await;
+    };
+
+    tracing::warn!(
+        "Límite de solicitudes excedido en {} (mac: {:?}, ip: {})",
+        ruta, mac_address, ip
+    );
+
+    let mensaje = "Demasiadas solicitudes, intente más tarde";
+    let mut response = if ruta.starts_with("/v1") {
+        ApiError::new(StatusCode::TOO_MANY_REQUESTS, "demasiadas_solicitudes", mensaje)
+            .into_response()
+    } else {
+        json_error(StatusCode::TOO_MANY_REQUESTS, mensaje).into_response()
+    };
+
+    let segundos = espera.as_secs() + u64::from(espera.subsec_nanos() > 0);
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(segundos.max(1)));
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn con_real_ip(ip: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", HeaderValue::from_static(ip));
+        headers
+    }
+
+    #[test]
+    fn x_real_ip_solo_vale_detras_de_un_proxy_confiable() {
+        let proxy: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let cliente: SocketAddr = "203.0.113.7:4000".parse().unwrap();
+        let proxies = [red_confiable("10.0.0.1").unwrap()];
+        let headers = con_real_ip("198.51.100.9");
+
+        assert_eq!(ip_detras_de(&headers, proxy, &proxies).to_string(), "198.51.100.9");
+        assert_eq!(ip_detras_de(&headers, cliente, &proxies).to_string(), "203.0.113.7");
+        assert_eq!(ip_detras_de(&headers, proxy, &[]).to_string(), "10.0.0.1");
+        assert_eq!(ip_detras_de(&con_real_ip("basura"), proxy, &proxies).to_string(), "10.0.0.1");
+    }
+
+    #[test]
+    fn un_rango_confiable_cubre_la_red_de_docker() {
+        let nginx: SocketAddr = "172.28.0.5:4000".parse().unwrap();
+        let afuera: SocketAddr = "172.29.0.5:4000".parse().unwrap();
+        let proxies = [red_confiable("172.28.0.0/16").unwrap()];
+        let headers = con_real_ip("198.51.100.9");
+
+        assert_eq!(ip_detras_de(&headers, nginx, &proxies).to_string(), "198.51.100.9");
+        assert_eq!(ip_detras_de(&headers, afuera, &proxies).to_string(), "172.29.0.5");
+        assert!(red_confiable("basura").is_none());
+    }
+}
@@ -0,0 +1,5 @@
+pub mod auditoria;
+pub mod usuarios;
+pub mod obsoletas;
+pub mod limites;
+pub mod correlacion;
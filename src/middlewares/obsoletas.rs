@@ -0,0 +1,12 @@
+use axum::{http::HeaderValue, response::Response};
+
+/// Marca las rutas sin versión como obsoletas; los clientes deben migrar a `/v1`.
+pub async fn marcar_obsoleta(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    headers.insert(
+        "link",
+        HeaderValue::from_static("</v1>; rel=\"successor-version\""),
+    );
+    response
+}
@@ -0,0 +1,86 @@
+use axum::{
+    Json,
+    extract::FromRequestParts,
+    http::{StatusCode, header, request::Parts},
+};
+use serde_json::Value;
+
+use crate::AppState;
+use crate::db::types::enums::RolUsuarioEnum;
+use crate::utils::credenciales::verificar_token;
+use crate::utils::utils::json_error;
+
+/// Usuario administrativo autenticado con `Authorization: Bearer <jwt>`.
+/// El usuario y sus grupos se leen en cada request, así que desactivarlo
+/// o cambiarle el alcance aplica de inmediato.
+#[derive(Debug, Clone)]
+pub struct UsuarioSesion {
+    pub id: i32,
+    pub nombre: String,
+    pub rol: RolUsuarioEnum,
+    pub grupos: Vec<i32>,
+}
+
+impl FromRequestParts<AppState> for UsuarioSesion {
+    type Rejection = (StatusCode, Json<Value>);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let id_usuario = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|val| val.to_str().ok())
+            .and_then(|val| val.strip_prefix("Bearer "))
+            .and_then(verificar_token)
+            .ok_or_else(|| json_error(StatusCode::UNAUTHORIZED, "Sesión inválida o expirada"))?;
+
+        let usuario = state
+            .repos
+            .usuarios
+            .activo(id_usuario)
+            .map_err(|_| json_error(StatusCode::UNAUTHORIZED, "Sesión inválida o expirada"))?;
+
+        let grupos = state
+            .repos
+            .usuarios
+            .grupos(usuario.id)
+            .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?;
+
+        Ok(UsuarioSesion {
+            id: usuario.id,
+            nombre: usuario.nombre,
+            rol: usuario.rol,
+            grupos,
+        })
+    }
+}
+
+impl UsuarioSesion {
+    pub fn es_superadmin(&self) -> bool {
+        self.rol == RolUsuarioEnum::Superadmin
+    }
+
+    /// El superadmin pasa siempre; el resto debe tener alguno de los roles indicados.
+    pub fn exigir_rol(&self, roles: &[RolUsuarioEnum]) -> Result<(), (StatusCode, Json<Value>)> {
+        if self.es_superadmin() || roles.contains(&self.rol) {
+            Ok(())
+        } else {
+            Err(json_error(StatusCode::FORBIDDEN, "Rol sin permiso para esta operación"))
+        }
+    }
+
+    pub fn exigir_grupo(&self, id_grupo: i32) -> Result<(), (StatusCode, Json<Value>)> {
+        if self.es_superadmin() || self.grupos.contains(&id_grupo) {
+            Ok(())
+        } else {
+            Err(json_error(StatusCode::FORBIDDEN, "Grupo fuera de su alcance"))
+        }
+    }
+
+    /// Grupos a los que se limitan las consultas; `None` si puede ver todos.
+    pub fn grupos_visibles(&self) -> Option<&[i32]> {
+        (!self.es_superadmin()).then_some(self.grupos.as_slice())
+    }
+}
@@ -1,22 +1,13 @@
 use crate::AppState;
-use crate::db::types::enums::CajasEstadoEnum;
-use crate::schema::{cajas, grupos};
-use crate::utils::cajas_utils::guardar_datos_caja;
-use chrono::prelude::*;
-use chrono_tz::America::Panama;
-use diesel::prelude::*;
+use crate::db::models::AlcanceCierre;
+use crate::db::types::enums::ActorEnum;
+use crate::utils::cajas_utils::cerrar_cajas;
+use crate::utils::correos::{MarcaCierre, notificar_cierres};
+use crate::utils::tiempo;
+use chrono::Duration;
+use std::env;
 use tokio_cron_scheduler::{JobBuilder, JobScheduler};
 
-#[derive(Queryable, Debug, serde::Serialize)]
-pub struct CajaWithCreds {
-    pub id: i32,
-    pub nombre_caja: String,
-    pub estado: CajasEstadoEnum,
-    pub api_key: String,
-    pub secret_key: String,
-    pub token_autorizacion: Option<String>,
-}
-
 pub async fn cerrar_cajas_job(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
     let scheduler = JobScheduler::new().await.unwrap();
 
@@ -30,45 +21,31 @@ pub async fn cerrar_cajas_job(state: &AppState) -> Result<(), Box<dyn std::error
         .with_run_async(Box::new(move |_uuid, mut _lock| {
             let state = state.clone(); // 👈 move it into the closure
             Box::pin(async move {
-                let mut conn = state.db_pool.get().unwrap();
-
-                let cajas_with_keys: Vec<CajaWithCreds> = cajas::table
-                    .inner_join(grupos::table.on(grupos::id.eq(cajas::id_grupo)))
-                    .filter(cajas::estado.eq(CajasEstadoEnum::Abierto))
-                    .select((
-                        cajas::id,
-                        cajas::nombre_caja,
-                        cajas::estado,
-                        grupos::api_key,
-                        grupos::secret_key,
-                        cajas::token_autorizacion,
-                    ))
-                    .load(&mut conn)
-                    .unwrap();
-
-                let now_in_panama = Panama
-                    .from_utc_datetime(&Utc::now().naive_utc())
-                    .format("%m/%d/%Y %I:%M:%S %p")
-                    .to_string()
-                    .to_uppercase();
-
                 println!(
                     "horario: {}, revisando si las cajas estan abiertas",
-                    now_in_panama,
+                    tiempo::texto_legado(&tiempo::ahora()),
                 );
 
-                for caja in cajas_with_keys {
-                    println!("cerrando la caja: {}", caja.nombre_caja);
-
-                    let _ = guardar_datos_caja(
-                        state.clone(),
-                        caja.api_key,
-                        caja.secret_key,
-                        caja.token_autorizacion,
-                        caja.id,
-                        caja.nombre_caja,
-                    )
-                    .await;
+                let marca = MarcaCierre::tomar(state.repos.cierres.as_ref());
+
+                let resultados = match cerrar_cajas(
+                    &state,
+                    AlcanceCierre::Todas,
+                    ActorEnum::Scheduler,
+                    "cierre-programado",
+                )
+                .await
+                {
+                    Ok(resultados) => resultados,
+                    Err(err) => {
+                        println!("no se pudieron leer las cajas por cerrar: {}", err);
+                        return;
+                    }
+                };
+
+                match marca {
+                    Ok(marca) => notificar_cierres(&state, marca, &resultados).await,
+                    Err(err) => println!("no se enviaron los correos de cierre: {}", err),
                 }
             })
         }))
@@ -80,3 +57,112 @@ pub async fn cerrar_cajas_job(state: &AppState) -> Result<(), Box<dyn std::error
 
     Ok(())
 }
+
+/// Devuelve a su estado de recuperación las cajas que llevan demasiado tiempo en
+/// `Abriendo` o `Cerrando`: el kiosko se cayó o la solicitud venció a mitad de la operación.
+pub async fn recuperar_cajas_job(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
+    let scheduler = JobScheduler::new().await.unwrap();
+
+    let state = state.clone();
+
+    // segundos que puede durar una apertura o un cierre en Yappy
+    let espera = env::var("CAJA_TRANSICION_SEGUNDOS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(120);
+
+    let recuperar_job = JobBuilder::new()
+        .with_timezone(chrono_tz::America::Panama)
+        .with_cron_job_type()
+        .with_schedule("0 * * * * *")
+        .unwrap()
+        .with_run_async(Box::new(move |_uuid, mut _lock| {
+            let state = state.clone();
+            Box::pin(async move {
+                recuperar_cajas(&state, espera);
+            })
+        }))
+        .build()
+        .unwrap();
+
+    scheduler.add(recuperar_job).await.unwrap();
+    scheduler.start().await.unwrap();
+
+    Ok(())
+}
+
+fn recuperar_cajas(state: &AppState, espera: i64) {
+    let limite = tiempo::ahora() - Duration::seconds(espera);
+
+    let a_medias = state.repos.cajas.a_medias().unwrap_or_default();
+
+    for (id_caja, estado, desde) in a_medias {
+        if desde.is_some_and(|desde| desde > limite) {
+            continue;
+        }
+
+        let motivo = format!("Sin respuesta por más de {} s en {:?}", espera, estado);
+
+        match state.repos.cajas.recuperar(id_caja, ActorEnum::Scheduler, motivo) {
+            Ok(Some(nuevo)) => {
+                state.repos.kioskos.invalidar_caja(id_caja);
+                println!("caja {} recuperada de {:?} a {:?}", id_caja, estado, nuevo);
+            }
+            Ok(None) => {}
+            Err(err) => println!("no se pudo recuperar la caja {}: {}", id_caja, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::Caja;
+    use crate::db::repos::memoria::prueba;
+    use crate::db::types::enums::{CajasEstadoEnum, CajasTipoEnum};
+
+    fn segunda_caja(memoria: &mut crate::db::repos::memoria::Memoria) {
+        let mut caja: Caja = memoria.cajas[0].clone();
+        caja.id = 2;
+        caja.nombre_caja = "Caja 2".to_string();
+        memoria.cajas.push(caja);
+    }
+
+    #[test]
+    fn recupera_solo_las_cajas_vencidas() {
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Cerrado, CajasTipoEnum::Kiosko);
+        segunda_caja(&mut memoria);
+
+        let ahora = tiempo::ahora();
+        prueba::transicion(&mut memoria, 1, CajasEstadoEnum::Abriendo, ahora - Duration::seconds(600));
+        prueba::transicion(&mut memoria, 2, CajasEstadoEnum::Abriendo, ahora);
+
+        let (state, repos) = prueba::estado(memoria);
+        recuperar_cajas(&state, 120);
+
+        let datos = repos.datos();
+        assert_eq!(datos.cajas[0].estado, CajasEstadoEnum::Cerrado);
+        assert_eq!(datos.cajas[1].estado, CajasEstadoEnum::Abriendo);
+
+        let ultima = datos.transiciones.last().unwrap();
+        assert_eq!(ultima.id_caja, 1);
+        assert_eq!(ultima.estado_anterior, CajasEstadoEnum::Abriendo);
+        assert_eq!(ultima.actor, ActorEnum::Scheduler);
+    }
+
+    #[test]
+    fn un_cierre_a_medias_queda_en_error() {
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        prueba::transicion(
+            &mut memoria,
+            1,
+            CajasEstadoEnum::Cerrando,
+            tiempo::ahora() - Duration::seconds(600),
+        );
+
+        let (state, repos) = prueba::estado(memoria);
+        recuperar_cajas(&state, 120);
+
+        assert_eq!(repos.datos().cajas[0].estado, CajasEstadoEnum::ErrorCierre);
+    }
+}
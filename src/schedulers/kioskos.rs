@@ -0,0 +1,46 @@
+use crate::AppState;
+use crate::utils::tiempo;
+use chrono::Duration;
+use std::env;
+use tokio_cron_scheduler::{JobBuilder, JobScheduler};
+
+pub async fn kioskos_desconectados_job(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
+    let scheduler = JobScheduler::new().await.unwrap();
+
+    let state = state.clone();
+
+    // segundos sin heartbeat para dar un kiosko por desconectado
+    let silencio = env::var("KIOSKO_SILENCIO_SEGUNDOS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(120);
+
+    let kioskos_job = JobBuilder::new()
+        .with_timezone(chrono_tz::America::Panama)
+        .with_cron_job_type()
+        .with_schedule("*/30 * * * * *")
+        .unwrap()
+        .with_run_async(Box::new(move |_uuid, mut _lock| {
+            let state = state.clone();
+            Box::pin(async move {
+                let limite = tiempo::ahora() - Duration::seconds(silencio);
+
+                let desconectados = state
+                    .repos
+                    .kioskos
+                    .marcar_desconectados(limite)
+                    .unwrap_or(0);
+
+                if desconectados > 0 {
+                    println!("{} kiosko(s) marcados como desconectados", desconectados);
+                }
+            })
+        }))
+        .build()
+        .unwrap();
+
+    scheduler.add(kioskos_job).await.unwrap();
+    scheduler.start().await.unwrap();
+
+    Ok(())
+}
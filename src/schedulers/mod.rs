@@ -1 +1,4 @@
-pub mod cajas;
\ No newline at end of file
+pub mod cajas;
+pub mod kioskos;
+pub mod transacciones;
+pub mod webhooks;
@@ -0,0 +1,324 @@
+use crate::AppState;
+use crate::controllers::structs::yappy::{CuerpoTransaccionYappy, RespuestaYappy};
+use crate::db::models::{NewAuditoria, TransaccionPendiente};
+use crate::db::types::enums::ActorEnum;
+use crate::utils::auditoria::{registrar_auditoria, resultado_de};
+use crate::utils::cajas_utils::consultar_transaccion_en_yappy;
+use crate::utils::monitor_transacciones::es_estado_final;
+use crate::utils::tiempo;
+use chrono::Duration;
+use serde_json::json;
+use std::collections::HashSet;
+use std::env;
+use tokio_cron_scheduler::{JobBuilder, JobScheduler};
+
+// corridas (una por minuto) que se espera un estado final de Yappy antes de expirar
+// la transaccion en MACY
+const REINTENTOS_SIN_ESTADO: i64 = 15;
+
+pub async fn expirar_transacciones_job(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
+    let scheduler = JobScheduler::new().await.unwrap();
+
+    let state = state.clone();
+
+    // minutos que puede quedar un QR pendiente antes de darlo por vencido
+    let ttl = env::var("TRANSACCION_TTL_MINUTOS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(15);
+
+    let expirar_job = JobBuilder::new()
+        .with_timezone(chrono_tz::America::Panama)
+        .with_cron_job_type()
+        .with_schedule("0 * * * * *")
+        .unwrap()
+        .with_run_async(Box::new(move |_uuid, mut _lock| {
+            let state = state.clone();
+            Box::pin(async move {
+                expirar_vencidas(&state, ttl).await;
+            })
+        }))
+        .build()
+        .unwrap();
+
+    scheduler.add(expirar_job).await.unwrap();
+    scheduler.start().await.unwrap();
+
+    Ok(())
+}
+
+async fn expirar_vencidas(state: &AppState, ttl: i64) {
+    let limite = tiempo::ahora() - Duration::minutes(ttl);
+
+    let pendientes = state
+        .repos
+        .transacciones
+        .pendientes(limite)
+        .unwrap_or_default();
+
+    // las que siguen pendientes pasados los reintentos ya no esperan a Yappy
+    let abandonadas: HashSet<String> = state
+        .repos
+        .transacciones
+        .pendientes(limite - Duration::minutes(REINTENTOS_SIN_ESTADO))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|pendiente| pendiente.id_transaccion_yappy)
+        .collect();
+
+    for pendiente in pendientes {
+        let abandonada = abandonadas.contains(&pendiente.id_transaccion_yappy);
+        expirar_transaccion(state, pendiente, abandonada).await;
+    }
+
+    // un minuto de margen para no perder un pago hecho justo antes de que venza el QR
+    let desde = limite - Duration::minutes(1);
+
+    let canceladas = state
+        .repos
+        .transacciones
+        .canceladas(desde)
+        .unwrap_or_default();
+
+    for cancelada in canceladas {
+        reconciliar_cancelada(state, cancelada).await;
+    }
+}
+
+/// Un QR cancelado en MACY sigue vivo en Yappy; si el cliente lo pagó igual, la venta
+/// pasa a COMPLETED y queda en la auditoría como error para que se reembolse.
+async fn reconciliar_cancelada(state: &AppState, cancelada: TransaccionPendiente) {
+    // sin sesion en Yappy el QR ya no se puede pagar
+    if cancelada.token_autorizacion.is_none() {
+        return;
+    }
+
+    let respuesta = match consultar_transaccion_en_yappy(
+        cancelada.api_key,
+        cancelada.secret_key,
+        cancelada.token_autorizacion,
+        &cancelada.id_transaccion_yappy,
+    )
+    .await
+    {
+        Ok(json) => json,
+        Err((_status, err_json)) => {
+            // se reintenta en la siguiente corrida mientras el QR siga vigente
+            println!(
+                "no se pudo consultar la transaccion cancelada {}: {}",
+                cancelada.id_transaccion_yappy, err_json.0
+            );
+            return;
+        }
+    };
+
+    let yappy = RespuestaYappy::<CuerpoTransaccionYappy>::de(&respuesta);
+    let estado = yappy.body.as_ref().and_then(|cuerpo| cuerpo.status.as_deref());
+
+    if estado != Some("COMPLETED") {
+        return;
+    }
+
+    // la caja ya no apunta a esta transaccion: solo cambia el estado (y encola pago.completado)
+    if let Err(err) = state
+        .repos
+        .transacciones
+        .actualizar_estado(&cancelada.id_transaccion_yappy, "COMPLETED")
+    {
+        println!(
+            "no se pudo registrar el pago de la transaccion cancelada {}: {}",
+            cancelada.id_transaccion_yappy, err
+        );
+        return;
+    }
+
+    println!(
+        "la transaccion {} se pagó después de cancelada; debe reembolsarse",
+        cancelada.id_transaccion_yappy
+    );
+
+    registrar_auditoria(
+        state,
+        NewAuditoria {
+            actor: ActorEnum::Scheduler,
+            id_kiosko: None,
+            id_caja: Some(cancelada.id_caja),
+            id_cajero: None,
+            request_id: None,
+            accion: "pago-tras-cancelacion".to_string(),
+            resumen: json!({
+                "transaccion": cancelada.id_transaccion_yappy,
+                "estado": "COMPLETED",
+                "requiere_reembolso": true,
+            }),
+            codigo_http: None,
+            // error a proposito: es una alerta para soporte
+            resultado: resultado_de(false, yappy.codigo()),
+            codigo_yappy: yappy.codigo().map(str::to_string),
+        },
+    );
+}
+
+/// Cierra la transacción con el estado final que informe Yappy. Si no hay sesión, o si
+/// Yappy sigue sin estado final pasados los reintentos, la expira en MACY y libera la caja.
+async fn expirar_transaccion(state: &AppState, pendiente: TransaccionPendiente, abandonada: bool) {
+    // sin sesion (el cierre nocturno borra el token) el QR ya no se puede pagar
+    if pendiente.token_autorizacion.is_none() {
+        expirar_en_macy(state, pendiente, "QR vencido sin sesión en Yappy");
+        return;
+    }
+
+    let respuesta = match consultar_transaccion_en_yappy(
+        pendiente.api_key.clone(),
+        pendiente.secret_key.clone(),
+        pendiente.token_autorizacion.clone(),
+        &pendiente.id_transaccion_yappy,
+    )
+    .await
+    {
+        Ok(json) => json,
+        Err((_status, err_json)) => {
+            println!(
+                "no se pudo consultar la transaccion {}: {}",
+                pendiente.id_transaccion_yappy, err_json.0
+            );
+            if abandonada {
+                expirar_en_macy(state, pendiente, "QR vencido; Yappy no respondió");
+            }
+            return;
+        }
+    };
+
+    let yappy = RespuestaYappy::<CuerpoTransaccionYappy>::de(&respuesta);
+    let codigo_yappy = yappy.status.as_ref().map(|status| status.code.clone());
+
+    // un PENDING o un estado desconocido no prueba que el cliente no haya pagado
+    let estado = match yappy.body.and_then(|cuerpo| cuerpo.status) {
+        Some(estado) if es_estado_final(&estado) => estado,
+        otro => {
+            println!(
+                "la transaccion {} sigue sin estado final en Yappy ({:?})",
+                pendiente.id_transaccion_yappy, otro
+            );
+            if abandonada {
+                expirar_en_macy(state, pendiente, "QR vencido sin estado final en Yappy");
+            }
+            return;
+        }
+    };
+
+    cerrar_transaccion(state, pendiente, &estado, None, codigo_yappy);
+}
+
+fn expirar_en_macy(state: &AppState, pendiente: TransaccionPendiente, motivo: &str) {
+    cerrar_transaccion(state, pendiente, "EXPIRED", Some(motivo), None);
+}
+
+fn cerrar_transaccion(
+    state: &AppState,
+    pendiente: TransaccionPendiente,
+    estado: &str,
+    motivo: Option<&str>,
+    codigo_yappy: Option<String>,
+) {
+    // solo libera la caja si sigue apuntando a esta transaccion
+    if let Err(err) = state.repos.transacciones.finalizar(
+        pendiente.id_caja,
+        &pendiente.id_transaccion_yappy,
+        estado,
+        motivo,
+    ) {
+        println!(
+            "no se pudo expirar la transaccion {}: {}",
+            pendiente.id_transaccion_yappy, err
+        );
+        return;
+    }
+
+    let evento = json!({
+        "transaccion": pendiente.id_transaccion_yappy,
+        "estado": estado,
+    });
+
+    state
+        .monitor
+        .finalizar(&pendiente.id_transaccion_yappy, evento.clone());
+
+    let mut resumen = evento;
+    if let Some(motivo) = motivo {
+        resumen["motivo"] = json!(motivo);
+    }
+
+    registrar_auditoria(
+        state,
+        NewAuditoria {
+            actor: ActorEnum::Scheduler,
+            id_kiosko: None,
+            id_caja: Some(pendiente.id_caja),
+            id_cajero: None,
+            request_id: None,
+            accion: "expirar-transaccion".to_string(),
+            resumen,
+            codigo_http: None,
+            resultado: resultado_de(true, codigo_yappy.as_deref()),
+            codigo_yappy,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repos::TransaccionRepo;
+    use crate::db::repos::memoria::prueba;
+    use crate::db::types::enums::{CajasEstadoEnum, CajasTipoEnum};
+
+    #[test]
+    fn solo_se_reconcilian_las_canceladas_con_el_qr_vigente() {
+        let ahora = tiempo::ahora();
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        prueba::transaccion(&mut memoria, "vigente", "CANCELLED", ahora - Duration::minutes(5));
+        prueba::transaccion(&mut memoria, "vencida", "CANCELLED", ahora - Duration::minutes(30));
+        prueba::transaccion(&mut memoria, "pendiente", "PENDING", ahora - Duration::minutes(5));
+        let (_, repos) = prueba::estado(memoria);
+
+        let canceladas = repos.canceladas(ahora - Duration::minutes(16)).unwrap();
+        let ids: Vec<_> = canceladas.iter().map(|t| t.id_transaccion_yappy.as_str()).collect();
+        assert_eq!(ids, ["vigente"]);
+    }
+
+    #[tokio::test]
+    async fn una_cancelada_sin_sesion_en_yappy_no_cambia() {
+        let ahora = tiempo::ahora();
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        prueba::transaccion(&mut memoria, "cancelada", "CANCELLED", ahora - Duration::minutes(5));
+        let (state, repos) = prueba::estado(memoria);
+
+        expirar_vencidas(&state, 15).await;
+
+        assert_eq!(TransaccionRepo::buscar(&repos, "cancelada").unwrap().estado, "CANCELLED");
+        assert!(repos.datos().eventos.is_empty());
+    }
+
+    #[tokio::test]
+    async fn una_pendiente_vencida_sin_sesion_expira_y_libera_la_caja() {
+        let ahora = tiempo::ahora();
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Cerrado, CajasTipoEnum::Kiosko);
+        prueba::transaccion(&mut memoria, "vencida", "PENDING", ahora - Duration::minutes(30));
+        prueba::transaccion(&mut memoria, "reciente", "PENDING", ahora - Duration::minutes(5));
+        memoria.cajas[0].transaccion_actual = Some("vencida".to_string());
+        let (state, repos) = prueba::estado(memoria);
+
+        expirar_vencidas(&state, 15).await;
+
+        let vencida = TransaccionRepo::buscar(&repos, "vencida").unwrap();
+        assert_eq!(vencida.estado, "EXPIRED");
+        assert!(vencida.motivo_cancelacion.is_some());
+        assert_eq!(repos.datos().cajas[0].transaccion_actual, None);
+        assert_eq!(TransaccionRepo::buscar(&repos, "reciente").unwrap().estado, "PENDING");
+
+        let auditoria = &repos.datos().auditoria;
+        assert_eq!(auditoria.len(), 1);
+        assert_eq!(auditoria[0].accion, "expirar-transaccion");
+    }
+}
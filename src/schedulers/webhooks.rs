@@ -0,0 +1,38 @@
+use crate::AppState;
+use crate::utils::webhooks::entregar_pendientes;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_cron_scheduler::{JobBuilder, JobScheduler};
+
+pub async fn entregar_webhooks_job(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
+    let scheduler = JobScheduler::new().await.unwrap();
+
+    let state = state.clone();
+
+    // una entrega lenta no debe solaparse con la siguiente corrida
+    let en_curso = Arc::new(Mutex::new(()));
+
+    let webhooks_job = JobBuilder::new()
+        .with_timezone(chrono_tz::America::Panama)
+        .with_cron_job_type()
+        .with_schedule("*/10 * * * * *")
+        .unwrap()
+        .with_run_async(Box::new(move |_uuid, mut _lock| {
+            let state = state.clone();
+            let en_curso = en_curso.clone();
+            Box::pin(async move {
+                let Ok(_guardia) = en_curso.try_lock() else {
+                    return;
+                };
+
+                entregar_pendientes(&state).await;
+            })
+        }))
+        .build()
+        .unwrap();
+
+    scheduler.add(webhooks_job).await.unwrap();
+    scheduler.start().await.unwrap();
+
+    Ok(())
+}
@@ -1,3 +1,28 @@
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::db::types::enums::ActorEnumMapping;
+
+    auditoria (id) {
+        id -> Integer,
+        #[max_length = 9]
+        actor -> ActorEnumMapping,
+        id_kiosko -> Nullable<Integer>,
+        id_caja -> Nullable<Integer>,
+        #[max_length = 50]
+        accion -> Varchar,
+        resumen -> Json,
+        codigo_http -> Nullable<Integer>,
+        #[max_length = 20]
+        codigo_yappy -> Nullable<Varchar>,
+        #[max_length = 20]
+        resultado -> Varchar,
+        fecha -> Nullable<Timestamp>,
+        id_cajero -> Nullable<Integer>,
+        #[max_length = 64]
+        request_id -> Nullable<Varchar>,
+    }
+}
+
 diesel::table! {
 
     caja_cierre_errores (id) {
@@ -5,6 +30,26 @@ diesel::table! {
         id_caja -> Integer,
         respuesta_json -> Json,
         fecha -> Nullable<Timestamp>,
+        id_cajero -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::db::types::enums::{ActorEnumMapping, CajasEstadoEnumMapping};
+
+    caja_transiciones (id) {
+        id -> Integer,
+        id_caja -> Integer,
+        #[max_length = 13]
+        estado_anterior -> CajasEstadoEnumMapping,
+        #[max_length = 13]
+        estado_nuevo -> CajasEstadoEnumMapping,
+        #[max_length = 9]
+        actor -> ActorEnumMapping,
+        #[max_length = 255]
+        motivo -> Varchar,
+        fecha -> Nullable<Timestamp>,
     }
 }
 
@@ -17,25 +62,39 @@ diesel::table! {
         monto -> Decimal,
         transacciones -> Integer,
         fecha -> Nullable<Timestamp>,
+        id_cajero -> Nullable<Integer>,
     }
 }
 
 diesel::table! {
     use diesel::sql_types::*;
-    use crate::db::types::enums::CajasEstadoEnumMapping; 
+    use crate::db::types::enums::{CajasEstadoEnumMapping, CajasTipoEnumMapping};
 
     cajas (id) {
         id -> Integer,
         id_grupo -> Integer,
         #[max_length = 100]
         nombre_caja -> Varchar,
-        #[max_length = 50]
-        tipo -> Varchar,
+        #[max_length = 6]
+        tipo -> CajasTipoEnumMapping,
         token_autorizacion -> Nullable<Text>,
         #[max_length = 100]
         transaccion_actual -> Nullable<Varchar>,
-        #[max_length = 7]
+        #[max_length = 13]
         estado -> CajasEstadoEnumMapping,
+        id_cajero_actual -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    cajeros (id) {
+        id -> Integer,
+        id_grupo -> Integer,
+        #[max_length = 100]
+        nombre -> Varchar,
+        #[max_length = 255]
+        pin_hash -> Varchar,
+        activo -> Bool,
     }
 }
 
@@ -50,6 +109,57 @@ diesel::table! {
         api_key -> Varchar,
         #[max_length = 255]
         secret_key -> Varchar,
+        plantilla_recibo -> Nullable<Text>,
+        correos_cierre -> Nullable<Json>,
+        version_credenciales -> Integer,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::db::types::enums::ActorEnumMapping;
+
+    reembolsos (id) {
+        id -> Integer,
+        id_transaccion -> Integer,
+        monto -> Decimal,
+        #[max_length = 255]
+        motivo -> Varchar,
+        #[max_length = 9]
+        actor -> ActorEnumMapping,
+        id_cajero -> Nullable<Integer>,
+        exitoso -> Bool,
+        #[max_length = 20]
+        codigo_yappy -> Nullable<Varchar>,
+        respuesta_json -> Json,
+        fecha -> Nullable<Timestamp>,
+        #[max_length = 64]
+        request_id -> Nullable<Varchar>,
+        pendiente -> Bool,
+    }
+}
+
+diesel::table! {
+    sesiones_cajero (id) {
+        id -> Integer,
+        id_caja -> Integer,
+        id_cajero -> Integer,
+        inicio -> Nullable<Timestamp>,
+        fin -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::db::types::enums::ModoRedondeoEnumMapping;
+
+    impuestos_grupo (id_grupo) {
+        id_grupo -> Integer,
+        tasa_general -> Decimal,
+        tasas_categoria -> Json,
+        categorias_exentas -> Json,
+        #[max_length = 9]
+        modo_redondeo -> ModoRedondeoEnumMapping,
     }
 }
 
@@ -61,18 +171,158 @@ diesel::table! {
         nombre -> Varchar,
         #[max_length = 50]
         mac_address -> Varchar,
+        ultima_conexion -> Nullable<Timestamp>,
+        #[max_length = 50]
+        version_app -> Nullable<Varchar>,
+        #[max_length = 45]
+        ip -> Nullable<Varchar>,
+        en_linea -> Bool,
+    }
+}
+
+diesel::table! {
+    transaccion_items (id) {
+        id -> Integer,
+        id_transaccion -> Integer,
+        #[max_length = 100]
+        sku -> Varchar,
+        #[max_length = 255]
+        descripcion -> Varchar,
+        cantidad -> Integer,
+        precio_unitario -> Decimal,
+        descuento -> Decimal,
+        exento_itbms -> Bool,
+        impuesto -> Decimal,
+        total -> Decimal,
+        #[max_length = 50]
+        categoria -> Nullable<Varchar>,
+        tasa_impuesto -> Decimal,
+    }
+}
+
+diesel::table! {
+    transacciones (id) {
+        id -> Integer,
+        id_caja -> Integer,
+        id_cajero -> Nullable<Integer>,
+        #[max_length = 100]
+        id_transaccion_yappy -> Varchar,
+        #[max_length = 100]
+        id_orden -> Nullable<Varchar>,
+        #[max_length = 10]
+        tipo_qr -> Varchar,
+        subtotal -> Decimal,
+        impuesto -> Decimal,
+        propina -> Decimal,
+        descuento -> Decimal,
+        total -> Decimal,
+        #[max_length = 20]
+        estado -> Varchar,
+        fecha -> Nullable<Timestamp>,
+        fecha_actualizacion -> Nullable<Timestamp>,
+        #[max_length = 255]
+        motivo_cancelacion -> Nullable<Varchar>,
+        qr_hash -> Nullable<Text>,
+        tasa_impuesto -> Nullable<Decimal>,
+        #[max_length = 255]
+        discrepancia_impuesto -> Nullable<Varchar>,
+        #[max_length = 64]
+        request_id -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use crate::db::types::enums::RolUsuarioEnumMapping;
+
+    usuarios (id) {
+        id -> Integer,
+        #[max_length = 100]
+        nombre -> Varchar,
+        #[max_length = 255]
+        email -> Varchar,
+        #[max_length = 255]
+        password_hash -> Varchar,
+        #[max_length = 11]
+        rol -> RolUsuarioEnumMapping,
+        activo -> Bool,
+        creado -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    usuarios_grupos (id_usuario, id_grupo) {
+        id_usuario -> Integer,
+        id_grupo -> Integer,
+    }
+}
+
+diesel::table! {
+    webhooks (id) {
+        id -> Integer,
+        id_grupo -> Integer,
+        #[max_length = 500]
+        url -> Varchar,
+        #[max_length = 255]
+        secreto -> Varchar,
+        eventos -> Json,
+        activo -> Bool,
+        creado -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    webhooks_outbox (id) {
+        id -> Integer,
+        id_webhook -> Integer,
+        #[max_length = 50]
+        evento -> Varchar,
+        payload -> Json,
+        #[max_length = 20]
+        estado -> Varchar,
+        intentos -> Integer,
+        proximo_intento -> Timestamp,
+        #[max_length = 500]
+        ultimo_error -> Nullable<Varchar>,
+        creado -> Nullable<Timestamp>,
+        entregado -> Nullable<Timestamp>,
     }
 }
 
 diesel::joinable!(caja_cierre_errores -> cajas (id_caja));
 diesel::joinable!(caja_cierre_resumen -> cajas (id_caja));
+diesel::joinable!(caja_transiciones -> cajas (id_caja));
+diesel::joinable!(cajas -> cajeros (id_cajero_actual));
 diesel::joinable!(cajas -> grupos (id_grupo));
+diesel::joinable!(cajeros -> grupos (id_grupo));
+diesel::joinable!(impuestos_grupo -> grupos (id_grupo));
 diesel::joinable!(kioskos -> cajas (id_caja));
+diesel::joinable!(reembolsos -> transacciones (id_transaccion));
+diesel::joinable!(sesiones_cajero -> cajas (id_caja));
+diesel::joinable!(sesiones_cajero -> cajeros (id_cajero));
+diesel::joinable!(transaccion_items -> transacciones (id_transaccion));
+diesel::joinable!(transacciones -> cajas (id_caja));
+diesel::joinable!(usuarios_grupos -> grupos (id_grupo));
+diesel::joinable!(usuarios_grupos -> usuarios (id_usuario));
+diesel::joinable!(webhooks -> grupos (id_grupo));
+diesel::joinable!(webhooks_outbox -> webhooks (id_webhook));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    auditoria,
     caja_cierre_errores,
     caja_cierre_resumen,
+    caja_transiciones,
     cajas,
+    cajeros,
     grupos,
+    impuestos_grupo,
     kioskos,
+    reembolsos,
+    sesiones_cajero,
+    transaccion_items,
+    transacciones,
+    usuarios,
+    usuarios_grupos,
+    webhooks,
+    webhooks_outbox,
 );
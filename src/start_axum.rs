@@ -1,33 +1,159 @@
 use axum::{
     Router,
+    extract::Request,
+    middleware,
     routing::{delete, get, post},
 };
 
+use std::{env, net::SocketAddr, time::Duration};
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
 use tower_http::cors::CorsLayer;
 use tower_http::catch_panic::CatchPanicLayer;
 
 use crate::{controllers::yappy::{
-    abrir_caja, cerrar_caja, generar_qr, handle_transaccion, hello_world,
+    abrir_caja, cancelar_transaccion, cerrar_caja, generar_qr, handle_transaccion, hello_world,
+    qr_transaccion, retornar_transaccion, stream_transaccion,
 }, AppState};
 use crate::controllers::grupos::{
    get_grupos
 };
+use crate::controllers::auditoria::get_auditoria;
+use crate::controllers::kioskos::heartbeat;
+use crate::controllers::cajeros::{login_cajero, logout_cajero};
+use crate::controllers::reembolsos::crear_reembolso;
+use crate::controllers::recibos::reimprimir_recibo;
+use crate::controllers::usuarios::{crear_usuario, listar_usuarios, login_usuario, perfil_usuario};
+use crate::controllers::v1;
+use crate::controllers::cierres::{cerrar_caja_admin, correr_cierres};
+use crate::middlewares::auditoria::auditar_kiosko;
+use crate::middlewares::correlacion::{IdSolicitud, correlacionar};
+use crate::middlewares::limites::{Limitador, Limites, limitar};
+use crate::middlewares::obsoletas::marcar_obsoleta;
+#[cfg(not(feature = "swagger-ui"))]
+use crate::controllers::openapi::openapi_json;
+#[cfg(feature = "swagger-ui")]
+use crate::controllers::openapi::ApiDoc;
+#[cfg(feature = "swagger-ui")]
+use utoipa::OpenApi;
+#[cfg(feature = "swagger-ui")]
+use utoipa_swagger_ui::SwaggerUi;
 
 // Import your controller handlers
 
 pub async fn start_axum(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
-    let app = Router::new()
-        .route("/", get(hello_world))
-        .route("/grupos", get(get_grupos))
+    // cada llamada a estas rutas termina en Yappy con las credenciales del grupo
+    let limites = Limites::new(Limitador::desde_env("GENERAL", 30, 120))
+        .ruta(&["/generar-qr", "/v1/cobros"], Limitador::desde_env("GENERAR_QR", 5, 20))
+        .ruta(
+            &["/abrir-sesion", "/v1/sesion", "/cajero/login", "/v1/cajero/login"],
+            Limitador::desde_env("ABRIR_SESION", 3, 6),
+        )
+        .ruta(&["/admin/login", "/v1/admin/login"], Limitador::desde_env("LOGIN", 5, 10));
+    limites.iniciar_limpieza();
+
+    let max_cuerpo = env::var("MAX_CUERPO_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(64 * 1024);
+    let timeout = env::var("TIMEOUT_SEGUNDOS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+
+    // rutas que llaman los kioskos, todas quedan registradas en la auditoria
+    let kiosko = Router::new()
         .route("/abrir-sesion", get(abrir_caja))
         .route("/generar-qr", post(generar_qr))
+        .route("/qr-transaccion", get(qr_transaccion))
         .route("/cerrar-sesion", delete(cerrar_caja))
         .route("/estado-transaccion", get(handle_transaccion))
-        .route("/retornar-transaccion", get(handle_transaccion))
+        .route("/retornar-transaccion", get(retornar_transaccion))
+        .route("/cancelar-transaccion", post(cancelar_transaccion))
+        .route("/reembolsos", post(crear_reembolso))
+        .route("/recibos/{id}", get(reimprimir_recibo))
+        .route("/cajero/login", post(login_cajero))
+        .route("/cajero/logout", post(logout_cajero))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auditar_kiosko));
+
+    // rutas administrativas, autenticadas con el JWT del usuario
+    let admin = Router::new()
+        .route("/login", post(login_usuario))
+        .route("/yo", get(perfil_usuario))
+        .route("/usuarios", get(listar_usuarios).post(crear_usuario));
+
+    let legado = Router::new()
+        .route("/grupos", get(get_grupos))
+        .route("/auditoria", get(get_auditoria))
+        .route("/heartbeat", post(heartbeat))
+        // el stream no pasa por la auditoria porque esta bufferea la respuesta completa
+        .route("/estado-transaccion/stream", get(stream_transaccion))
+        .nest("/admin", admin)
+        .merge(kiosko)
+        .route_layer(middleware::from_fn_with_state(limites.clone(), limitar))
+        .layer(middleware::map_response(marcar_obsoleta));
+
+    let v1_kiosko = Router::new()
+        .route("/sesion", post(v1::abrir_sesion).delete(v1::cerrar_sesion))
+        .route("/cobros", post(v1::crear_cobro))
+        .route("/cobros/actual", get(v1::estado_cobro))
+        .route("/cobros/actual/qr", get(v1::qr_cobro))
+        .route("/cobros/actual/cancelacion", post(v1::cancelar_cobro))
+        .route("/cobros/actual/reembolso", post(v1::reembolsar_cobro))
+        .route("/reembolsos", post(v1::reembolsar))
+        .route("/recibos/{id}", get(v1::recibo))
+        .route("/cajero/login", post(v1::entrar_cajero))
+        .route("/cajero/logout", post(v1::salir_cajero))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auditar_kiosko));
+
+    let v1_admin = Router::new()
+        .route("/login", post(v1::admin_login))
+        .route("/yo", get(v1::admin_perfil))
+        .route("/usuarios", get(v1::admin_usuarios).post(v1::admin_crear_usuario))
+        .route("/cierres", post(correr_cierres))
+        .route("/cajas/{id}/cierre", post(cerrar_caja_admin));
+
+    let v1 = Router::new()
+        .route("/grupos", get(v1::grupos))
+        .route("/auditoria", get(v1::auditoria))
+        .route("/heartbeat", post(v1::latido))
+        .route("/cobros/actual/stream", get(v1::stream_cobro))
+        .nest("/admin", v1_admin)
+        .merge(v1_kiosko)
+        .route_layer(middleware::from_fn_with_state(limites, limitar));
+
+    let app = Router::new()
+        .route("/", get(hello_world))
+        .nest("/v1", v1)
+        .merge(legado);
+
+    // con el feature swagger-ui la misma especificacion se sirve junto a la UI en /docs
+    #[cfg(feature = "swagger-ui")]
+    let app = app.merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()));
+    #[cfg(not(feature = "swagger-ui"))]
+    let app = app.route("/openapi.json", get(openapi_json));
+
+    let app = app
+        .layer(RequestBodyLimitLayer::new(max_cuerpo))
+        .layer(TimeoutLayer::new(Duration::from_secs(timeout)))
         .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http())
+        .layer(TraceLayer::new_for_http().make_span_with(|request: &Request| {
+            let id = request
+                .extensions()
+                .get::<IdSolicitud>()
+                .map_or("", |id| id.0.as_str());
+            // info y no debug: los eventos de los handlers llevan el id aunque se suba el nivel
+            tracing::info_span!(
+                "request",
+                method = %request.method(),
+                uri = %request.uri(),
+                request_id = %id,
+            )
+        }))
         .layer(CatchPanicLayer::new())
+        // por fuera de todo, así el span de la traza y las respuestas de error ya tienen el id
+        .layer(middleware::from_fn(correlacionar))
         .with_state(state.clone());
 
     // solo para desarrollo
@@ -36,7 +162,12 @@ pub async fn start_axum(state: &AppState) -> Result<(), Box<dyn std::error::Erro
     .init();
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3333").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 
     Ok(())
 }
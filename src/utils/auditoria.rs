@@ -0,0 +1,117 @@
+use crate::AppState;
+use crate::db::models::NewAuditoria;
+use crate::db::types::enums::{ActorEnum, RolUsuarioEnum};
+use crate::middlewares::correlacion;
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+// se compara el nombre completo del campo: "pin" no debe tapar "propina"
+const CAMPOS_SENSIBLES: [&str; 12] = [
+    "token",
+    "token_autorizacion",
+    "access_token",
+    "key",
+    "api_key",
+    "secret",
+    "secret_key",
+    "authorization",
+    "password",
+    "contrasena",
+    "pin",
+    "jwt",
+];
+
+/// Inserta un registro en `auditoria`. Un fallo al auditar nunca debe tumbar la operación auditada.
+pub fn registrar_auditoria(state: &AppState, registro: NewAuditoria) {
+    let accion = registro.accion.clone();
+
+    if let Err(err) = state.repos.auditoria.registrar(registro) {
+        tracing::error!("no se pudo registrar la auditoria de {}: {}", accion, err);
+    }
+}
+
+/// Registra una acción de un usuario del panel; su id y rol quedan en el resumen.
+/// `usuario` es `None` cuando todavía no se sabe quién es, p. ej. un login fallido.
+pub fn auditar_usuario(
+    state: &AppState,
+    usuario: Option<(i32, RolUsuarioEnum)>,
+    accion: &str,
+    id_caja: Option<i32>,
+    mut resumen: Value,
+    status: StatusCode,
+) {
+    if let Some((id_usuario, rol)) = usuario {
+        resumen["id_usuario"] = json!(id_usuario);
+        resumen["rol"] = json!(rol);
+    }
+
+    registrar_auditoria(
+        state,
+        NewAuditoria {
+            actor: ActorEnum::Admin,
+            id_kiosko: None,
+            id_caja,
+            id_cajero: None,
+            request_id: correlacion::actual(),
+            accion: accion.to_string(),
+            resumen,
+            codigo_http: Some(status.as_u16() as i32),
+            codigo_yappy: None,
+            resultado: resultado_de(status.is_success(), None),
+        },
+    );
+}
+
+/// Reemplaza por `***` el valor de cualquier campo que sea una credencial.
+pub fn redactar(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (campo, valor) in map.iter_mut() {
+                let campo = campo.to_lowercase().replace('-', "_");
+                if CAMPOS_SENSIBLES.contains(&campo.as_str()) {
+                    *valor = Value::String("***".to_string());
+                } else {
+                    redactar(valor);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redactar),
+        _ => {}
+    }
+}
+
+/// Resultado de una operación a partir del código HTTP y del código de Yappy, si lo hay.
+pub fn resultado_de(exito_http: bool, codigo_yappy: Option<&str>) -> String {
+    match (exito_http, codigo_yappy) {
+        (true, None) | (true, Some("YP-0000")) => "exito",
+        _ => "error",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solo_se_tapan_los_campos_sensibles_completos() {
+        let mut cuerpo = json!({
+            "PIN": "1234",
+            "propina": 1.5,
+            "api-key": "abc",
+            "items": [{ "sku": "A1", "token": "xyz" }],
+        });
+
+        redactar(&mut cuerpo);
+
+        assert_eq!(
+            cuerpo,
+            json!({
+                "PIN": "***",
+                "propina": 1.5,
+                "api-key": "***",
+                "items": [{ "sku": "A1", "token": "***" }],
+            })
+        );
+    }
+}
@@ -1,10 +1,14 @@
 use crate::AppState;
 use crate::controllers::structs::yappy::AbrirCaja;
 use crate::db::{
-    models::{NewCajaCierreError, NewCajaCierreResumen},
-    types::enums::CajasEstadoEnum,
+    models::{
+        AlcanceCierre, EventoWebhook, KioskoInfo, NewAuditoria, NewCajaCierreError,
+        NewCajaCierreResumen, TransicionError,
+    },
+    types::enums::{ActorEnum, CajasEstadoEnum, CajasTipoEnum},
 };
-use crate::schema::{caja_cierre_errores, caja_cierre_resumen, cajas};
+use crate::middlewares::correlacion;
+use crate::utils::auditoria::{registrar_auditoria, resultado_de};
 use crate::utils::utils::{get_info_by_mac_address, insert_auth_headers, json_error};
 use axum::http::HeaderMap;
 use axum::{Json, http::StatusCode};
@@ -14,6 +18,115 @@ use std::env;
 //use serde::Serialize;
 use serde_json::Value;
 
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct ResultadoCierre {
+    pub id_caja: i32,
+    pub nombre_caja: String,
+    pub exito: bool,
+    pub codigo_yappy: Option<String>,
+    pub respuesta: Value,
+}
+
+/// Cierra en Yappy las cajas abiertas o con error de cierre dentro del alcance,
+/// dejando cada intento en la auditoría con la `accion` indicada.
+pub async fn cerrar_cajas(
+    state: &AppState,
+    alcance: AlcanceCierre,
+    actor: ActorEnum,
+    accion: &str,
+) -> QueryResult<Vec<ResultadoCierre>> {
+    let cajas_with_keys = state.repos.cierres.cajas_por_cerrar(&alcance)?;
+
+    let mut resultados = Vec::new();
+
+    for caja in cajas_with_keys {
+        tracing::info!("cerrando la caja: {}", caja.nombre_caja);
+
+        let resultado = guardar_datos_caja(
+            state.clone(),
+            caja.api_key,
+            caja.secret_key,
+            caja.token_autorizacion,
+            caja.id,
+            caja.nombre_caja.clone(),
+            actor.clone(),
+        )
+        .await;
+
+        let (respuesta, exito) = match resultado {
+            Ok(json) => (json, true),
+            Err((_status, err_json)) => (err_json.0, false),
+        };
+        let codigo_yappy = respuesta
+            .pointer("/status/code")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        let resultado = resultado_de(exito, codigo_yappy.as_deref());
+
+        registrar_auditoria(
+            state,
+            NewAuditoria {
+                actor: actor.clone(),
+                id_kiosko: None,
+                id_caja: Some(caja.id),
+                id_cajero: None,
+                request_id: correlacion::actual(),
+                accion: accion.to_string(),
+                resumen: serde_json::json!({ "error": respuesta.get("error") }),
+                codigo_http: None,
+                resultado: resultado.clone(),
+                codigo_yappy: codigo_yappy.clone(),
+            },
+        );
+
+        resultados.push(ResultadoCierre {
+            id_caja: caja.id,
+            nombre_caja: caja.nombre_caja,
+            exito: resultado == "exito",
+            codigo_yappy,
+            respuesta,
+        });
+    }
+
+    Ok(resultados)
+}
+
+/// Cierre de una sola caja aunque haya quedado a medias: `Cerrando` pasa a `ErrorCierre` y se
+/// cierra en Yappy como cualquier otra; `Abriendo` vuelve a `Cerrado` sin llamar a Yappy, porque
+/// no hay token de esa apertura con qué cerrar. `None` si la caja está cerrada o deshabilitada.
+pub async fn forzar_cierre(
+    state: &AppState,
+    id_caja: i32,
+    actor: ActorEnum,
+    accion: &str,
+    motivo: String,
+) -> Result<Option<ResultadoCierre>, TransicionError> {
+    let recuperada = state.repos.cajas.recuperar(id_caja, actor.clone(), motivo)?;
+
+    if recuperada.is_some() {
+        state.repos.kioskos.invalidar_caja(id_caja);
+    }
+
+    if recuperada == Some(CajasEstadoEnum::Cerrado) {
+        let caja = state.repos.cajas.buscar(id_caja)?;
+
+        return Ok(Some(ResultadoCierre {
+            id_caja,
+            nombre_caja: caja.nombre_caja,
+            exito: true,
+            codigo_yappy: None,
+            respuesta: serde_json::json!({
+                "estado_anterior": CajasEstadoEnum::Abriendo,
+                "estado": CajasEstadoEnum::Cerrado,
+            }),
+        }));
+    }
+
+    Ok(cerrar_cajas(state, AlcanceCierre::Caja(id_caja), actor, accion)
+        .await?
+        .pop())
+}
+
 pub async fn guardar_datos_caja(
     state: AppState,
     api_key: String,
@@ -21,13 +134,30 @@ pub async fn guardar_datos_caja(
     auth_token: Option<String>,
     caja_id: i32,
     nombre_caja: String,
+    actor: ActorEnum,
 ) -> Result<Value, (StatusCode, Json<Value>)> {
-    let mut conn = state.db_pool.get().unwrap();
+    // el cierre se atribuye al cajero en turno, si la caja es atendida
+    let id_cajero: Option<i32> = state
+        .repos
+        .cajas
+        .buscar(caja_id)
+        .map(|caja| caja.id_cajero_actual)
+        .unwrap_or(None);
+
+    let _invalidar = InvalidarContexto(&state, caja_id);
+
+    state.repos.cajas.transicionar(
+        caja_id,
+        CajasEstadoEnum::Cerrando,
+        actor.clone(),
+        "Cierre de sesión en Yappy".to_string(),
+        None,
+    )?;
 
     let respuesta =
         cerrar_caja_en_yappy(api_key.clone(), secret_key.clone(), auth_token.clone()).await;
 
-    println!("respuesta de caja {}: {:#?}", nombre_caja, respuesta);
+    tracing::debug!("respuesta de caja {}: {:#?}", nombre_caja, respuesta);
 
     match &respuesta {
         Ok(json) => {
@@ -36,6 +166,8 @@ pub async fn guardar_datos_caja(
 
             if let Some("YP-0000") = code {
                 // It's a successful response, extract summaries
+                let mut lineas = Vec::new();
+
                 if let Some(summary) = json.pointer("/body/summary").and_then(|v| v.as_array()) {
                     for entry in summary {
                         let tipo = entry
@@ -55,31 +187,54 @@ pub async fn guardar_datos_caja(
                             tipo,
                             monto: BigDecimal::from_f64(monto).unwrap(),
                             transacciones: transacciones as i32,
+                            id_cajero,
                         };
 
-                        let _ = diesel::insert_into(caja_cierre_resumen::table)
-                            .values(&resumen)
-                            .execute(&mut conn);
+                        lineas.push(serde_json::json!({
+                            "tipo": resumen.tipo,
+                            "monto": resumen.monto,
+                            "transacciones": resumen.transacciones,
+                        }));
+
+                        let _ = state.repos.cierres.guardar_resumen(resumen);
                     }
                 }
 
-                let _ = diesel::update(cajas::table)
-                    .filter(cajas::id.eq(caja_id))
-                    .set((
-                        cajas::token_autorizacion.eq(None::<String>),
-                        cajas::estado.eq(CajasEstadoEnum::Cerrado),
-                    ))
-                    .execute(&mut conn);
+                state.repos.cajas.terminar_cierre(
+                    caja_id,
+                    actor,
+                    "Sesión cerrada en Yappy".to_string(),
+                    serde_json::json!({
+                        "nombre_caja": nombre_caja,
+                        "exito": true,
+                        "codigo_yappy": code,
+                        "resumen": lineas,
+                    }),
+                )?;
             } else {
                 // Save full response to caja_cierre_errores
                 let error = NewCajaCierreError {
                     id_caja: caja_id,
                     respuesta_json: json.clone(),
+                    id_cajero,
                 };
 
-                let _ = diesel::insert_into(caja_cierre_errores::table)
-                    .values(&error)
-                    .execute(&mut conn);
+                let _ = state.repos.cierres.guardar_error(error);
+
+                state.repos.cajas.transicionar(
+                    caja_id,
+                    CajasEstadoEnum::ErrorCierre,
+                    actor,
+                    format!("Yappy rechazó el cierre: {}", code.unwrap_or("sin código")),
+                    Some((
+                        EventoWebhook::CierreFinalizado,
+                        serde_json::json!({
+                            "nombre_caja": nombre_caja,
+                            "exito": false,
+                            "codigo_yappy": code,
+                        }),
+                    )),
+                )?;
             }
         }
 
@@ -88,11 +243,25 @@ pub async fn guardar_datos_caja(
             let error = NewCajaCierreError {
                 id_caja: caja_id,
                 respuesta_json: err_json.0.clone(),
+                id_cajero,
             };
 
-            let _ = diesel::insert_into(caja_cierre_errores::table)
-                .values(&error)
-                .execute(&mut conn);
+            let _ = state.repos.cierres.guardar_error(error);
+
+            state.repos.cajas.transicionar(
+                caja_id,
+                CajasEstadoEnum::ErrorCierre,
+                actor,
+                "Error de comunicación con Yappy al cerrar".to_string(),
+                Some((
+                    EventoWebhook::CierreFinalizado,
+                    serde_json::json!({
+                        "nombre_caja": nombre_caja,
+                        "exito": false,
+                        "codigo_yappy": null,
+                    }),
+                )),
+            )?;
         }
     };
 
@@ -123,9 +292,37 @@ pub async fn cerrar_caja_en_yappy(
         .await
         .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?;
 
-    let response_json: serde_json::Value = serde_json::from_str(&response).unwrap();
+    // una respuesta ilegible cuenta como error de cierre, la caja no queda en Cerrando
+    serde_json::from_str(&response).map_err(|err| json_error(StatusCode::BAD_GATEWAY, err))
+}
+
+pub async fn consultar_transaccion_en_yappy(
+    api_key: String,
+    secret_key: String,
+    auth_token: Option<String>,
+    transaccion_id: &str,
+) -> Result<Value, (StatusCode, Json<Value>)> {
+
+    let client = reqwest::Client::new();
 
-    Ok(response_json)
+    let url = format!(
+        "{}/transaction/{}",
+        env::var("YAPPY_ENDPOINT")
+            .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err,))?,
+        transaccion_id
+    );
+
+    let response = client
+        .get(url)
+        .headers(insert_auth_headers(api_key, secret_key, auth_token))
+        .send()
+        .await
+        .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?
+        .text()
+        .await
+        .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?;
+
+    serde_json::from_str(&response).map_err(|err| json_error(StatusCode::BAD_GATEWAY, err))
 }
 
 pub async fn abrir_caja_and_return_value(
@@ -141,53 +338,134 @@ pub async fn abrir_caja_and_return_value(
     let info = get_info_by_mac_address(&state, mac_address)
         .map_err(|_| json_error(StatusCode::INTERNAL_SERVER_ERROR, "Sin acceso"))?;
 
+    if info.tipo == CajasTipoEnum::Cajero && info.nombre_cajero.is_none() {
+        return Err(json_error(StatusCode::FORBIDDEN, "No hay cajero en turno"));
+    }
+
+    let actor = info.actor();
+    abrir_sesion_en_yappy(&state, info, actor).await
+}
+
+/// Abre la sesión de la caja del kiosko en Yappy y guarda el token.
+pub async fn abrir_sesion_en_yappy(
+    state: &AppState,
+    info: KioskoInfo,
+    actor: ActorEnum,
+) -> Result<Value, (StatusCode, Json<Value>)> {
     let info_abrir = AbrirCaja {
         id_caja: info.nombre_caja.to_string(),
         id_grupo: info.id_yappy.clone(),
         nombre_caja: Some(info.nombre.clone()),
-        nombre_cajero: None,
+        nombre_cajero: info.nombre_cajero.clone(),
     };
 
     let formatted = info_abrir.to_payload();
 
-    let client = reqwest::Client::new();
+    // la configuracion se valida antes de pasar a Abriendo para no dejar la caja a medias
     let url = format!(
         "{}/session/device",
         env::var("YAPPY_ENDPOINT")
             .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err))?
     );
 
-    let response = client
-        .post(url)
-        .headers(insert_auth_headers(info.api_key, info.secret_key, None))
-        .json(&formatted)
-        .send()
-        .await
-        .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?
-        .text()
-        .await
-        .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?;
+    let _invalidar = InvalidarContexto(state, info.id_caja);
+
+    state.repos.cajas.transicionar(
+        info.id_caja,
+        CajasEstadoEnum::Abriendo,
+        actor.clone(),
+        "Apertura de sesión en Yappy".to_string(),
+        None,
+    )?;
+
+    let client = reqwest::Client::new();
 
-    let response_json: Value = serde_json::from_str(&response).unwrap();
+    let response = async {
+        client
+            .post(url)
+            .headers(insert_auth_headers(info.api_key, info.secret_key, None))
+            .json(&formatted)
+            .send()
+            .await?
+            .text()
+            .await
+    }
+    .await;
+
+    let response = match response {
+        Ok(response) => response,
+        Err(err) => {
+            state.repos.cajas.transicionar(
+                info.id_caja,
+                CajasEstadoEnum::Cerrado,
+                actor,
+                "Error de comunicación con Yappy al abrir".to_string(),
+                None,
+            )?;
+            return Err(json_error(StatusCode::BAD_REQUEST, err));
+        }
+    };
+
+    let response_json: Value = match serde_json::from_str(&response) {
+        Ok(json) => json,
+        Err(err) => {
+            state.repos.cajas.transicionar(
+                info.id_caja,
+                CajasEstadoEnum::Cerrado,
+                actor,
+                "Respuesta ilegible de Yappy al abrir".to_string(),
+                None,
+            )?;
+            return Err(json_error(StatusCode::BAD_GATEWAY, err));
+        }
+    };
 
-    let mut conn = state.db_pool.get().unwrap();
+    let token = response_json
+        .pointer("/body/token")
+        .and_then(|v| v.as_str());
+
+    let motivo = match token {
+        Some(_) => "Sesión abierta en Yappy".to_string(),
+        None => format!(
+            "Yappy rechazó la apertura: {}",
+            response_json
+                .pointer("/status/code")
+                .and_then(|v| v.as_str())
+                .unwrap_or("sin código")
+        ),
+    };
 
-    let _ = diesel::update(cajas::table.filter(cajas::id.eq(info.id_caja)))
-        .set((
-            cajas::token_autorizacion.eq(response_json
-                .pointer("/body/token")
-                .and_then(|v| v.as_str())),
-            cajas::estado.eq(CajasEstadoEnum::Abierto),
-        ))
-        .execute(&mut conn);
+    state.repos.cajas.terminar_apertura(
+        info.id_caja,
+        token,
+        actor.clone(),
+        motivo,
+        serde_json::json!({
+            "nombre_caja": info.nombre_caja,
+            "kiosko": info.nombre,
+            "cajero": info.nombre_cajero,
+            "actor": actor,
+        }),
+    )?;
 
     Ok(response_json)
 }
 
+// al salir por cualquier camino, incluso con `?`, los kioskos de la caja vuelven a leer su
+// contexto completo de la base (el cierre también suelta al cajero)
+struct InvalidarContexto<'a>(&'a AppState, i32);
+
+impl Drop for InvalidarContexto<'_> {
+    fn drop(&mut self) {
+        self.0.repos.kioskos.invalidar_caja(self.1);
+    }
+}
+
 pub async fn manage_transaction_response(
     path: &str,
     response_json: &Value,
     id_caja: i32,
+    transaccion_id: &str,
     state: &AppState,
 ) -> Result<Option<String>, (StatusCode, Json<Value>)> {
     // Handle "estado-transaccion"
@@ -196,6 +474,8 @@ pub async fn manage_transaction_response(
             .pointer("/body/status")
             .and_then(|s| s.as_str())
         {
+            let _ = state.repos.transacciones.actualizar_estado(transaccion_id, status);
+
             if status == "COMPLETED" {
                 let referencia = update_caja_transaccion_actual_null(&state, id_caja);
                 return Ok(referencia?);
@@ -222,22 +502,46 @@ fn update_caja_transaccion_actual_null(
     state: &AppState,
     id_caja: i32,
 ) -> Result<Option<String>, (StatusCode, Json<Value>)> {
-    let mut conn = state.db_pool.get().unwrap();
-
-    let referencia: Option<String> = cajas::table
-        .filter(cajas::id.eq(id_caja))
-        .select(cajas::transaccion_actual)
-        .first(&mut conn).unwrap();
-
-    diesel::update(cajas::table.filter(cajas::id.eq(id_caja)))
-        .set(cajas::transaccion_actual.eq(None::<String>))
-        .execute(&mut conn)
-        .map_err(|err| {
-            json_error(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Error al actualizar la caja: {}", err),
-            )
-        })?;
-
-    Ok(referencia)
+    state.repos.cajas.soltar_transaccion_actual(id_caja).map_err(|err| {
+        json_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Error al actualizar la caja: {}", err),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repos::memoria::prueba;
+    use crate::db::types::enums::CajasTipoEnum;
+    use crate::utils::tiempo;
+
+    #[tokio::test]
+    async fn forzar_cierre_recupera_una_apertura_sin_llamar_a_yappy() {
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Cerrado, CajasTipoEnum::Kiosko);
+        prueba::transicion(&mut memoria, 1, CajasEstadoEnum::Abriendo, tiempo::ahora());
+        let (state, repos) = prueba::estado(memoria);
+
+        let resultado = forzar_cierre(&state, 1, ActorEnum::Admin, "cierre-admin", "prueba".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(resultado.exito);
+        assert_eq!(repos.datos().cajas[0].estado, CajasEstadoEnum::Cerrado);
+    }
+
+    #[tokio::test]
+    async fn forzar_cierre_de_una_caja_cerrada_no_hace_nada() {
+        let memoria = prueba::memoria(CajasEstadoEnum::Cerrado, CajasTipoEnum::Kiosko);
+        let (state, repos) = prueba::estado(memoria);
+
+        let resultado = forzar_cierre(&state, 1, ActorEnum::Admin, "cierre-admin", "prueba".to_string())
+            .await
+            .unwrap();
+
+        assert!(resultado.is_none());
+        assert!(repos.datos().transiciones.is_empty());
+    }
 }
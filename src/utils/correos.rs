@@ -0,0 +1,316 @@
+use std::collections::BTreeMap;
+use std::env;
+
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, header::ContentType},
+    transport::smtp::authentication::Credentials,
+};
+use serde_json::Value;
+
+use crate::AppState;
+use crate::db::repos::CierreRepo;
+use crate::utils::cajas_utils::ResultadoCierre;
+use crate::utils::tiempo;
+
+/// Servidor SMTP para las notificaciones. Sin `SMTP_HOST` no se envían correos.
+///
+/// `SMTP_TLS` puede ser `tls`, `starttls` o `ninguno` (por defecto, útil con un
+/// buzón de pruebas local como mailpit en el puerto 1025).
+pub struct ConfigSmtp {
+    transporte: AsyncSmtpTransport<Tokio1Executor>,
+    remitente: Mailbox,
+}
+
+impl ConfigSmtp {
+    pub fn desde_env() -> Option<Self> {
+        let host = env::var("SMTP_HOST").ok()?;
+        let port = env::var("SMTP_PORT").ok().and_then(|v| v.parse().ok());
+
+        let builder = match env::var("SMTP_TLS").unwrap_or_default().as_str() {
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&host),
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host),
+            _ => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host)),
+        };
+
+        let mut builder = match builder {
+            Ok(builder) => builder,
+            Err(err) => {
+                println!("configuración SMTP inválida: {}", err);
+                return None;
+            }
+        };
+
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+        if let Ok(usuario) = env::var("SMTP_USUARIO")
+            && !usuario.is_empty()
+        {
+            let password = env::var("SMTP_PASSWORD").unwrap_or_default();
+            builder = builder.credentials(Credentials::new(usuario, password));
+        }
+
+        let remitente = env::var("SMTP_REMITENTE")
+            .unwrap_or_else(|_| "MACY <macy@localhost>".to_string())
+            .parse()
+            .map_err(|err| println!("SMTP_REMITENTE inválido: {}", err))
+            .ok()?;
+
+        Some(ConfigSmtp {
+            transporte: builder.build(),
+            remitente,
+        })
+    }
+}
+
+/// Últimos ids de resumen y de errores de cierre antes de una corrida, para
+/// saber qué filas escribió la corrida.
+#[derive(Debug, Clone, Copy)]
+pub struct MarcaCierre {
+    resumen: i32,
+    errores: i32,
+}
+
+impl MarcaCierre {
+    pub fn tomar(cierres: &dyn CierreRepo) -> QueryResult<Self> {
+        let (resumen, errores) = cierres.ultimos_ids()?;
+        Ok(MarcaCierre { resumen, errores })
+    }
+}
+
+struct CajaDelCorreo {
+    nombre_caja: String,
+    exito: bool,
+    resumen: Vec<(String, BigDecimal, i32)>,
+    errores: Vec<Value>,
+}
+
+/// Envía a cada grupo con `correos_cierre` el resumen de sus cajas en la corrida.
+pub async fn notificar_cierres(state: &AppState, marca: MarcaCierre, resultados: &[ResultadoCierre]) {
+    let Some(smtp) = ConfigSmtp::desde_env() else {
+        println!("SMTP_HOST no configurado, no se envían correos de cierre");
+        return;
+    };
+
+    if resultados.is_empty() {
+        return;
+    }
+
+    let correos = match armar_correos(state, marca, resultados) {
+        Ok(correos) => correos,
+        Err(err) => {
+            println!("no se pudo armar el correo de cierre: {}", err);
+            return;
+        }
+    };
+
+    for (nombre_grupo, destinatarios, cuerpo) in correos {
+        let fecha = tiempo::hoy_panama().format("%d/%m/%Y");
+
+        let mut mensaje = Message::builder()
+            .from(smtp.remitente.clone())
+            .subject(format!("Cierre de cajas {} - {}", nombre_grupo, fecha));
+
+        for destinatario in &destinatarios {
+            match destinatario.parse::<Mailbox>() {
+                Ok(mailbox) => mensaje = mensaje.to(mailbox),
+                Err(err) => println!("correo inválido {} en el grupo {}: {}", destinatario, nombre_grupo, err),
+            }
+        }
+
+        let mensaje = match mensaje.header(ContentType::TEXT_PLAIN).body(cuerpo) {
+            Ok(mensaje) => mensaje,
+            Err(err) => {
+                println!("no se pudo armar el correo del grupo {}: {}", nombre_grupo, err);
+                continue;
+            }
+        };
+
+        match smtp.transporte.send(mensaje).await {
+            Ok(_) => println!("correo de cierre enviado al grupo {}", nombre_grupo),
+            Err(err) => println!("error enviando el correo de cierre del grupo {}: {}", nombre_grupo, err),
+        }
+    }
+}
+
+/// (nombre del grupo, destinatarios, cuerpo) por cada grupo con correos configurados.
+fn armar_correos(
+    state: &AppState,
+    marca: MarcaCierre,
+    resultados: &[ResultadoCierre],
+) -> QueryResult<Vec<(String, Vec<String>, String)>> {
+    let ids: Vec<i32> = resultados.iter().map(|r| r.id_caja).collect();
+
+    let (resumen, errores) = state
+        .repos
+        .cierres
+        .guardados_desde(&ids, (marca.resumen, marca.errores))?;
+
+    // id_grupo -> (nombre, destinatarios, cajas)
+    let mut por_grupo: BTreeMap<i32, (String, Vec<String>, Vec<CajaDelCorreo>)> = BTreeMap::new();
+
+    for resultado in resultados {
+        let id_grupo = state.repos.cajas.buscar(resultado.id_caja)?.id_grupo;
+        let grupo = state.repos.grupos.buscar(id_grupo)?;
+
+        let destinatarios: Vec<String> = grupo
+            .correos_cierre
+            .as_ref()
+            .and_then(|v| v.as_array())
+            .map(|lista| {
+                lista
+                    .iter()
+                    .filter_map(|v| v.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if destinatarios.is_empty() {
+            continue;
+        }
+
+        por_grupo
+            .entry(grupo.id)
+            .or_insert_with(|| (grupo.nombre, destinatarios, Vec::new()))
+            .2
+            .push(CajaDelCorreo {
+                nombre_caja: resultado.nombre_caja.clone(),
+                exito: resultado.exito,
+                resumen: resumen
+                    .iter()
+                    .filter(|r| r.id_caja == resultado.id_caja)
+                    .map(|r| (r.tipo.clone(), r.monto.clone(), r.transacciones))
+                    .collect(),
+                errores: errores
+                    .iter()
+                    .filter(|e| e.id_caja == resultado.id_caja)
+                    .map(|e| e.respuesta_json.clone())
+                    .collect(),
+            });
+    }
+
+    Ok(por_grupo
+        .into_values()
+        .map(|(nombre, destinatarios, cajas)| {
+            let cuerpo = cuerpo_correo(&nombre, &cajas);
+            (nombre, destinatarios, cuerpo)
+        })
+        .collect())
+}
+
+fn cuerpo_correo(nombre_grupo: &str, cajas: &[CajaDelCorreo]) -> String {
+    let fallidas = cajas.iter().filter(|c| !c.exito).count();
+
+    let mut cuerpo = format!(
+        "Cierre de cajas del grupo {}\n{} caja(s) cerrada(s), {} con error.\n",
+        nombre_grupo,
+        cajas.len() - fallidas,
+        fallidas
+    );
+
+    for caja in cajas {
+        cuerpo.push_str(&format!(
+            "\n{} - {}\n",
+            caja.nombre_caja,
+            if caja.exito { "cerrada" } else { "ERROR" }
+        ));
+
+        for (tipo, monto, transacciones) in &caja.resumen {
+            cuerpo.push_str(&format!(
+                "  {:<20} {:>12} {:>6} transacciones\n",
+                tipo,
+                monto.with_scale(2),
+                transacciones
+            ));
+        }
+
+        for error in &caja.errores {
+            let codigo = error
+                .pointer("/status/code")
+                .and_then(|v| v.as_str())
+                .unwrap_or("sin código");
+            let descripcion = error
+                .pointer("/status/description")
+                .or_else(|| error.get("error"))
+                .map(|v| v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string()))
+                .unwrap_or_default();
+
+            cuerpo.push_str(&format!("  error {}: {}\n", codigo, descripcion));
+        }
+    }
+
+    cuerpo
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::{NewCajaCierreError, NewCajaCierreResumen};
+    use crate::db::repos::memoria::prueba;
+    use crate::db::types::enums::{CajasEstadoEnum, CajasTipoEnum};
+    use serde_json::json;
+
+    fn resultado(exito: bool) -> ResultadoCierre {
+        ResultadoCierre {
+            id_caja: 1,
+            nombre_caja: "Caja 1".to_string(),
+            exito,
+            codigo_yappy: None,
+            respuesta: json!({}),
+        }
+    }
+
+    #[test]
+    fn el_correo_solo_lleva_lo_que_guardo_la_corrida() {
+        let mut memoria = prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko);
+        memoria.grupos[0].correos_cierre = Some(json!(["finanzas@macy.pa"]));
+        memoria.cierres_resumen.push(NewCajaCierreResumen {
+            id_caja: 1,
+            tipo: "ANTERIOR".to_string(),
+            monto: "1.00".parse().unwrap(),
+            transacciones: 1,
+            id_cajero: None,
+        });
+        let (state, repos) = prueba::estado(memoria);
+
+        let marca = MarcaCierre::tomar(&repos).unwrap();
+        let cierres = &state.repos.cierres;
+        cierres
+            .guardar_resumen(NewCajaCierreResumen {
+                id_caja: 1,
+                tipo: "VENTAS".to_string(),
+                monto: "25.50".parse().unwrap(),
+                transacciones: 3,
+                id_cajero: None,
+            })
+            .unwrap();
+        cierres
+            .guardar_error(NewCajaCierreError {
+                id_caja: 1,
+                respuesta_json: json!({ "status": { "code": "YP-0400", "description": "fallo" } }),
+                id_cajero: None,
+            })
+            .unwrap();
+
+        let correos = armar_correos(&state, marca, &[resultado(false)]).unwrap();
+
+        assert_eq!(correos.len(), 1);
+        let (grupo, destinatarios, cuerpo) = &correos[0];
+        assert_eq!(grupo, "Grupo 1");
+        assert_eq!(destinatarios, &vec!["finanzas@macy.pa".to_string()]);
+        assert!(cuerpo.contains("VENTAS") && cuerpo.contains("25.50"));
+        assert!(cuerpo.contains("error YP-0400: fallo"));
+        assert!(!cuerpo.contains("ANTERIOR"));
+    }
+
+    #[test]
+    fn un_grupo_sin_correos_no_recibe_nada() {
+        let (state, repos) = prueba::estado(prueba::memoria(CajasEstadoEnum::Abierto, CajasTipoEnum::Kiosko));
+        let marca = MarcaCierre::tomar(&repos).unwrap();
+
+        assert!(armar_correos(&state, marca, &[resultado(true)]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
+};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// Hash argon2 en formato PHC, apto para guardar PINs y contraseñas.
+pub fn hashear_secreto(secreto: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(secreto.as_bytes(), &salt)?
+        .to_string())
+}
+
+pub fn verificar_secreto(secreto: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| {
+            Argon2::default()
+                .verify_password(secreto.as_bytes(), &parsed)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClaimsUsuario {
+    pub sub: i32,
+    pub exp: i64,
+}
+
+fn secreto_jwt() -> Result<String, jsonwebtoken::errors::Error> {
+    env::var("JWT_SECRET")
+        .ok()
+        .filter(|secreto| !secreto.is_empty())
+        .ok_or_else(|| jsonwebtoken::errors::ErrorKind::InvalidKeyFormat.into())
+}
+
+/// Firma un JWT HS256 para el usuario; dura `JWT_HORAS` (8 por defecto).
+pub fn emitir_token(id_usuario: i32) -> Result<(String, DateTime<Utc>), jsonwebtoken::errors::Error> {
+    let horas: i64 = env::var("JWT_HORAS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(8);
+    let expira = Utc::now() + Duration::hours(horas);
+
+    let token = encode(
+        &Header::default(),
+        &ClaimsUsuario {
+            sub: id_usuario,
+            exp: expira.timestamp(),
+        },
+        &EncodingKey::from_secret(secreto_jwt()?.as_bytes()),
+    )?;
+
+    Ok((token, expira))
+}
+
+/// Valida firma y expiración; devuelve el id del usuario.
+pub fn verificar_token(token: &str) -> Option<i32> {
+    let secreto = secreto_jwt().ok()?;
+
+    decode::<ClaimsUsuario>(
+        token,
+        &DecodingKey::from_secret(secreto.as_bytes()),
+        &Validation::default(),
+    )
+    .ok()
+    .map(|datos| datos.claims.sub)
+}
@@ -1,2 +1,13 @@
 pub mod utils;
-pub mod cajas_utils;
\ No newline at end of file
+pub mod cajas_utils;
+pub mod auditoria;
+pub mod monitor_transacciones;
+pub mod credenciales;
+pub mod transacciones_utils;
+pub mod qr;
+pub mod recibos;
+pub mod totales;
+pub mod usuarios;
+pub mod correos;
+pub mod webhooks;
+pub mod tiempo;
\ No newline at end of file
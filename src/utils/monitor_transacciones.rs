@@ -0,0 +1,183 @@
+use crate::AppState;
+use crate::db::models::KioskoInfo;
+use crate::utils::cajas_utils::{consultar_transaccion_en_yappy, manage_transaction_response};
+use crate::utils::recibos::recibo_impreso;
+use crate::utils::tiempo;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::Instrument;
+
+const ESPERA_INICIAL: Duration = Duration::from_secs(2);
+const ESPERA_MAXIMA: Duration = Duration::from_secs(15);
+const DURACION_MAXIMA: Duration = Duration::from_secs(10 * 60);
+
+/// Consulta a Yappy una sola vez por transacción y reparte cada cambio de estado
+/// a todos los kioskos suscritos.
+#[derive(Clone, Default)]
+pub struct MonitorTransacciones {
+    canales: Arc<Mutex<HashMap<String, watch::Sender<Value>>>>,
+}
+
+pub fn es_estado_final(estado: &str) -> bool {
+    matches!(
+        estado,
+        "COMPLETED" | "EXPIRED" | "REFUNDED" | "DECLINED" | "FAILED" | "CANCELLED"
+    )
+}
+
+impl MonitorTransacciones {
+    pub fn suscribir(
+        &self,
+        state: &AppState,
+        info: KioskoInfo,
+        transaccion_id: String,
+    ) -> watch::Receiver<Value> {
+        let mut canales = self.canales.lock().unwrap();
+
+        if let Some(tx) = canales.get(&transaccion_id) {
+            return tx.subscribe();
+        }
+
+        let (tx, rx) = watch::channel(Value::Null);
+        canales.insert(transaccion_id.clone(), tx.clone());
+
+        // la consulta es compartida: no hereda el request id del primer suscrito, sus logs
+        // van bajo su propio span con la transaccion
+        let span = tracing::info_span!("monitor", transaccion = %transaccion_id);
+        tokio::spawn(
+            vigilar_transaccion(state.clone(), self.clone(), info, transaccion_id, tx)
+                .instrument(span),
+        );
+
+        rx
+    }
+
+    /// Envía un evento final a los suscritos y deja de consultar la transacción.
+    pub fn finalizar(&self, transaccion_id: &str, evento: Value) {
+        if let Some(tx) = self.canales.lock().unwrap().remove(transaccion_id) {
+            let _ = tx.send(evento);
+        }
+    }
+
+    fn vigente(&self, transaccion_id: &str, tx: &watch::Sender<Value>) -> bool {
+        self.canales
+            .lock()
+            .unwrap()
+            .get(transaccion_id)
+            .is_some_and(|actual| actual.same_channel(tx))
+    }
+
+    /// Quita el canal si ya nadie escucha; devuelve `false` si alguien se suscribió mientras tanto.
+    fn liberar(&self, transaccion_id: &str, forzar: bool) -> bool {
+        let mut canales = self.canales.lock().unwrap();
+
+        match canales.get(transaccion_id) {
+            Some(tx) if forzar || tx.receiver_count() == 0 => {
+                canales.remove(transaccion_id);
+                true
+            }
+            Some(_) => false,
+            None => true,
+        }
+    }
+}
+
+async fn vigilar_transaccion(
+    state: AppState,
+    monitor: MonitorTransacciones,
+    info: KioskoInfo,
+    transaccion_id: String,
+    tx: watch::Sender<Value>,
+) {
+    let inicio = tokio::time::Instant::now();
+    let mut espera = ESPERA_INICIAL;
+    let mut estado_anterior: Option<String> = None;
+
+    loop {
+        // otro flujo (p. ej. una cancelacion) ya cerro la transaccion
+        if !monitor.vigente(&transaccion_id, &tx) {
+            return;
+        }
+
+        if tx.receiver_count() == 0 && monitor.liberar(&transaccion_id, false) {
+            return;
+        }
+
+        let respuesta = consultar_transaccion_en_yappy(
+            info.api_key.clone(),
+            info.secret_key.clone(),
+            info.token_autorizacion.clone(),
+            &transaccion_id,
+        )
+        .await;
+
+        match respuesta {
+            Ok(response_json) => {
+                let estado = response_json
+                    .pointer("/body/status")
+                    .and_then(|s| s.as_str())
+                    .unwrap_or("UNKNOWN")
+                    .to_string();
+
+                if estado_anterior.as_deref() != Some(estado.as_str()) {
+                    let mut evento = json!({
+                        "transaccion": transaccion_id,
+                        "estado": estado,
+                        "data": response_json,
+                    });
+
+                    // mismo contenido que agrega handle_transaccion al completar
+                    if let Ok(Some(referencia)) = manage_transaction_response(
+                        "estado-transaccion",
+                        &response_json,
+                        info.id_caja,
+                        &transaccion_id,
+                        &state,
+                    )
+                    .await
+                    {
+                        let ahora = tiempo::ahora();
+
+                        evento["referencia"] = json!(referencia);
+                        evento["id_caja"] = json!(info.id_caja);
+                        evento["nombre_caja"] = json!(info.nombre_caja);
+                        evento["fecha"] = json!(tiempo::rfc3339(&ahora));
+                        evento["fecha_texto"] = json!(tiempo::texto_legado(&ahora));
+
+                        if let Some(recibo) = recibo_impreso(&state.repos, &referencia) {
+                            evento["recibo"] = json!(recibo);
+                        }
+                    }
+
+                    let _ = tx.send(evento);
+                    estado_anterior = Some(estado.clone());
+                    espera = ESPERA_INICIAL;
+                } else {
+                    espera = (espera * 3 / 2).min(ESPERA_MAXIMA);
+                }
+
+                if es_estado_final(&estado) {
+                    monitor.liberar(&transaccion_id, true);
+                    return;
+                }
+            }
+            Err((_status, err_json)) => {
+                tracing::warn!(
+                    "error consultando la transaccion {}: {}",
+                    transaccion_id, err_json.0
+                );
+                espera = (espera * 2).min(ESPERA_MAXIMA);
+            }
+        }
+
+        if inicio.elapsed() > DURACION_MAXIMA {
+            monitor.liberar(&transaccion_id, true);
+            return;
+        }
+
+        tokio::time::sleep(espera).await;
+    }
+}
@@ -0,0 +1,231 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use image::{
+    GrayImage, ImageFormat, Luma, Rgba, RgbaImage,
+    imageops::{self, FilterType},
+};
+use qrcode::{Color, EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+use std::{env, io::Cursor};
+use thiserror::Error;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Error, Debug)]
+pub enum QrError {
+    #[error("No se pudo generar el QR: {0}")]
+    Codigo(#[from] qrcode::types::QrError),
+    #[error("No se pudo procesar la imagen: {0}")]
+    Imagen(#[from] image::ImageError),
+    #[error("QR_LOGO_PATH no está configurado")]
+    SinLogo,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatoQR {
+    #[default]
+    Png,
+    Svg,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, ToSchema)]
+pub enum CorreccionQR {
+    L,
+    M,
+    Q,
+    H,
+}
+
+/// Opciones de dibujo; lo que no venga en la petición se toma de `QR_TAMANO`,
+/// `QR_MARGEN` y `QR_CORRECCION`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OpcionesQR {
+    #[serde(default)]
+    pub formato: FormatoQR,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tamano: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub margen: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correccion: Option<CorreccionQR>,
+    #[serde(default)]
+    pub logo: bool,
+}
+
+pub struct ImagenQR {
+    pub formato: FormatoQR,
+    pub bytes: Vec<u8>,
+}
+
+impl ImagenQR {
+    pub fn content_type(&self) -> &'static str {
+        match self.formato {
+            FormatoQR::Png => "image/png",
+            FormatoQR::Svg => "image/svg+xml",
+        }
+    }
+
+    pub fn base64(&self) -> String {
+        STANDARD.encode(&self.bytes)
+    }
+}
+
+fn env_u32(nombre: &str, defecto: u32) -> u32 {
+    env::var(nombre)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(defecto)
+}
+
+impl OpcionesQR {
+    fn tamano(&self) -> u32 {
+        self.tamano.unwrap_or_else(|| env_u32("QR_TAMANO", 300)).clamp(64, 2048)
+    }
+
+    fn margen(&self) -> u32 {
+        self.margen.unwrap_or_else(|| env_u32("QR_MARGEN", 4)).min(16)
+    }
+
+    fn nivel(&self) -> EcLevel {
+        let correccion = self.correccion.or_else(|| {
+            match env::var("QR_CORRECCION").ok()?.to_uppercase().as_str() {
+                "L" => Some(CorreccionQR::L),
+                "M" => Some(CorreccionQR::M),
+                "Q" => Some(CorreccionQR::Q),
+                "H" => Some(CorreccionQR::H),
+                _ => None,
+            }
+        });
+
+        match correccion {
+            Some(CorreccionQR::L) => EcLevel::L,
+            Some(CorreccionQR::M) => EcLevel::M,
+            Some(CorreccionQR::Q) => EcLevel::Q,
+            Some(CorreccionQR::H) => EcLevel::H,
+            // el logo tapa parte del codigo, hace falta la correccion mas alta
+            None if self.logo => EcLevel::H,
+            None => EcLevel::M,
+        }
+    }
+}
+
+pub fn renderizar_qr(contenido: &str, opciones: &OpcionesQR) -> Result<ImagenQR, QrError> {
+    let codigo = QrCode::with_error_correction_level(contenido.as_bytes(), opciones.nivel())?;
+    let logo = if opciones.logo { Some(cargar_logo()?) } else { None };
+
+    let bytes = match opciones.formato {
+        FormatoQR::Png => renderizar_png(&codigo, opciones, logo)?,
+        FormatoQR::Svg => renderizar_svg(&codigo, opciones, logo).into_bytes(),
+    };
+
+    Ok(ImagenQR {
+        formato: opciones.formato,
+        bytes,
+    })
+}
+
+fn cargar_logo() -> Result<RgbaImage, QrError> {
+    let ruta = env::var("QR_LOGO_PATH").map_err(|_| QrError::SinLogo)?;
+    Ok(image::open(ruta)?.to_rgba8())
+}
+
+/// Tamaño de cada módulo en pixeles y tamaño final de la imagen.
+fn dimensiones(codigo: &QrCode, opciones: &OpcionesQR) -> (u32, u32) {
+    let modulos = codigo.width() as u32 + 2 * opciones.margen();
+    let escala = (opciones.tamano() / modulos).max(1);
+    (escala, escala * modulos)
+}
+
+fn renderizar_png(
+    codigo: &QrCode,
+    opciones: &OpcionesQR,
+    logo: Option<RgbaImage>,
+) -> Result<Vec<u8>, QrError> {
+    let (escala, lado) = dimensiones(codigo, opciones);
+    let margen = opciones.margen();
+    let ancho = codigo.width() as u32;
+    let colores = codigo.to_colors();
+
+    let qr = GrayImage::from_fn(lado, lado, |x, y| {
+        let (mx, my) = (x / escala, y / escala);
+        let oscuro = mx >= margen
+            && my >= margen
+            && mx - margen < ancho
+            && my - margen < ancho
+            && colores[((my - margen) * ancho + (mx - margen)) as usize] == Color::Dark;
+
+        if oscuro { Luma([0]) } else { Luma([255]) }
+    });
+
+    let mut salida = Cursor::new(Vec::new());
+
+    match logo {
+        Some(logo) => {
+            let mut imagen: RgbaImage = image::DynamicImage::ImageLuma8(qr).to_rgba8();
+            let lado_logo = lado / 5;
+            let logo = imageops::resize(&logo, lado_logo, lado_logo, FilterType::Triangle);
+
+            // recuadro blanco para que el logo no se mezcle con los modulos
+            let borde = escala.max(2);
+            let inicio = (lado - lado_logo) / 2 - borde;
+            let fondo = RgbaImage::from_pixel(
+                lado_logo + 2 * borde,
+                lado_logo + 2 * borde,
+                Rgba([255, 255, 255, 255]),
+            );
+            imageops::overlay(&mut imagen, &fondo, inicio as i64, inicio as i64);
+            imageops::overlay(
+                &mut imagen,
+                &logo,
+                (inicio + borde) as i64,
+                (inicio + borde) as i64,
+            );
+            imagen.write_to(&mut salida, ImageFormat::Png)?;
+        }
+        None => qr.write_to(&mut salida, ImageFormat::Png)?,
+    }
+
+    Ok(salida.into_inner())
+}
+
+fn renderizar_svg(codigo: &QrCode, opciones: &OpcionesQR, logo: Option<RgbaImage>) -> String {
+    let (escala, lado) = dimensiones(codigo, opciones);
+    let margen = opciones.margen();
+    let ancho = codigo.width() as u32;
+
+    let mut trazo = String::new();
+    for (i, color) in codigo.to_colors().iter().enumerate() {
+        if *color == Color::Dark {
+            let x = (i as u32 % ancho + margen) * escala;
+            let y = (i as u32 / ancho + margen) * escala;
+            trazo.push_str(&format!("M{x} {y}h{escala}v{escala}h-{escala}z"));
+        }
+    }
+
+    let mut svg = format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{lado}" height="{lado}" viewBox="0 0 {lado} {lado}"><rect width="100%" height="100%" fill="#fff"/><path d="{trazo}" fill="#000"/>"##
+    );
+
+    if let Some(logo) = logo {
+        let mut png = Cursor::new(Vec::new());
+        if image::DynamicImage::ImageRgba8(logo)
+            .write_to(&mut png, ImageFormat::Png)
+            .is_ok()
+        {
+            let lado_logo = lado / 5;
+            let borde = escala.max(2);
+            let inicio = (lado - lado_logo) / 2;
+            svg.push_str(&format!(
+                r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#fff"/><image x="{inicio}" y="{inicio}" width="{lado_logo}" height="{lado_logo}" href="data:image/png;base64,{}"/>"##,
+                inicio - borde,
+                inicio - borde,
+                lado_logo + 2 * borde,
+                lado_logo + 2 * borde,
+                STANDARD.encode(png.into_inner()),
+            ));
+        }
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
@@ -0,0 +1,211 @@
+use crate::db::models::Transaccion;
+use crate::db::repos::Repos;
+use crate::utils::tiempo;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use diesel::OptionalExtension;
+use minijinja::Environment;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+// columnas de una impresora termica de 80mm con la fuente A
+const ANCHO: usize = 42;
+
+const PLANTILLA_POR_DEFECTO: &str = r#"{{ centrar(grupo | upper) }}
+{{ centrar(caja) }}
+{% if cajero %}{{ centrar("Atendido por: " ~ cajero) }}
+{% endif %}{{ separador }}
+{% for item in items %}{{ linea(item.cantidad ~ " x " ~ item.descripcion, item.total) }}
+{% endfor %}{% if items %}{{ separador }}
+{% endif %}{{ linea("Pedido", id_orden or "-") }}
+{{ linea("Subtotal", subtotal) }}
+{{ linea("ITBMS", impuesto) }}
+{% if propina != "0.00" %}{{ linea("Propina", propina) }}
+{% endif %}{% if descuento != "0.00" %}{{ linea("Descuento", "-" ~ descuento) }}
+{% endif %}{{ separador }}
+{{ linea("TOTAL B/.", total) }}
+{{ separador }}
+{{ linea("Ref. Yappy", referencia) }}
+{{ linea("Fecha", fecha) }}
+{% if reimpresion %}{{ centrar("*** REIMPRESION ***") }}
+{% endif %}
+{{ centrar("Gracias por su compra") }}
+"#;
+
+#[derive(Debug, Serialize)]
+pub struct ItemRecibo {
+    pub descripcion: String,
+    pub cantidad: i32,
+    pub total: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatosRecibo {
+    pub items: Vec<ItemRecibo>,
+    pub grupo: String,
+    pub caja: String,
+    pub cajero: Option<String>,
+    pub id_orden: Option<String>,
+    pub subtotal: String,
+    pub impuesto: String,
+    pub propina: String,
+    pub descuento: String,
+    pub total: String,
+    pub referencia: String,
+    pub fecha: String,
+    pub reimpresion: bool,
+}
+
+pub struct Recibo {
+    pub texto: String,
+}
+
+/// El recibo en los tres formatos, como va en las respuestas JSON.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReciboImpreso {
+    pub texto: String,
+    pub html: String,
+    pub escpos_base64: String,
+}
+
+impl Recibo {
+    pub fn html(&self) -> String {
+        let escapado = self
+            .texto
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+
+        format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Recibo</title></head>\
+             <body><pre style=\"font-family: monospace\">{}</pre></body></html>",
+            escapado
+        )
+    }
+
+    /// Bytes listos para una impresora ESC/POS, con la página de códigos WPC1252 y corte al final.
+    pub fn escpos(&self) -> Vec<u8> {
+        let mut bytes = vec![0x1B, 0x40, 0x1B, 0x74, 0x10];
+
+        // las letras con tilde de español caben en Latin-1, que coincide con WPC1252
+        bytes.extend(self.texto.chars().map(|c| {
+            if (c as u32) <= 0xFF { c as u8 } else { b'?' }
+        }));
+
+        bytes.extend([0x1B, 0x64, 0x04, 0x1D, 0x56, 0x42, 0x00]);
+        bytes
+    }
+
+    pub fn impreso(&self) -> ReciboImpreso {
+        ReciboImpreso {
+            texto: self.texto.clone(),
+            html: self.html(),
+            escpos_base64: STANDARD.encode(self.escpos()),
+        }
+    }
+}
+
+fn linea(etiqueta: String, valor: String) -> String {
+    let largo = etiqueta.chars().count() + valor.chars().count();
+    if largo < ANCHO {
+        format!("{}{}{}", etiqueta, " ".repeat(ANCHO - largo), valor)
+    } else {
+        format!("{}\n{:>ancho$}", etiqueta, valor, ancho = ANCHO)
+    }
+}
+
+fn centrar(texto: String) -> String {
+    let largo = texto.chars().count();
+    if largo >= ANCHO {
+        texto
+    } else {
+        format!("{}{}", " ".repeat((ANCHO - largo) / 2), texto)
+    }
+}
+
+pub fn generar_recibo(plantilla: Option<&str>, datos: &DatosRecibo) -> Result<Recibo, minijinja::Error> {
+    let mut env = Environment::new();
+    env.add_function("linea", linea);
+    env.add_function("centrar", centrar);
+    env.add_global("separador", "-".repeat(ANCHO));
+    env.add_template("recibo", plantilla.unwrap_or(PLANTILLA_POR_DEFECTO))?;
+
+    let texto = env.get_template("recibo")?.render(datos)?;
+
+    Ok(Recibo { texto })
+}
+
+/// Arma el recibo de una venta con la plantilla de su grupo.
+pub fn recibo_de_transaccion(
+    repos: &Repos,
+    transaccion: &Transaccion,
+    reimpresion: bool,
+) -> Result<Recibo, String> {
+    let caja = repos
+        .cajas
+        .buscar(transaccion.id_caja)
+        .map_err(|err| err.to_string())?;
+    let grupo = repos
+        .grupos
+        .buscar(caja.id_grupo)
+        .map_err(|err| err.to_string())?;
+
+    let cajero = match transaccion.id_cajero {
+        Some(id_cajero) => repos
+            .cajas
+            .buscar_cajero(id_cajero)
+            .optional()
+            .map_err(|err| err.to_string())?
+            .map(|cajero| cajero.nombre),
+        None => None,
+    };
+
+    // la fecha de la venta: un reembolso posterior cambia `fecha_actualizacion`
+    // y la reimpresion debe salir igual que el recibo original
+    let fecha = transaccion
+        .fecha
+        .map(|fecha| tiempo::texto_legado(&fecha))
+        .unwrap_or_default();
+
+    let items = repos
+        .transacciones
+        .items(transaccion.id)
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|item| ItemRecibo {
+            descripcion: item.descripcion,
+            cantidad: item.cantidad,
+            total: format!("{:.2}", item.total),
+        })
+        .collect();
+
+    let datos = DatosRecibo {
+        items,
+        grupo: grupo.nombre,
+        caja: caja.nombre_caja,
+        cajero,
+        id_orden: transaccion.id_orden.clone(),
+        subtotal: format!("{:.2}", transaccion.subtotal),
+        impuesto: format!("{:.2}", transaccion.impuesto),
+        propina: format!("{:.2}", transaccion.propina),
+        descuento: format!("{:.2}", transaccion.descuento),
+        total: format!("{:.2}", transaccion.total),
+        referencia: transaccion.id_transaccion_yappy.clone(),
+        fecha,
+        reimpresion,
+    };
+
+    generar_recibo(grupo.plantilla_recibo.as_deref(), &datos).map_err(|err| err.to_string())
+}
+
+/// Recibo (texto, html y ESC/POS) de una venta buscada por su id de Yappy.
+pub fn recibo_impreso(repos: &Repos, id_transaccion_yappy: &str) -> Option<ReciboImpreso> {
+    let transaccion = repos.transacciones.buscar(id_transaccion_yappy).ok()?;
+
+    match recibo_de_transaccion(repos, &transaccion, false) {
+        Ok(recibo) => Some(recibo.impreso()),
+        Err(err) => {
+            tracing::warn!("no se pudo generar el recibo de {}: {}", id_transaccion_yappy, err);
+            None
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Fechas de MACY en un solo lugar.
+//!
+//! La base guarda todo en UTC (cada conexión del pool corre con `time_zone = '+00:00'`),
+//! las respuestas salen en RFC 3339 con la hora de Panamá (`-05:00`) y los reportes
+//! agrupan por día de Panamá.
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::America::Panama;
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// Formato que mostraban los kioskos antes de RFC 3339; se mantiene en recibos y en `fecha_texto`.
+pub const FORMATO_LEGADO: &str = "%m/%d/%Y %I:%M:%S %p";
+
+/// Hora actual en UTC, lista para guardar en la base.
+pub fn ahora() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+pub fn en_panama(utc: &NaiveDateTime) -> DateTime<Tz> {
+    Panama.from_utc_datetime(utc)
+}
+
+/// `2026-10-19T18:30:00-05:00`
+pub fn rfc3339(utc: &NaiveDateTime) -> String {
+    en_panama(utc).to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+/// `10/19/2026 06:30:00 PM`
+pub fn texto_legado(utc: &NaiveDateTime) -> String {
+    en_panama(utc).format(FORMATO_LEGADO).to_string().to_uppercase()
+}
+
+pub fn dia_panama(utc: &NaiveDateTime) -> NaiveDate {
+    en_panama(utc).date_naive()
+}
+
+pub fn hoy_panama() -> NaiveDate {
+    dia_panama(&ahora())
+}
+
+/// Inicio y fin (exclusivo) en UTC del día de Panamá, para filtrar columnas de la base.
+pub fn limites_dia(dia: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+    let inicio = Panama
+        .from_local_datetime(&dia.and_hms_opt(0, 0, 0).unwrap())
+        .unwrap()
+        .naive_utc();
+    (inicio, inicio + Duration::days(1))
+}
+
+/// Lee una fecha con zona (RFC 3339) o sin ella; las que no traen zona se toman como hora de Panamá.
+pub fn interpretar(texto: &str) -> Option<NaiveDateTime> {
+    if let Ok(fecha) = DateTime::parse_from_rfc3339(texto) {
+        return Some(fecha.naive_utc());
+    }
+
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|formato| NaiveDateTime::parse_from_str(texto, formato).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(texto, "%Y-%m-%d")
+                .ok()
+                .and_then(|dia| dia.and_hms_opt(0, 0, 0))
+        })
+        .and_then(|local| Panama.from_local_datetime(&local).single())
+        .map(|fecha| fecha.naive_utc())
+}
+
+/// Para `#[serde(serialize_with = "...")]` en columnas `Timestamp`.
+pub fn serializar<S: Serializer>(utc: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&rfc3339(utc))
+}
+
+/// Para `#[serde(serialize_with = "...")]` en columnas `Nullable<Timestamp>`.
+pub fn serializar_opcion<S: Serializer>(
+    utc: &Option<NaiveDateTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match utc {
+        Some(utc) => serializar(utc, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Para `#[serde(deserialize_with = "...")]` en filtros opcionales; ver `interpretar`.
+pub fn deserializar_opcion<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveDateTime>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(texto) => interpretar(&texto)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("fecha inválida: {}", texto))),
+        None => Ok(None),
+    }
+}
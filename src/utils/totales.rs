@@ -0,0 +1,241 @@
+use crate::controllers::structs::yappy::{GenerarQR, ItemPedido};
+use crate::db::models::{ImpuestosGrupo, NewTransaccionItem};
+use crate::db::repos::GrupoRepo;
+use crate::db::types::enums::ModoRedondeoEnum;
+use crate::utils::transacciones_utils::decimal;
+use bigdecimal::{BigDecimal, RoundingMode, ToPrimitive, Zero};
+use diesel::QueryResult;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+// ITBMS general de Panamá, para grupos sin configuracion propia
+const TASA_ITBMS: &str = "0.07";
+
+// diferencia maxima aceptada entre lo que calcula el kiosko y MACY
+const TOLERANCIA: &str = "0.01";
+
+/// Reglas de impuesto de un grupo: tasa general, tasas por categoría,
+/// categorías exentas y modo de redondeo.
+pub struct ConfiguracionImpuestos {
+    pub tasa_general: BigDecimal,
+    pub tasas_categoria: HashMap<String, BigDecimal>,
+    pub categorias_exentas: Vec<String>,
+    pub redondeo: RoundingMode,
+}
+
+impl Default for ConfiguracionImpuestos {
+    fn default() -> Self {
+        ConfiguracionImpuestos {
+            tasa_general: BigDecimal::from_str(TASA_ITBMS).unwrap(),
+            tasas_categoria: HashMap::new(),
+            categorias_exentas: Vec::new(),
+            redondeo: RoundingMode::HalfUp,
+        }
+    }
+}
+
+impl From<ImpuestosGrupo> for ConfiguracionImpuestos {
+    fn from(config: ImpuestosGrupo) -> Self {
+        // las tasas pueden venir como numero o como texto en el JSON
+        let tasas_categoria = config
+            .tasas_categoria
+            .as_object()
+            .map(|tasas| {
+                tasas
+                    .iter()
+                    .filter_map(|(categoria, tasa)| {
+                        let tasa = match tasa {
+                            serde_json::Value::String(texto) => BigDecimal::from_str(texto).ok(),
+                            otro => BigDecimal::from_str(&otro.to_string()).ok(),
+                        };
+                        tasa.map(|tasa| (categoria.to_lowercase(), tasa))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let categorias_exentas = config
+            .categorias_exentas
+            .as_array()
+            .map(|categorias| {
+                categorias
+                    .iter()
+                    .filter_map(|c| c.as_str().map(str::to_lowercase))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        ConfiguracionImpuestos {
+            tasa_general: config.tasa_general,
+            tasas_categoria,
+            categorias_exentas,
+            redondeo: match config.modo_redondeo {
+                ModoRedondeoEnum::HalfUp => RoundingMode::HalfUp,
+                ModoRedondeoEnum::HalfEven => RoundingMode::HalfEven,
+                ModoRedondeoEnum::Up => RoundingMode::Up,
+                ModoRedondeoEnum::Down => RoundingMode::Down,
+            },
+        }
+    }
+}
+
+impl ConfiguracionImpuestos {
+    /// Carga la configuración del grupo; si no tiene, aplica el ITBMS general.
+    pub fn cargar(grupos: &dyn GrupoRepo, id_grupo: i32) -> QueryResult<Self> {
+        Ok(grupos.impuestos(id_grupo)?.map(Into::into).unwrap_or_default())
+    }
+
+    /// Tasa que corresponde a un item según su categoría y si viene marcado como exento.
+    pub fn tasa_item(&self, item: &ItemPedido) -> BigDecimal {
+        if item.exento_itbms {
+            return BigDecimal::zero();
+        }
+
+        match item.categoria.as_deref().map(str::to_lowercase) {
+            Some(categoria) if self.categorias_exentas.contains(&categoria) => BigDecimal::zero(),
+            Some(categoria) => self
+                .tasas_categoria
+                .get(&categoria)
+                .cloned()
+                .unwrap_or_else(|| self.tasa_general.clone()),
+            None => self.tasa_general.clone(),
+        }
+    }
+
+    pub fn redondear(&self, monto: BigDecimal) -> BigDecimal {
+        monto.with_scale_round(2, self.redondeo)
+    }
+
+    /// Impuesto de un cobro sin items, con la tasa general sobre el importe neto.
+    pub fn impuesto_de(&self, subtotal: f64, descuento: f64) -> BigDecimal {
+        self.redondear((decimal(subtotal) - decimal(descuento)) * &self.tasa_general)
+    }
+}
+
+pub struct LineaCalculada {
+    pub item: ItemPedido,
+    pub tasa: BigDecimal,
+    pub impuesto: BigDecimal,
+    pub total: BigDecimal,
+}
+
+impl LineaCalculada {
+    /// El `id_transaccion` lo asigna el repo al registrar la venta.
+    pub fn to_new(&self) -> NewTransaccionItem {
+        NewTransaccionItem {
+            id_transaccion: 0,
+            sku: self.item.sku.clone(),
+            descripcion: self.item.descripcion.clone(),
+            cantidad: self.item.cantidad,
+            precio_unitario: decimal(self.item.precio_unitario),
+            descuento: decimal(self.item.descuento),
+            exento_itbms: self.item.exento_itbms,
+            impuesto: self.impuesto.clone(),
+            total: self.total.clone(),
+            categoria: self.item.categoria.clone(),
+            tasa_impuesto: self.tasa.clone(),
+        }
+    }
+}
+
+pub struct TotalesPedido {
+    pub subtotal: BigDecimal,
+    pub descuento: BigDecimal,
+    pub impuesto: BigDecimal,
+    pub total: BigDecimal,
+    pub lineas: Vec<LineaCalculada>,
+}
+
+impl TotalesPedido {
+    /// La tasa de todas las líneas si es la misma; `None` si el pedido mezcla tasas.
+    pub fn tasa_unica(&self) -> Option<BigDecimal> {
+        let tasa = &self.lineas.first()?.tasa;
+        self.lineas
+            .iter()
+            .all(|linea| &linea.tasa == tasa)
+            .then(|| tasa.clone())
+    }
+
+    /// Diferencias entre los montos del kiosko y los calculados; vacío si todo cuadra.
+    /// Si el kiosko no envió impuesto, ese campo no se compara.
+    pub fn discrepancias(&self, payload: &GenerarQR) -> Vec<String> {
+        [
+            ("subtotal", Some(payload.subtotal), &self.subtotal),
+            ("descuento", Some(payload.descuento), &self.descuento),
+            ("impuesto", payload.impuesto, &self.impuesto),
+            ("total", Some(payload.total), &self.total),
+        ]
+        .into_iter()
+        .filter_map(|(campo, kiosko, macy)| kiosko.map(|kiosko| (campo, kiosko, macy)))
+        .filter(|(_, kiosko, macy)| difiere(*kiosko, macy))
+        .map(|(campo, kiosko, macy)| format!("{}: kiosko {:.2}, MACY {}", campo, kiosko, macy))
+        .collect()
+    }
+
+    /// Reemplaza los montos del cobro por los calculados, que son los que se envían a Yappy.
+    pub fn aplicar(&self, payload: &mut GenerarQR) {
+        payload.subtotal = self.subtotal.to_f64().unwrap_or_default();
+        payload.descuento = self.descuento.to_f64().unwrap_or_default();
+        payload.impuesto = self.impuesto.to_f64();
+        payload.total = self.total.to_f64().unwrap_or_default();
+    }
+}
+
+/// Calcula subtotal, descuento, ITBMS y total a partir de las líneas del pedido.
+pub fn calcular_totales(
+    items: &[ItemPedido],
+    propina: f64,
+    impuestos: &ConfiguracionImpuestos,
+) -> Result<TotalesPedido, String> {
+    if items.is_empty() {
+        return Err("El pedido no tiene items".to_string());
+    }
+
+    let mut subtotal = BigDecimal::zero();
+    let mut descuento = BigDecimal::zero();
+    let mut impuesto = BigDecimal::zero();
+    let mut lineas = Vec::with_capacity(items.len());
+
+    for item in items {
+        let bruto = decimal(item.precio_unitario) * BigDecimal::from(item.cantidad);
+        let descuento_linea = decimal(item.descuento);
+
+        if item.cantidad <= 0
+            || item.precio_unitario < 0.0
+            || descuento_linea < BigDecimal::zero()
+            || descuento_linea > bruto
+        {
+            return Err(format!("Item {} inválido", item.sku));
+        }
+
+        let importe = &bruto - &descuento_linea;
+        let tasa = impuestos.tasa_item(item);
+        let impuesto_linea = impuestos.redondear(&importe * &tasa);
+
+        subtotal += &bruto;
+        descuento += &descuento_linea;
+        impuesto += &impuesto_linea;
+
+        lineas.push(LineaCalculada {
+            item: item.clone(),
+            total: impuestos.redondear(importe + &impuesto_linea),
+            tasa,
+            impuesto: impuesto_linea,
+        });
+    }
+
+    let total = &subtotal - &descuento + &impuesto + decimal(propina);
+
+    Ok(TotalesPedido {
+        subtotal: impuestos.redondear(subtotal),
+        descuento: impuestos.redondear(descuento),
+        impuesto: impuestos.redondear(impuesto),
+        total: impuestos.redondear(total),
+        lineas,
+    })
+}
+
+/// Indica si un monto del kiosko se aleja del calculado más de la tolerancia.
+pub fn difiere(kiosko: f64, macy: &BigDecimal) -> bool {
+    (decimal(kiosko) - macy).abs() > BigDecimal::from_str(TOLERANCIA).unwrap()
+}
@@ -0,0 +1,55 @@
+use crate::utils::utils::{insert_auth_headers, json_error};
+use axum::{Json, http::StatusCode};
+use bigdecimal::{BigDecimal, FromPrimitive, RoundingMode};
+use serde_json::{Value, json};
+use std::env;
+
+/// Montos en balboas, redondeados a centésimos.
+pub fn decimal(monto: f64) -> BigDecimal {
+    BigDecimal::from_f64(monto)
+        .unwrap_or_default()
+        .with_scale_round(2, RoundingMode::HalfUp)
+}
+
+pub async fn reembolsar_en_yappy(
+    api_key: String,
+    secret_key: String,
+    auth_token: Option<String>,
+    transaccion_id: &str,
+    monto: Option<&BigDecimal>,
+    motivo: &str,
+) -> Result<Value, (StatusCode, Json<Value>)> {
+
+    let client = reqwest::Client::new();
+
+    let url = format!(
+        "{}/transaction/{}",
+        env::var("YAPPY_ENDPOINT")
+            .map_err(|err| json_error(StatusCode::INTERNAL_SERVER_ERROR, err,))?,
+        transaccion_id
+    );
+
+    let mut request = client
+        .put(url)
+        .headers(insert_auth_headers(api_key, secret_key, auth_token));
+
+    // sin monto Yappy devuelve la transaccion completa
+    if let Some(monto) = monto {
+        request = request.json(&json!({
+            "body": {
+                "amount": monto.to_string(),
+                "description": motivo,
+            }
+        }));
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?
+        .text()
+        .await
+        .map_err(|err| json_error(StatusCode::BAD_REQUEST, err))?;
+
+    serde_json::from_str(&response).map_err(|err| json_error(StatusCode::BAD_GATEWAY, err))
+}
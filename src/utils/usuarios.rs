@@ -0,0 +1,37 @@
+use crate::AppState;
+use crate::db::models::NewUsuario;
+use crate::db::types::enums::RolUsuarioEnum;
+use crate::utils::credenciales::hashear_secreto;
+use std::env;
+
+/// Crea el primer superadmin con `ADMIN_EMAIL` y `ADMIN_PASSWORD` si la tabla
+/// de usuarios está vacía. Sin esas variables no hace nada.
+pub fn crear_superadmin_inicial(state: &AppState) {
+    let (Ok(email), Ok(password)) = (env::var("ADMIN_EMAIL"), env::var("ADMIN_PASSWORD")) else {
+        return;
+    };
+
+    let usuarios = &state.repos.usuarios;
+
+    let existentes = usuarios.contar().unwrap_or_default();
+    if existentes > 0 {
+        return;
+    }
+
+    let Ok(password_hash) = hashear_secreto(&password) else {
+        println!("No se pudo hashear la contraseña del superadmin inicial");
+        return;
+    };
+
+    let nuevo = NewUsuario {
+        nombre: "Administrador".to_string(),
+        email: email.trim().to_lowercase(),
+        password_hash,
+        rol: RolUsuarioEnum::Superadmin,
+    };
+
+    match usuarios.crear(nuevo, &[]) {
+        Ok(_) => println!("Superadmin inicial creado: {}", email),
+        Err(err) => println!("Error al crear el superadmin inicial: {:?}", err),
+    }
+}
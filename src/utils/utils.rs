@@ -2,13 +2,8 @@ use axum::{Json, http::StatusCode};
 use reqwest::header::{HeaderMap, HeaderValue};
 use serde_json::json;
 
-use crate::db::models::{Caja, Grupo, Kiosko};
-use crate::schema::{
-    cajas::dsl as cajas_dsl, grupos::dsl as grupos_dsl, kioskos::dsl as kioskos_dsl,
-};
-use diesel::prelude::*;
-use serde::Serialize;
-use crate::db::types::enums::CajasEstadoEnum;
+use crate::db::models::{KioskoInfo, ReembolsoError, TransicionError};
+use crate::middlewares::correlacion;
 use crate::AppState;
 
 /// Converts any error with a label into an Axum-compatible error response.
@@ -17,13 +12,32 @@ pub fn json_error<E: std::fmt::Display>(
     err: E,
 ) -> (StatusCode, Json<serde_json::Value>) {
     let error_msg = format!("{}", err);
-    (
-        status,
-        Json(json!({
-            "success": false,
-            "error": error_msg
-        })),
-    )
+    let mut cuerpo = json!({
+        "success": false,
+        "error": error_msg
+    });
+    if let Some(id) = correlacion::actual() {
+        cuerpo["request_id"] = json!(id);
+    }
+    (status, Json(cuerpo))
+}
+
+impl From<TransicionError> for (StatusCode, Json<serde_json::Value>) {
+    fn from(err: TransicionError) -> Self {
+        match err {
+            TransicionError::Invalida(..) => json_error(StatusCode::CONFLICT, err),
+            TransicionError::DbError(_) => json_error(StatusCode::INTERNAL_SERVER_ERROR, err),
+        }
+    }
+}
+
+impl From<ReembolsoError> for (StatusCode, Json<serde_json::Value>) {
+    fn from(err: ReembolsoError) -> Self {
+        match err {
+            ReembolsoError::ExcedeSaldo(_) => json_error(StatusCode::UNPROCESSABLE_ENTITY, err),
+            ReembolsoError::DbError(_) => json_error(StatusCode::INTERNAL_SERVER_ERROR, err),
+        }
+    }
 }
 
 pub fn insert_auth_headers(
@@ -40,55 +54,16 @@ pub fn insert_auth_headers(
         auth_headers.insert("authorization", HeaderValue::from_str(&t).unwrap());
     }
 
+    // para cruzar nuestros logs con los de Yappy
+    if let Some(id) = correlacion::actual().and_then(|id| HeaderValue::from_str(&id).ok()) {
+        auth_headers.insert(correlacion::ENCABEZADO, id);
+    }
+
     //println!("{:#?}", auth_headers);
     return auth_headers;
 }
 
-#[derive(Debug, Serialize)]
-pub struct KioskoInfo {
-    // From kiosko
-    pub nombre: String,
-
-    // From caja
-    pub id_caja: i32,
-    pub nombre_caja: String,
-    pub token_autorizacion: Option<String>,
-    pub estado: CajasEstadoEnum, // or String if you serialize it as string
-
-    // From grupo
-    pub id_yappy: String,
-    pub nombre_grupo: String,
-    pub api_key: String,
-    pub secret_key: String,
-}
-
 pub fn get_info_by_mac_address(state: &AppState, mac_address: &str) -> Result<KioskoInfo, diesel::result::Error> {
-    let mut conn = state.db_pool.get().unwrap();
-
-    let kiosko = kioskos_dsl::kioskos
-        .filter(kioskos_dsl::mac_address.eq(mac_address))
-        .select(Kiosko::as_select())
-        .first::<Kiosko>(&mut conn)?; // Unwrap or return error
-
-    let caja = cajas_dsl::cajas
-        .filter(cajas_dsl::id.eq(kiosko.id_caja))
-        .select(Caja::as_select())
-        .first::<Caja>(&mut conn)?;
-
-    let grupo = grupos_dsl::grupos
-        .filter(grupos_dsl::id.eq(caja.id_grupo))
-        .select(Grupo::as_select())
-        .first::<Grupo>(&mut conn)?;
-
-    Ok(KioskoInfo {
-        nombre: kiosko.nombre,
-        id_caja: caja.id,
-        nombre_caja: caja.nombre_caja,
-        token_autorizacion: caja.token_autorizacion,
-        estado: caja.estado,
-        id_yappy: grupo.id_yappy,
-        nombre_grupo: grupo.nombre,
-        api_key: grupo.api_key,
-        secret_key: grupo.secret_key,
-    })
+    state.repos.kioskos.info_por_mac(mac_address)
 }
+
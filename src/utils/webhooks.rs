@@ -0,0 +1,165 @@
+use std::env;
+use std::time::Duration;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::AppState;
+use crate::utils::tiempo;
+
+/// Secreto aleatorio de 32 bytes en hex para un webhook nuevo.
+pub fn generar_secreto() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Firma `<timestamp>.<cuerpo>` con HMAC-SHA256 y el secreto del webhook, en hex.
+pub fn firmar(secreto: &str, timestamp: i64, cuerpo: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secreto.as_bytes()).expect("HMAC acepta cualquier largo de llave");
+    mac.update(format!("{}.{}", timestamp, cuerpo).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Espera antes del siguiente intento: 30s, 1m, 2m, 4m... hasta una hora.
+fn espera_reintento(intentos: i32) -> chrono::Duration {
+    let segundos = 30i64.saturating_mul(1 << intentos.clamp(0, 7));
+    chrono::Duration::seconds(segundos.min(3600))
+}
+
+/// Envía los eventos pendientes cuyo próximo intento ya venció. Los que no reciben
+/// un 2xx se reintentan con backoff hasta que el receptor los confirme o el webhook
+/// se desactive.
+pub async fn entregar_pendientes(state: &AppState) {
+    let lote = env::var("WEBHOOK_LOTE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(50);
+
+    let webhooks = &state.repos.webhooks;
+
+    let pendientes = match webhooks.entregas_pendientes(tiempo::ahora(), lote) {
+        Ok(pendientes) => pendientes,
+        Err(err) => {
+            println!("no se pudieron leer los webhooks pendientes: {}", err);
+            return;
+        }
+    };
+
+    if pendientes.is_empty() {
+        return;
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap();
+
+    for (entrega, webhook) in pendientes {
+        let mut payload = entrega.payload.clone();
+        payload["id"] = json!(entrega.id);
+        let cuerpo = payload.to_string();
+
+        let timestamp = Utc::now().timestamp();
+        let firma = firmar(&webhook.secreto, timestamp, &cuerpo);
+
+        let resultado = client
+            .post(&webhook.url)
+            .header("content-type", "application/json")
+            .header("x-macy-evento", &entrega.evento)
+            .header("x-macy-entrega", entrega.id)
+            .header("x-macy-firma", format!("t={},v1={}", timestamp, firma))
+            .body(cuerpo)
+            .send()
+            .await;
+
+        let error = match resultado {
+            Ok(respuesta) if respuesta.status().is_success() => None,
+            Ok(respuesta) => Some(format!("HTTP {}", respuesta.status())),
+            Err(err) => Some(err.to_string()),
+        };
+
+        let ahora = tiempo::ahora();
+
+        let actualizado = match &error {
+            None => webhooks.marcar_entregada(entrega.id, entrega.intentos + 1, ahora),
+            Some(error) => {
+                println!(
+                    "webhook {} no confirmó la entrega {} ({}): {}",
+                    webhook.id, entrega.id, entrega.evento, error
+                );
+
+                webhooks.reprogramar_entrega(
+                    entrega.id,
+                    entrega.intentos + 1,
+                    ahora + espera_reintento(entrega.intentos),
+                    error.chars().take(500).collect(),
+                )
+            }
+        };
+
+        if let Err(err) = actualizado {
+            println!("no se pudo actualizar la entrega {}: {}", entrega.id, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::{Webhook, WebhookOutbox};
+    use crate::db::repos::memoria::{Memoria, prueba};
+
+    fn webhook(id: i32, activo: bool) -> Webhook {
+        Webhook {
+            id,
+            id_grupo: 1,
+            // nadie escucha en el puerto 1: el envío falla de inmediato
+            url: "http://127.0.0.1:1/webhook".to_string(),
+            secreto: "secreto".to_string(),
+            eventos: json!([]),
+            activo,
+            creado: None,
+        }
+    }
+
+    fn entrega(id: i32, id_webhook: i32) -> WebhookOutbox {
+        WebhookOutbox {
+            id,
+            id_webhook,
+            evento: "pago.completado".to_string(),
+            payload: json!({ "evento": "pago.completado" }),
+            estado: "pendiente".to_string(),
+            intentos: 0,
+            proximo_intento: tiempo::ahora() - chrono::Duration::minutes(1),
+            ultimo_error: None,
+            creado: None,
+            entregado: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn una_entrega_fallida_se_reprograma_con_su_error() {
+        let memoria = Memoria {
+            webhooks: vec![webhook(1, true), webhook(2, false)],
+            entregas: vec![entrega(1, 1), entrega(2, 2)],
+            ..Default::default()
+        };
+        let (state, repos) = prueba::estado(memoria);
+
+        entregar_pendientes(&state).await;
+
+        let datos = repos.datos();
+        let fallida = &datos.entregas[0];
+        assert_eq!(fallida.estado, "pendiente");
+        assert_eq!(fallida.intentos, 1);
+        assert!(fallida.proximo_intento > tiempo::ahora());
+        assert!(fallida.ultimo_error.is_some());
+
+        // la de un webhook desactivado no se intenta
+        assert_eq!(datos.entregas[1].intentos, 0);
+    }
+}